@@ -0,0 +1,854 @@
+use std::{
+    path::{self, Path, PathBuf},
+    process::ExitCode,
+};
+
+use catalog_lib::{
+    applicability::{evaluate, HardwareInventory, Verdict},
+    cab::CompressionType,
+    cab_to_xml,
+    dcu::{DcuCli, DcuOptions, ProcessRunner},
+    detect::{choose_client, detect_clients, ClientKind, SystemServices},
+    diff::diff_manifests,
+    discover::{discover, DiscoverOptions},
+    ensure_staged_packages,
+    error::CatalogError,
+    filter::{ComponentFilter, FilterMode},
+    filter_catalog, handle_xml,
+    hash::{verify_catalog_hash, CatalogHashValues, HashAlgorithm, HashFormat},
+    install_catalog_with_ic,
+    inventory::{
+        default_collector, FixtureCollector, Inventory, InventoryCollector, SysfsCollector,
+    },
+    launcher::{launch_software, SystemLauncher},
+    load_manifest, load_rules_near,
+    merge::MergeOptions,
+    merge_catalogs, prepare_catalog,
+    registry::{MemoryRegistry, RegistryBackend, RegistrySnapshot, SnapshotRegistry},
+    restore_ic,
+    rewrite::RewriteRules,
+    rollback_snapshot, serve_catalog,
+    server::ServeOptions,
+    snapshot_dir, stage_repository, verify_staged_packages, xml_to_cab,
+};
+use clap::{Parser, Subcommand, ValueEnum};
+use serde_json::{json, Value};
+
+/// 退出码: 0 成功, 1 出错, 2 参数错误 (由 clap 处理), 3 校验不通过或有差异
+const EXIT_FAILURE: u8 = 1;
+const EXIT_CHECK_FAILED: u8 = 3;
+
+/// 不依赖图形界面的 catalog 工具, 结果以 json 输出到 stdout
+#[derive(Debug, Parser)]
+#[command(name = "catalog-cli", version)]
+struct Cli {
+    /// 在 stderr 显示更多日志: -v 显示操作步骤, -vv 显示调试信息
+    #[arg(short, long, global = true, action = clap::ArgAction::Count)]
+    verbose: u8,
+    #[command(subcommand)]
+    command: Command,
+}
+
+/// 把库的日志写到 stderr, stdout 只用于输出结果
+struct StderrLogger;
+
+impl log::Log for StderrLogger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &log::Record) {
+        if self.enabled(record.metadata()) {
+            eprintln!("[{}] {}", record.level(), record.args());
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+fn init_logger(cli: &Cli) {
+    let level = match cli.verbose {
+        0 => log::LevelFilter::Warn,
+        1 => log::LevelFilter::Info,
+        _ => log::LevelFilter::Debug,
+    };
+    // serve 默认显示访问日志
+    let level = match cli.command {
+        Command::Serve { .. } => level.max(log::LevelFilter::Info),
+        _ => level,
+    };
+    if log::set_logger(&StderrLogger).is_ok() {
+        log::set_max_level(level);
+    }
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// 从 cab 中解压 catalog xml
+    Extract {
+        cab: PathBuf,
+        /// 输出目录, 默认是 cab 所在的目录
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// 按改写规则修改 xml, 保存为 `_<文件名>`
+    Rewrite {
+        xml: PathBuf,
+        /// 规则文件, 默认读取 xml 旁边的 rewrite_rules.json
+        #[arg(long)]
+        rules: Option<PathBuf>,
+    },
+    /// 把 xml 打包成同名的 cab
+    Pack {
+        xml: PathBuf,
+        #[arg(long, value_enum, default_value_t = Compression::MsZip)]
+        compression: Compression,
+    },
+    /// 计算 catalog 哈希, 或与注册表中的值比对
+    Hash {
+        #[arg(required = true)]
+        xml: Vec<PathBuf>,
+        #[command(flatten)]
+        format: HashFormatArgs,
+        /// CustomCatalogHashValues 的内容或单个哈希, 给出时只做校验
+        #[arg(long)]
+        verify: Option<String>,
+    },
+    /// 列出目录中所有的 catalog 和 inventory collector, 从新到旧排列
+    Discover {
+        #[arg(default_value = ".")]
+        root: PathBuf,
+        #[arg(long)]
+        max_depth: Option<usize>,
+        /// 跳过匹配的文件或目录, 给出时替换默认的 target, .git 和 node_modules
+        #[arg(long)]
+        ignore: Vec<String>,
+    },
+    /// 只保留 (或删除) 符合条件的组件, 生成新的 catalog 和哈希
+    Filter {
+        /// cab 或 xml
+        catalog: PathBuf,
+        #[command(flatten)]
+        filter: FilterArgs,
+        /// 输出目录, 默认是 catalog 所在的目录
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        #[command(flatten)]
+        format: HashFormatArgs,
+    },
+    /// 合并多个 catalog, 重复的组件只保留一个, 版本冲突时保留较新的
+    Merge {
+        #[arg(required = true, num_args = 2..)]
+        catalogs: Vec<PathBuf>,
+        /// 合并后的 xml, 同目录下生成同名的 cab
+        #[arg(short, long)]
+        output: PathBuf,
+        /// 新的 identifier, 默认由所有来源生成
+        #[arg(long)]
+        identifier: Option<String>,
+        /// 新的 version, 默认是当天日期
+        #[arg(long)]
+        version: Option<String>,
+        #[command(flatten)]
+        format: HashFormatArgs,
+    },
+    /// 依次执行 extract, rewrite, pack 和 hash
+    Prepare {
+        cab: PathBuf,
+        #[arg(long)]
+        rules: Option<PathBuf>,
+        /// 客户端要求的哈希格式, 可以先用 hash --verify 确认
+        #[command(flatten)]
+        format: HashFormatArgs,
+    },
+    /// 建立离线仓库: 改写 catalog, 从来源目录中收集并校验所有更新包, 有缺少时以退出码 3 结束
+    Stage {
+        /// cab 或 xml
+        catalog: PathBuf,
+        /// 更新包所在目录或按原始 path 排列的镜像, 可以给多个
+        #[arg(short, long, required = true)]
+        source: Vec<PathBuf>,
+        /// 仓库目录
+        #[arg(short, long)]
+        output: PathBuf,
+        #[arg(long)]
+        rules: Option<PathBuf>,
+        #[command(flatten)]
+        format: HashFormatArgs,
+    },
+    /// 用本地 HTTP 服务提供 catalog 和更新包, baseLocation 改为服务地址, 按 Ctrl+C 结束
+    Serve {
+        /// cab 或 xml, 所在目录就是服务的根目录
+        catalog: PathBuf,
+        #[arg(long, default_value = "127.0.0.1")]
+        host: String,
+        /// 0 表示由系统分配
+        #[arg(long, default_value_t = 0)]
+        port: u16,
+        /// 写入 baseLocation 的主机名, 监听 0.0.0.0 时必须给出
+        #[arg(long)]
+        public_host: Option<String>,
+        /// 访问日志文件
+        #[arg(long)]
+        access_log: Option<PathBuf>,
+        /// 同时处理请求的线程数
+        #[arg(long, default_value_t = 8)]
+        workers: usize,
+        #[arg(long)]
+        rules: Option<PathBuf>,
+        #[command(flatten)]
+        format: HashFormatArgs,
+    },
+    /// 把改写后的 catalog 注册到 DU/DCU
+    Install {
+        #[arg(required = true)]
+        xml: Vec<PathBuf>,
+        /// 同时替换 inventory collector
+        #[arg(long)]
+        ic: Option<PathBuf>,
+        /// inventory collector 所在目录, 默认使用 DU/DCU 的服务目录
+        #[arg(long)]
+        service_dir: Option<PathBuf>,
+        /// DU 和 DCU 都安装时使用哪一个: dcu 或 du
+        #[arg(long)]
+        client: Option<ClientKind>,
+        /// 不检查 catalog 旁边的更新包
+        #[arg(long)]
+        skip_verify: bool,
+        #[command(flatten)]
+        format: HashFormatArgs,
+        #[command(flatten)]
+        target: RegistryTarget,
+    },
+    /// 用备份还原原来的 inventory collector
+    RestoreIc {
+        #[arg(long)]
+        service_dir: Option<PathBuf>,
+    },
+    /// 列出已安装的 DU/DCU 以及版本, 安装目录和服务状态
+    Detect {
+        #[command(flatten)]
+        target: RegistryTarget,
+    },
+    /// 直接启动已安装的 DU/DCU
+    Launch {
+        /// 其他方式都失败时模拟 Win+S 搜索启动
+        #[arg(long)]
+        keystroke_fallback: bool,
+        #[arg(long)]
+        client: Option<ClientKind>,
+        #[command(flatten)]
+        target: RegistryTarget,
+    },
+    /// 用 dcu-cli 扫描可用的更新
+    Scan {
+        #[command(flatten)]
+        dcu: DcuArgs,
+    },
+    /// 用 dcu-cli 安装更新, 不会自动重启
+    Apply {
+        #[command(flatten)]
+        dcu: DcuArgs,
+    },
+    /// 用快照恢复注册表
+    Rollback {
+        /// 快照文件, 默认使用快照目录中最新的一个
+        #[arg(long)]
+        snapshot: Option<PathBuf>,
+        #[command(flatten)]
+        target: RegistryTarget,
+    },
+    /// 检查 catalog 引用的更新包是否存在, 大小和哈希是否一致, 不一致时以退出码 3 结束
+    Verify {
+        /// cab 或 xml
+        catalog: PathBuf,
+        /// 更新包所在目录, 默认是 catalog 所在的目录
+        #[arg(long)]
+        dir: Option<PathBuf>,
+    },
+    /// 列出 catalog 的基本信息和所有组件
+    Inspect { catalog: PathBuf },
+    /// 按硬件信息判断每个组件是否适用, 并给出每一项检查的原因
+    Applicable {
+        catalog: PathBuf,
+        /// json 或每行一个 key=value 的文本: system_id, os_code, os_arch, pci, hwid, 默认采集本机信息
+        #[arg(long)]
+        inventory: Option<PathBuf>,
+        #[command(flatten)]
+        collect: CollectArgs,
+        /// 只输出适用的组件
+        #[arg(long)]
+        only_applicable: bool,
+    },
+    /// 采集本机的 systemID, PCI 设备, BIOS 和驱动版本, 输出可以作为 applicable 的 --inventory
+    Inventory {
+        #[command(flatten)]
+        collect: CollectArgs,
+        /// 同时保存到文件
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// 按 packageID, identifier 和设备的 componentID 比较两个 catalog
+    Diff {
+        old: PathBuf,
+        new: PathBuf,
+        #[arg(long, value_enum, default_value_t = DiffFormat::Json)]
+        format: DiffFormat,
+        /// 有差异时以退出码 3 结束
+        #[arg(long)]
+        exit_code: bool,
+    },
+}
+
+/// 写入 CustomCatalogHashValues 的哈希格式
+#[derive(Debug, clap::Args)]
+struct HashFormatArgs {
+    #[arg(long, default_value_t = HashAlgorithm::Sha3_384)]
+    algorithm: HashAlgorithm,
+    /// base64 保留末尾的 `=`
+    #[arg(long)]
+    padded: bool,
+}
+
+impl HashFormatArgs {
+    fn format(&self) -> HashFormat {
+        HashFormat::new(self.algorithm, self.padded)
+    }
+}
+
+#[derive(Debug, clap::Args)]
+struct CollectArgs {
+    /// 从 json 文件读取, 不采集本机信息
+    #[arg(long, conflicts_with = "sysfs_root")]
+    fixture: Option<PathBuf>,
+    /// 从指定目录下的 sys/bus/pci 和 sys/class/dmi 读取
+    #[arg(long)]
+    sysfs_root: Option<PathBuf>,
+    /// DU 的 osCode, 如 W11S5, 无法从系统中得到
+    #[arg(long)]
+    os_code: Option<String>,
+}
+
+impl CollectArgs {
+    fn collect(&self) -> Result<Inventory, CatalogError> {
+        let collector: Box<dyn InventoryCollector> = match (&self.fixture, &self.sysfs_root) {
+            (Some(path), _) => Box::new(FixtureCollector { path: path.clone() }),
+            (None, Some(root)) => Box::new(SysfsCollector { root: root.clone() }),
+            (None, None) => default_collector(),
+        };
+        let mut inventory = collector.collect()?;
+        if self.os_code.is_some() {
+            inventory.os_code = self.os_code.clone();
+        }
+        Ok(inventory)
+    }
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum Compression {
+    None,
+    MsZip,
+}
+
+impl From<Compression> for CompressionType {
+    fn from(compression: Compression) -> Self {
+        match compression {
+            Compression::None => CompressionType::None,
+            Compression::MsZip => CompressionType::MsZip,
+        }
+    }
+}
+
+/// 多个值用逗号分隔, 不同选项之间是 "且"
+#[derive(Debug, clap::Args)]
+struct FilterArgs {
+    /// json 格式的过滤条件, 与下面的选项合并
+    #[arg(long)]
+    filter_file: Option<PathBuf>,
+    /// 删除匹配的组件, 默认只保留匹配的组件
+    #[arg(long)]
+    drop: bool,
+    #[arg(long, value_delimiter = ',')]
+    package_id: Vec<String>,
+    #[arg(long, value_delimiter = ',')]
+    category: Vec<String>,
+    #[arg(long, value_delimiter = ',')]
+    component_type: Vec<String>,
+    #[arg(long, value_delimiter = ',')]
+    criticality: Vec<String>,
+    #[arg(long, value_delimiter = ',')]
+    package_type: Vec<String>,
+    #[arg(long, value_delimiter = ',')]
+    os_code: Vec<String>,
+}
+
+impl FilterArgs {
+    fn filter(self) -> Result<ComponentFilter, CatalogError> {
+        let mut filter = match &self.filter_file {
+            Some(path) => serde_json::from_str(&std::fs::read_to_string(path)?)?,
+            None => ComponentFilter::default(),
+        };
+        if self.drop {
+            filter.mode = FilterMode::Drop;
+        }
+        filter.package_ids.extend(self.package_id);
+        filter.categories.extend(self.category);
+        filter.component_types.extend(self.component_type);
+        filter.criticalities.extend(self.criticality);
+        filter.package_types.extend(self.package_type);
+        filter.os_codes.extend(self.os_code);
+        Ok(filter)
+    }
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum DiffFormat {
+    Json,
+    Text,
+    Markdown,
+}
+
+#[derive(Debug, clap::Args)]
+struct RegistryTarget {
+    /// 使用 json 文件模拟的注册表, 不存在时新建; 不给出时修改本机注册表
+    #[arg(long)]
+    registry: Option<PathBuf>,
+    /// 注册表快照的目录, 默认为 %ProgramData%\catalog-rs\snapshots
+    #[arg(long)]
+    snapshot_dir: Option<PathBuf>,
+}
+
+#[derive(Debug, clap::Args)]
+struct DcuArgs {
+    /// 使用指定的 catalog, 默认使用注册表中配置的 catalog
+    #[arg(long)]
+    catalog: Option<PathBuf>,
+    /// 例如 bios, driver, firmware
+    #[arg(long, value_delimiter = ',')]
+    update_type: Vec<String>,
+    /// 报告和日志的目录
+    #[arg(long, default_value = "dcu")]
+    output_dir: PathBuf,
+    /// dcu-cli.exe 的路径, 默认在 DCU 的安装目录中查找
+    #[arg(long)]
+    dcu_cli: Option<PathBuf>,
+    #[arg(long)]
+    client: Option<ClientKind>,
+    #[command(flatten)]
+    target: RegistryTarget,
+}
+
+impl DcuArgs {
+    fn options(&self) -> Result<DcuOptions, CatalogError> {
+        Ok(DcuOptions {
+            catalog: self.catalog.as_deref().map(path::absolute).transpose()?,
+            update_types: self.update_type.clone(),
+            output_dir: path::absolute(&self.output_dir)?,
+        })
+    }
+
+    fn dcu_cli(&self) -> Result<DcuCli<ProcessRunner>, CatalogError> {
+        match &self.dcu_cli {
+            Some(program) => Ok(DcuCli::new(program.clone(), ProcessRunner)),
+            None => with_registry(&self.target, |registry| {
+                let client = choose_client(registry, &SystemServices, self.client)?;
+                DcuCli::locate(registry, &client.software(), ProcessRunner)
+            }),
+        }
+    }
+}
+
+impl RegistryTarget {
+    fn snapshot_dir(&self) -> PathBuf {
+        self.snapshot_dir.clone().unwrap_or_else(snapshot_dir)
+    }
+}
+
+fn load_memory_registry(path: &Path) -> Result<MemoryRegistry, CatalogError> {
+    if path.is_file() {
+        MemoryRegistry::from_path(path)
+    } else {
+        Ok(MemoryRegistry::new())
+    }
+}
+
+/// 在 `target` 指定的注册表上执行 `f`, json 文件会在结束后保存
+fn with_registry<T>(
+    target: &RegistryTarget,
+    f: impl FnOnce(&mut dyn RegistryBackend) -> Result<T, CatalogError>,
+) -> Result<T, CatalogError> {
+    match &target.registry {
+        Some(path) => {
+            let mut registry = load_memory_registry(path)?;
+            let result = f(&mut registry)?;
+            registry.save(path)?;
+            Ok(result)
+        }
+        #[cfg(windows)]
+        None => f(&mut catalog_lib::registry::WindowsRegistry),
+        #[cfg(not(windows))]
+        None => Err(CatalogError::SelectedFileError(
+            "The system registry is only available on Windows, use --registry".into(),
+        )),
+    }
+}
+
+fn absolute_paths(paths: &[PathBuf]) -> Result<Vec<PathBuf>, CatalogError> {
+    Ok(paths.iter().map(path::absolute).collect::<Result<_, _>>()?)
+}
+
+fn load_rules(rules: Option<&Path>, near: &Path) -> Result<RewriteRules, CatalogError> {
+    match rules {
+        Some(path) => RewriteRules::from_path(path),
+        None => load_rules_near(near),
+    }
+}
+
+fn hash(
+    xml: &[PathBuf],
+    format: HashFormat,
+    verify: Option<&str>,
+) -> Result<(Value, bool), CatalogError> {
+    let paths = absolute_paths(xml)?;
+    let Some(registry_value) = verify else {
+        let hash_values = CatalogHashValues::from_catalogs_with(&paths, format)?;
+        return Ok((serde_json::to_value(hash_values)?, true));
+    };
+    let mut results = Vec::new();
+    let mut verified = true;
+    for path in paths {
+        let matches = verify_catalog_hash(&path, registry_value)?;
+        verified &= !matches.is_empty();
+        results.push(json!({ "path": path, "matches": matches }));
+    }
+    Ok((
+        json!({ "verified": verified, "catalogs": results }),
+        verified,
+    ))
+}
+
+fn install(
+    xml: &[PathBuf],
+    ic: Option<&Path>,
+    service_dir: Option<&Path>,
+    client: Option<ClientKind>,
+    format: HashFormat,
+    target: &RegistryTarget,
+) -> Result<Value, CatalogError> {
+    let hash_values = CatalogHashValues::from_catalogs_with(&absolute_paths(xml)?, format)?;
+    let snapshot_dir = target.snapshot_dir();
+    let (client, ic, snapshot) = with_registry(target, |registry| {
+        let mut registry = SnapshotRegistry::with_dir(registry, &snapshot_dir);
+        let (client, ic) = install_catalog_with_ic(
+            &mut registry,
+            &mut SystemServices,
+            client,
+            &hash_values,
+            ic,
+            service_dir,
+        )?;
+        Ok((client, ic, registry.path().map(Path::to_path_buf)))
+    })?;
+    Ok(json!({
+        "client": client,
+        "hash_values": hash_values,
+        "ic": ic,
+        "snapshot": snapshot,
+    }))
+}
+
+fn launch(
+    keystroke_fallback: bool,
+    client: Option<ClientKind>,
+    target: &RegistryTarget,
+) -> Result<Value, CatalogError> {
+    with_registry(target, |registry| {
+        let client = choose_client(registry, &SystemServices, client)?;
+        let launched = launch_software(
+            &mut SystemLauncher,
+            registry,
+            &client.software(),
+            keystroke_fallback,
+        )?;
+        Ok(json!({ "client": client, "launched": launched }))
+    })
+}
+
+fn rollback(snapshot: Option<&Path>, target: &RegistryTarget) -> Result<Value, CatalogError> {
+    let snapshot = match snapshot {
+        Some(path) => path.to_path_buf(),
+        None => RegistrySnapshot::latest_in(&target.snapshot_dir())
+            .ok_or_else(|| CatalogError::CurrentFileError("registry snapshot".into()))?,
+    };
+    with_registry(target, |registry| rollback_snapshot(registry, &snapshot))?;
+    Ok(json!({ "snapshot": snapshot }))
+}
+
+fn inspect(catalog: &Path) -> Result<Value, CatalogError> {
+    let manifest = load_manifest(catalog)?;
+    let components: Vec<Value> = manifest
+        .software_components
+        .iter()
+        .map(|c| {
+            json!({
+                "package_id": c.package_id,
+                "name": c.display_name(),
+                "category": c.category.as_ref().map(|v| v.text()),
+                "component_type": c.component_type.as_ref().map(|v| &v.value),
+                "dell_version": c.dell_version,
+                "vendor_version": c.vendor_version,
+                "release_date": c.release_date,
+                "criticality": c.criticality.as_ref().map(|v| &v.value),
+                "size": c.size,
+                "path": c.path,
+                "system_ids": c.system_ids(),
+            })
+        })
+        .collect();
+    Ok(json!({
+        "base_location": manifest.base_location,
+        "identifier": manifest.identifier,
+        "release_id": manifest.release_id,
+        "version": manifest.version,
+        "date_time": manifest.date_time,
+        "components": components,
+    }))
+}
+
+/// 执行命令, 返回输出和是否通过检查
+fn run(command: Command) -> Result<(Value, bool), CatalogError> {
+    let output = match command {
+        Command::Extract { cab, output } => {
+            let output_dir = match output {
+                Some(dir) => dir,
+                None => path::absolute(&cab)?
+                    .parent()
+                    .map(Path::to_path_buf)
+                    .unwrap_or_default(),
+            };
+            json!({ "xml": cab_to_xml(&cab, &output_dir)? })
+        }
+        Command::Rewrite { xml, rules } => {
+            let rules = load_rules(rules.as_deref(), &xml)?;
+            json!({ "xml": handle_xml(xml, &rules)? })
+        }
+        Command::Pack { xml, compression } => {
+            json!({ "cab": xml_to_cab(&xml, compression.into())? })
+        }
+        Command::Hash {
+            xml,
+            format,
+            verify,
+        } => return hash(&xml, format.format(), verify.as_deref()),
+        Command::Discover {
+            root,
+            max_depth,
+            ignore,
+        } => {
+            let mut options = DiscoverOptions::default();
+            if let Some(max_depth) = max_depth {
+                options.max_depth = max_depth;
+            }
+            if !ignore.is_empty() {
+                options.ignore = ignore;
+            }
+            serde_json::to_value(discover(&root, &options)?)?
+        }
+        Command::Filter {
+            catalog,
+            filter,
+            output,
+            format,
+        } => {
+            let output_dir = match output {
+                Some(dir) => dir,
+                None => path::absolute(&catalog)?
+                    .parent()
+                    .map(Path::to_path_buf)
+                    .unwrap_or_default(),
+            };
+            serde_json::to_value(filter_catalog(
+                &catalog,
+                &filter.filter()?,
+                &output_dir,
+                format.format(),
+            )?)?
+        }
+        Command::Merge {
+            catalogs,
+            output,
+            identifier,
+            version,
+            format,
+        } => {
+            let options = MergeOptions {
+                identifier,
+                version,
+                date_time: None,
+            };
+            serde_json::to_value(merge_catalogs(
+                &catalogs,
+                &output,
+                &options,
+                format.format(),
+            )?)?
+        }
+        Command::Prepare { cab, rules, format } => {
+            let rules = load_rules(rules.as_deref(), &cab)?;
+            serde_json::to_value(prepare_catalog(&cab, &rules, format.format())?)?
+        }
+        Command::Stage {
+            catalog,
+            source,
+            output,
+            rules,
+            format,
+        } => {
+            let rules = load_rules(rules.as_deref(), &catalog)?;
+            let staged = stage_repository(&catalog, &source, &output, &rules, format.format())?;
+            let passed = staged.missing.is_empty();
+            return Ok((serde_json::to_value(staged)?, passed));
+        }
+        Command::Serve {
+            catalog,
+            host,
+            port,
+            public_host,
+            access_log,
+            workers,
+            rules,
+            format,
+        } => {
+            let rules = load_rules(rules.as_deref(), &catalog)?;
+            let options = ServeOptions {
+                host,
+                port,
+                public_host,
+                access_log,
+                workers,
+            };
+            let (served, server) = serve_catalog(&catalog, &rules, &options, format.format())?;
+            // 先输出地址和哈希, 之后一直处理请求
+            println!("{:#}", serde_json::to_value(&served)?);
+            server.run();
+            return Ok((Value::String(String::new()), true));
+        }
+        Command::Install {
+            xml,
+            ic,
+            service_dir,
+            client,
+            skip_verify,
+            format,
+            target,
+        } => {
+            if !skip_verify {
+                for xml in xml.iter() {
+                    ensure_staged_packages(xml)?;
+                }
+            }
+            install(
+                &xml,
+                ic.as_deref(),
+                service_dir.as_deref(),
+                client,
+                format.format(),
+                &target,
+            )?
+        }
+        Command::RestoreIc { service_dir } => {
+            serde_json::to_value(restore_ic(service_dir.as_deref(), &mut SystemServices)?)?
+        }
+        Command::Launch {
+            keystroke_fallback,
+            client,
+            target,
+        } => launch(keystroke_fallback, client, &target)?,
+        Command::Detect { target } => with_registry(&target, |registry| {
+            Ok(serde_json::to_value(detect_clients(
+                registry,
+                &SystemServices,
+            )?)?)
+        })?,
+        Command::Scan { dcu } => {
+            let result = dcu.dcu_cli()?.scan(&dcu.options()?)?;
+            let passed = result.exit_code.is_success();
+            return Ok((serde_json::to_value(result)?, passed));
+        }
+        Command::Apply { dcu } => {
+            let result = dcu.dcu_cli()?.apply(&dcu.options()?)?;
+            let passed = result.exit_code.is_success();
+            return Ok((serde_json::to_value(result)?, passed));
+        }
+        Command::Rollback { snapshot, target } => rollback(snapshot.as_deref(), &target)?,
+        Command::Verify { catalog, dir } => {
+            let report = verify_staged_packages(&catalog, dir.as_deref())?;
+            let passed = report.is_ok();
+            return Ok((serde_json::to_value(report)?, passed));
+        }
+        Command::Inspect { catalog } => inspect(&catalog)?,
+        Command::Applicable {
+            catalog,
+            inventory,
+            collect,
+            only_applicable,
+        } => {
+            let inventory = match inventory {
+                Some(path) => HardwareInventory::from_path(&path)?,
+                None => collect.collect()?.hardware(),
+            };
+            let mut results = evaluate(&load_manifest(&catalog)?, &inventory);
+            if only_applicable {
+                results.retain(|r| r.verdict == Verdict::Applicable);
+            }
+            json!({ "inventory": inventory, "components": results })
+        }
+        Command::Inventory { collect, output } => {
+            let inventory = collect.collect()?;
+            if let Some(output) = output {
+                inventory.save(&output)?;
+            }
+            serde_json::to_value(inventory)?
+        }
+        Command::Diff {
+            old,
+            new,
+            format,
+            exit_code,
+        } => {
+            let diff = diff_manifests(&load_manifest(&old)?, &load_manifest(&new)?);
+            let passed = !exit_code || diff.is_empty();
+            let output = match format {
+                DiffFormat::Json => serde_json::to_value(diff)?,
+                DiffFormat::Text => Value::String(diff.to_text()),
+                DiffFormat::Markdown => Value::String(diff.to_markdown()),
+            };
+            return Ok((output, passed));
+        }
+    };
+    Ok((output, true))
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    init_logger(&cli);
+    match run(cli.command) {
+        Ok((output, passed)) => {
+            // 文本格式的结果原样输出
+            match output {
+                Value::String(text) => print!("{}", text),
+                output => println!("{:#}", output),
+            }
+            if passed {
+                ExitCode::SUCCESS
+            } else {
+                ExitCode::from(EXIT_CHECK_FAILED)
+            }
+        }
+        Err(e) => {
+            eprintln!("catalog-cli--{}", e);
+            println!("{:#}", json!({ "error": e.to_string() }));
+            ExitCode::from(EXIT_FAILURE)
+        }
+    }
+}
@@ -0,0 +1,118 @@
+use std::{
+    path::{Path, PathBuf},
+    process::{Command, Output},
+};
+
+use catalog_lib::{
+    manifest::Manifest,
+    registry::{MemoryRegistry, RegValue, RegistryBackend, SERVICE_KEY},
+};
+use serde_json::Value;
+
+const XML_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/../Precision_0CBB.xml");
+const DCU_KEY: &str = r"SOFTWARE\Dell\UpdateService\Clients\CommandUpdate";
+
+fn catalog_cli(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_catalog-cli"))
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+fn stdout_json(output: &Output) -> Value {
+    serde_json::from_slice(&output.stdout).unwrap()
+}
+
+fn path_str(path: &Path) -> &str {
+    path.to_str().unwrap()
+}
+
+/// 复制 Precision catalog 到临时目录
+fn copy_catalog(dir: &Path) -> PathBuf {
+    let xml_path = dir.join("Precision_0CBB.xml");
+    std::fs::copy(XML_PATH, &xml_path).unwrap();
+    xml_path
+}
+
+#[test]
+fn test_install_with_memory_registry() {
+    let dir = tempfile::tempdir().unwrap();
+    let xml_path = copy_catalog(dir.path());
+    let registry_path = dir.path().join("registry.json");
+    let service_dir = dir.path().join("service");
+    std::fs::create_dir(&service_dir).unwrap();
+    let mut registry = MemoryRegistry::new();
+    registry.create_key(DCU_KEY).unwrap();
+    registry.save(&registry_path).unwrap();
+
+    let output = catalog_cli(&[
+        "install",
+        path_str(&xml_path),
+        "--registry",
+        path_str(&registry_path),
+        "--snapshot-dir",
+        path_str(&dir.path().join("snapshots")),
+        "--service-dir",
+        path_str(&service_dir),
+    ]);
+    assert_eq!(output.status.code(), Some(0));
+    let json = stdout_json(&output);
+    assert_eq!(json["client"]["kind"], "DellCommandUpdate");
+    assert!(json["snapshot"].is_string());
+
+    let registry = MemoryRegistry::from_path(&registry_path).unwrap();
+    let general = format!(r"{}\Preferences\Settings\General", DCU_KEY);
+    assert_eq!(
+        registry.read_value(&general, "CustomCatalogPaths").unwrap(),
+        Some(RegValue::MultiSz(vec![xml_path.to_string_lossy().into()]))
+    );
+    assert_eq!(
+        registry.read_value(&general, "EnableCatalogXML").unwrap(),
+        Some(RegValue::Dword(1))
+    );
+    let Some(RegValue::Sz(hash_values)) = registry
+        .read_value(SERVICE_KEY, "CustomCatalogHashValues")
+        .unwrap()
+    else {
+        panic!("CustomCatalogHashValues is not written");
+    };
+    let hash_values: Value = serde_json::from_str(&hash_values).unwrap();
+    assert_eq!(hash_values, json["hash_values"]);
+}
+
+#[test]
+fn test_verify_missing_package() {
+    let dir = tempfile::tempdir().unwrap();
+    let output = catalog_cli(&["verify", XML_PATH, "--dir", path_str(dir.path())]);
+    assert_eq!(output.status.code(), Some(3));
+    let json = stdout_json(&output);
+    assert_eq!(json["missing"].as_array().unwrap().len(), 2);
+}
+
+#[test]
+fn test_diff_exit_code() {
+    let output = catalog_cli(&["diff", XML_PATH, XML_PATH, "--exit-code"]);
+    assert_eq!(output.status.code(), Some(0));
+
+    let dir = tempfile::tempdir().unwrap();
+    let mut manifest = Manifest::from_path(XML_PATH).unwrap();
+    manifest.software_components[0].dell_version = "99.0.0".into();
+    let new_path = dir.path().join("new.xml");
+    std::fs::write(&new_path, manifest.to_xml_string().unwrap()).unwrap();
+    let output = catalog_cli(&["diff", XML_PATH, path_str(&new_path), "--exit-code"]);
+    assert_eq!(output.status.code(), Some(3));
+    assert_eq!(stdout_json(&output)["updated"].as_array().unwrap().len(), 1);
+}
+
+#[test]
+fn test_error_json() {
+    let dir = tempfile::tempdir().unwrap();
+    let missing = dir.path().join("missing.xml");
+    let output = catalog_cli(&["inspect", path_str(&missing)]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(stdout_json(&output)["error"].is_string());
+
+    // clap 处理的参数错误
+    let output = catalog_cli(&["inspect"]);
+    assert_eq!(output.status.code(), Some(2));
+}
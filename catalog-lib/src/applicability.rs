@@ -0,0 +1,599 @@
+use std::{fmt, fs, path::Path};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::CatalogError,
+    manifest::{Manifest, PCIInfo, SoftwareComponent},
+};
+
+/// 一个 PCI 设备, ID 是 4 位 hex, 子系统 ID 可以为空
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PciDevice {
+    pub vendor_id: String,
+    pub device_id: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub sub_vendor_id: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub sub_device_id: String,
+}
+
+impl PciDevice {
+    /// 解析 `8086:A1BD` 或 `8086:A1BD:1028:0CBB`
+    pub fn parse(text: &str) -> Option<Self> {
+        let parts: Vec<&str> = text.trim().split(':').collect();
+        let (vendor_id, device_id, sub_vendor_id, sub_device_id) = match parts[..] {
+            [vendor, device] => (vendor, device, "", ""),
+            [vendor, device, sub_vendor, sub_device] => (vendor, device, sub_vendor, sub_device),
+            _ => return None,
+        };
+        Some(PciDevice {
+            vendor_id: vendor_id.to_uppercase(),
+            device_id: device_id.to_uppercase(),
+            sub_vendor_id: sub_vendor_id.to_uppercase(),
+            sub_device_id: sub_device_id.to_uppercase(),
+        })
+    }
+
+    /// 解析 Windows 的 `PCI\VEN_8086&DEV_A1BD&SUBSYS_0CBB1028&REV_01`, `SUBSYS` 是子设备 ID 加子厂商 ID
+    pub fn from_hardware_id(hardware_id: &str) -> Option<Self> {
+        let upper = hardware_id.trim().to_uppercase();
+        let rest = upper.strip_prefix("PCI\\")?;
+        let mut device = PciDevice::default();
+        for part in rest.split('&') {
+            if let Some(vendor) = part.strip_prefix("VEN_") {
+                device.vendor_id = vendor.into();
+            } else if let Some(id) = part.strip_prefix("DEV_") {
+                device.device_id = id.into();
+            } else if let Some(subsys) = part.strip_prefix("SUBSYS_") {
+                if subsys.len() == 8 && subsys.bytes().all(|b| b.is_ascii_hexdigit()) {
+                    device.sub_device_id = subsys[..4].into();
+                    device.sub_vendor_id = subsys[4..].into();
+                }
+            }
+        }
+        (!device.vendor_id.is_empty() && !device.device_id.is_empty()).then_some(device)
+    }
+
+    /// catalog 中为空的子系统 ID 不限制, 硬件信息中没有子系统 ID 时无法判断
+    fn matches(&self, info: &PCIInfo) -> Outcome {
+        if !self.vendor_id.eq_ignore_ascii_case(&info.vendor_id)
+            || !self.device_id.eq_ignore_ascii_case(&info.device_id)
+        {
+            return Outcome::Mismatch;
+        }
+        let mut outcome = Outcome::Match;
+        for (wanted, actual) in [
+            (&info.sub_vendor_id, &self.sub_vendor_id),
+            (&info.sub_device_id, &self.sub_device_id),
+        ] {
+            if wanted.is_empty() {
+                continue;
+            }
+            if actual.is_empty() {
+                outcome = Outcome::Unknown;
+            } else if !wanted.eq_ignore_ascii_case(actual) {
+                return Outcome::Mismatch;
+            }
+        }
+        outcome
+    }
+}
+
+impl fmt::Display for PciDevice {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.vendor_id, self.device_id)?;
+        if !self.sub_vendor_id.is_empty() || !self.sub_device_id.is_empty() {
+            write!(f, ":{}:{}", self.sub_vendor_id, self.sub_device_id)?;
+        }
+        Ok(())
+    }
+}
+
+/// 一台机器的硬件信息, 为空的字段表示未知
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HardwareInventory {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub system_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub os_code: Option<String>,
+    /// `x64` 或 `x86`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub os_arch: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub pci_devices: Vec<PciDevice>,
+    /// 设备管理器中的硬件 ID, 如 `SWC\...` 或 `PCI\VEN_...`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub hardware_ids: Vec<String>,
+}
+
+impl HardwareInventory {
+    /// 每行一个 `key=value`, `#` 开头的是注释:
+    /// `system_id`, `os_code`, `os_arch`, `pci` (`8086:A1BD[:1028:0CBB]`) 和 `hwid`
+    pub fn from_text(text: &str) -> Result<Self, CatalogError> {
+        let mut inventory = HardwareInventory::default();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let invalid =
+                || CatalogError::InvalidInventory(format!("line {}: {}", number + 1, line));
+            let (key, value) = line.split_once('=').ok_or_else(invalid)?;
+            let value = value.trim().to_string();
+            match key.trim().to_lowercase().as_str() {
+                "system_id" => inventory.system_id = Some(value),
+                "os_code" => inventory.os_code = Some(value),
+                "os_arch" => inventory.os_arch = Some(value),
+                "pci" => inventory
+                    .pci_devices
+                    .push(PciDevice::parse(&value).ok_or_else(invalid)?),
+                "hwid" => inventory.hardware_ids.push(value),
+                _ => return Err(invalid()),
+            }
+        }
+        Ok(inventory)
+    }
+
+    /// json 或 `from_text` 的文本格式
+    pub fn from_path(path: &Path) -> Result<Self, CatalogError> {
+        let text = fs::read_to_string(path)?;
+        if text.trim_start().starts_with('{') {
+            Ok(serde_json::from_str(&text)?)
+        } else {
+            Self::from_text(&text)
+        }
+    }
+
+    /// `pci_devices` 加上从 `PCI\` 硬件 ID 中解析出的设备
+    pub fn all_pci_devices(&self) -> Vec<PciDevice> {
+        let mut devices = self.pci_devices.clone();
+        for device in self
+            .hardware_ids
+            .iter()
+            .filter_map(|id| PciDevice::from_hardware_id(id))
+        {
+            if !devices.contains(&device) {
+                devices.push(device);
+            }
+        }
+        devices
+    }
+}
+
+/// 适用性检查的项目
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Check {
+    System,
+    OperatingSystem,
+    Device,
+}
+
+/// 单项检查的结果
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Outcome {
+    Match,
+    Mismatch,
+    /// catalog 没有限制
+    Unrestricted,
+    /// 硬件信息中缺少需要的字段
+    Unknown,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct CheckResult {
+    pub check: Check,
+    pub outcome: Outcome,
+    pub reason: String,
+}
+
+impl CheckResult {
+    fn new(check: Check, outcome: Outcome, reason: impl Into<String>) -> Self {
+        CheckResult {
+            check,
+            outcome,
+            reason: reason.into(),
+        }
+    }
+}
+
+/// 组件是否适用于这台机器
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Verdict {
+    Applicable,
+    NotApplicable,
+    /// 没有不匹配的项目, 但有无法判断的项目
+    Unknown,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ComponentApplicability {
+    pub package_id: String,
+    pub name: String,
+    pub version: String,
+    pub verdict: Verdict,
+    pub checks: Vec<CheckResult>,
+}
+
+fn check_system(component: &SoftwareComponent, inventory: &HardwareInventory) -> CheckResult {
+    let system_ids = component.system_ids();
+    if system_ids.is_empty() {
+        return CheckResult::new(Check::System, Outcome::Unrestricted, "no SupportedSystems");
+    }
+    let Some(system_id) = &inventory.system_id else {
+        return CheckResult::new(Check::System, Outcome::Unknown, "inventory has no systemID");
+    };
+    if system_ids
+        .iter()
+        .any(|id| id.eq_ignore_ascii_case(system_id))
+    {
+        CheckResult::new(
+            Check::System,
+            Outcome::Match,
+            format!("systemID {} is supported", system_id),
+        )
+    } else {
+        CheckResult::new(
+            Check::System,
+            Outcome::Mismatch,
+            format!("systemID {} is not in {}", system_id, system_ids.join(", ")),
+        )
+    }
+}
+
+fn check_os(component: &SoftwareComponent, inventory: &HardwareInventory) -> CheckResult {
+    let operating_systems: Vec<_> = component
+        .supported_operating_systems
+        .iter()
+        .flat_map(|s| s.operating_systems.iter())
+        .collect();
+    if operating_systems.is_empty() {
+        return CheckResult::new(
+            Check::OperatingSystem,
+            Outcome::Unrestricted,
+            "no SupportedOperatingSystems",
+        );
+    }
+    let Some(os_code) = &inventory.os_code else {
+        return CheckResult::new(
+            Check::OperatingSystem,
+            Outcome::Unknown,
+            "inventory has no osCode",
+        );
+    };
+    let same_code: Vec<_> = operating_systems
+        .iter()
+        .filter(|os| os.os_code.eq_ignore_ascii_case(os_code))
+        .collect();
+    if same_code.is_empty() {
+        return CheckResult::new(
+            Check::OperatingSystem,
+            Outcome::Mismatch,
+            format!("osCode {} is not supported", os_code),
+        );
+    }
+    match &inventory.os_arch {
+        Some(arch)
+            if !same_code
+                .iter()
+                .any(|os| os.os_arch.eq_ignore_ascii_case(arch)) =>
+        {
+            CheckResult::new(
+                Check::OperatingSystem,
+                Outcome::Mismatch,
+                format!("osCode {} is supported but not on {}", os_code, arch),
+            )
+        }
+        Some(arch) => CheckResult::new(
+            Check::OperatingSystem,
+            Outcome::Match,
+            format!("osCode {} ({}) is supported", os_code, arch),
+        ),
+        None => CheckResult::new(
+            Check::OperatingSystem,
+            Outcome::Match,
+            format!("osCode {} is supported", os_code),
+        ),
+    }
+}
+
+fn check_devices(
+    component: &SoftwareComponent,
+    pci_devices: &[PciDevice],
+    hardware_ids: &[String],
+) -> CheckResult {
+    let pci_count: usize = component.devices().map(|d| d.pci_info.len()).sum();
+    let generic_count: usize = component.devices().map(|d| d.generic.len()).sum();
+    if pci_count == 0 && generic_count == 0 {
+        return CheckResult::new(
+            Check::Device,
+            Outcome::Unrestricted,
+            "no PCIInfo or Generic device IDs",
+        );
+    }
+    if pci_devices.is_empty() && hardware_ids.is_empty() {
+        return CheckResult::new(
+            Check::Device,
+            Outcome::Unknown,
+            "inventory has no PCI devices or hardware IDs",
+        );
+    }
+    let mut unknown = None;
+    for device in component.devices() {
+        if let Some(pci) = pci_devices.iter().find(|pci| {
+            device
+                .pci_info
+                .iter()
+                .any(|info| pci.matches(info) == Outcome::Match)
+        }) {
+            return CheckResult::new(
+                Check::Device,
+                Outcome::Match,
+                format!("PCI {} matches componentID {}", pci, device.component_id),
+            );
+        }
+        if let Some(id) = hardware_ids
+            .iter()
+            .find(|id| device.generic.iter().any(|g| g.eq_ignore_ascii_case(id)))
+        {
+            return CheckResult::new(
+                Check::Device,
+                Outcome::Match,
+                format!(
+                    "hardware ID {} matches componentID {}",
+                    id, device.component_id
+                ),
+            );
+        }
+        if unknown.is_none() {
+            unknown = pci_devices
+                .iter()
+                .find(|pci| {
+                    device
+                        .pci_info
+                        .iter()
+                        .any(|info| pci.matches(info) == Outcome::Unknown)
+                })
+                .map(|pci| (pci, &device.component_id));
+        }
+    }
+    if let Some((pci, component_id)) = unknown {
+        return CheckResult::new(
+            Check::Device,
+            Outcome::Unknown,
+            format!(
+                "PCI {} has no subsystem ID to compare with componentID {}",
+                pci, component_id
+            ),
+        );
+    }
+    CheckResult::new(
+        Check::Device,
+        Outcome::Mismatch,
+        format!(
+            "none of {} PCI devices and {} hardware IDs match {} PCI IDs and {} generic IDs",
+            pci_devices.len(),
+            hardware_ids.len(),
+            pci_count,
+            generic_count
+        ),
+    )
+}
+
+/// 逐项检查 systemID, 操作系统和设备, 有一项不匹配就不适用
+pub fn evaluate_component(
+    component: &SoftwareComponent,
+    inventory: &HardwareInventory,
+) -> ComponentApplicability {
+    evaluate_with(component, inventory, &inventory.all_pci_devices())
+}
+
+fn evaluate_with(
+    component: &SoftwareComponent,
+    inventory: &HardwareInventory,
+    pci_devices: &[PciDevice],
+) -> ComponentApplicability {
+    let checks = vec![
+        check_system(component, inventory),
+        check_os(component, inventory),
+        check_devices(component, pci_devices, &inventory.hardware_ids),
+    ];
+    let verdict = if checks.iter().any(|c| c.outcome == Outcome::Mismatch) {
+        Verdict::NotApplicable
+    } else if checks.iter().any(|c| c.outcome == Outcome::Unknown) {
+        Verdict::Unknown
+    } else {
+        Verdict::Applicable
+    };
+    ComponentApplicability {
+        package_id: component.package_id.clone(),
+        name: component.display_name().to_string(),
+        version: component.dell_version.clone(),
+        verdict,
+        checks,
+    }
+}
+
+/// catalog 中每个组件对这台机器的适用性
+pub fn evaluate(manifest: &Manifest, inventory: &HardwareInventory) -> Vec<ComponentApplicability> {
+    let pci_devices = inventory.all_pci_devices();
+    let results: Vec<_> = manifest
+        .software_components
+        .iter()
+        .map(|component| evaluate_with(component, inventory, &pci_devices))
+        .collect();
+    log::debug!(
+        "evaluate--{} of {} components applicable",
+        results
+            .iter()
+            .filter(|r| r.verdict == Verdict::Applicable)
+            .count(),
+        results.len()
+    );
+    results
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::manifest::{Device, SupportedDevices};
+
+    fn manifest() -> Manifest {
+        Manifest::from_path(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/../Precision_0CBB.xml"
+        ))
+        .unwrap()
+    }
+
+    fn verdicts(results: &[ComponentApplicability]) -> Vec<(&str, Verdict)> {
+        results
+            .iter()
+            .map(|r| (r.package_id.as_str(), r.verdict))
+            .collect()
+    }
+
+    #[test]
+    fn test_pci_device() {
+        let device =
+            PciDevice::from_hardware_id(r"PCI\VEN_8086&DEV_a1bd&SUBSYS_0CBB1028&REV_01").unwrap();
+        assert_eq!(device, PciDevice::parse("8086:A1BD:1028:0cbb").unwrap());
+        assert_eq!(device.to_string(), "8086:A1BD:1028:0CBB");
+        assert!(PciDevice::from_hardware_id(r"SWC\PROVIDER_INTEL").is_none());
+        // SUBSYS 不是 8 位十六进制数时忽略
+        let bad = PciDevice::from_hardware_id(r"PCI\VEN_8086&DEV_A1BD&SUBSYS_AB€CDE").unwrap();
+        assert!(bad.sub_vendor_id.is_empty() && bad.sub_device_id.is_empty());
+        assert!(PciDevice::parse("8086").is_none());
+
+        let info = PCIInfo {
+            vendor_id: "8086".into(),
+            device_id: "A1BD".into(),
+            ..Default::default()
+        };
+        assert_eq!(device.matches(&info), Outcome::Match);
+        let info = PCIInfo {
+            sub_vendor_id: "103C".into(),
+            ..info
+        };
+        assert_eq!(device.matches(&info), Outcome::Mismatch);
+
+        // 只知道厂商和设备 ID 时, 不能断定子系统不匹配
+        let device = PciDevice::parse("8086:A1BD").unwrap();
+        assert_eq!(device.matches(&info), Outcome::Unknown);
+        let info = PCIInfo {
+            device_id: "A1BE".into(),
+            ..info
+        };
+        assert_eq!(device.matches(&info), Outcome::Mismatch);
+    }
+
+    #[test]
+    fn test_check_devices_without_subsystem() {
+        let component = SoftwareComponent {
+            supported_devices: Some(SupportedDevices {
+                devices: vec![Device {
+                    component_id: "104446".into(),
+                    pci_info: vec![PCIInfo {
+                        vendor_id: "8086".into(),
+                        device_id: "A1BD".into(),
+                        sub_vendor_id: "1028".into(),
+                        sub_device_id: "0CBB".into(),
+                    }],
+                    ..Default::default()
+                }],
+            }),
+            ..Default::default()
+        };
+        let result = check_devices(&component, &[PciDevice::parse("8086:A1BD").unwrap()], &[]);
+        assert_eq!(result.outcome, Outcome::Unknown);
+        assert!(result.reason.contains("104446"));
+
+        let pci = [
+            PciDevice::parse("8086:A1BD").unwrap(),
+            PciDevice::parse("8086:A1BD:1028:0CBB").unwrap(),
+        ];
+        assert_eq!(check_devices(&component, &pci, &[]).outcome, Outcome::Match);
+        let pci = [PciDevice::parse("8086:A1BD:103C:0CBB").unwrap()];
+        assert_eq!(
+            check_devices(&component, &pci, &[]).outcome,
+            Outcome::Mismatch
+        );
+    }
+
+    #[test]
+    fn test_inventory_from_text() {
+        let inventory = HardwareInventory::from_text(
+            "# lab machine\nsystem_id = 0CBB\nos_code=W10P4\nos_arch=x64\npci=8086:A1BD\nhwid=SWC\\PROVIDER_INTEL_COMPONENT_ICLSCLIENT\n",
+        )
+        .unwrap();
+        assert_eq!(inventory.system_id.as_deref(), Some("0CBB"));
+        assert_eq!(inventory.pci_devices.len(), 1);
+        assert_eq!(inventory.hardware_ids.len(), 1);
+        let json = serde_json::to_string(&inventory).unwrap();
+        assert_eq!(
+            serde_json::from_str::<HardwareInventory>(&json).unwrap(),
+            inventory
+        );
+        assert!(matches!(
+            HardwareInventory::from_text("bios=1.0"),
+            Err(CatalogError::InvalidInventory(_))
+        ));
+    }
+
+    #[test]
+    fn test_evaluate() {
+        let manifest = manifest();
+        // WR6M5 是 ME 驱动, 按 PCI 设备匹配; MG49X 是 BIOS, 只限制机型
+        let inventory = HardwareInventory {
+            system_id: Some("0cbb".into()),
+            os_code: Some("W10P4".into()),
+            os_arch: Some("x64".into()),
+            hardware_ids: vec![r"PCI\VEN_8086&DEV_7E70&SUBSYS_0CBB1028".into()],
+            ..Default::default()
+        };
+        let results = evaluate(&manifest, &inventory);
+        assert_eq!(
+            verdicts(&results),
+            [
+                ("WR6M5", Verdict::Applicable),
+                ("MG49X", Verdict::Applicable)
+            ]
+        );
+        assert_eq!(results[1].checks[2].outcome, Outcome::Unrestricted);
+        assert!(results[0].checks[2].reason.contains("8086:7E70"));
+
+        let other = HardwareInventory {
+            system_id: Some("0ABC".into()),
+            os_arch: Some("x86".into()),
+            pci_devices: vec![PciDevice::parse("10EC:8168").unwrap()],
+            ..inventory.clone()
+        };
+        let results = evaluate(&manifest, &other);
+        assert_eq!(
+            verdicts(&results),
+            [
+                ("WR6M5", Verdict::NotApplicable),
+                ("MG49X", Verdict::NotApplicable)
+            ]
+        );
+        let outcomes: Vec<Outcome> = results[0].checks.iter().map(|c| c.outcome).collect();
+        assert_eq!(
+            outcomes,
+            [Outcome::Mismatch, Outcome::Mismatch, Outcome::Match]
+        );
+
+        // BIOS 没有列出操作系统, 只看机型就能判断
+        let unknown = HardwareInventory {
+            system_id: Some("0CBB".into()),
+            ..Default::default()
+        };
+        let results = evaluate(&manifest, &unknown);
+        assert_eq!(
+            verdicts(&results),
+            [("WR6M5", Verdict::Unknown), ("MG49X", Verdict::Applicable)]
+        );
+    }
+}
@@ -0,0 +1,275 @@
+use std::{cmp::Ordering, fmt};
+
+use serde::Serialize;
+
+use crate::{
+    detect::compare_versions,
+    manifest::{display_text, CodedValue, Manifest, SoftwareComponent},
+};
+
+/// 组件列表中的一行
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ComponentRow {
+    /// 在 `Manifest::software_components` 中的位置
+    pub index: usize,
+    pub package_id: String,
+    pub name: String,
+    pub category: String,
+    pub component_type: String,
+    pub dell_version: String,
+    pub vendor_version: String,
+    pub release_date: String,
+    pub criticality: String,
+    pub size: Option<u64>,
+}
+
+impl ComponentRow {
+    fn new(index: usize, component: &SoftwareComponent) -> Self {
+        let coded =
+            |value: Option<&CodedValue>| value.map(|v| v.text().to_string()).unwrap_or_default();
+        ComponentRow {
+            index,
+            package_id: component.package_id.clone(),
+            name: component.display_name().to_string(),
+            category: coded(component.category.as_ref()),
+            component_type: coded(component.component_type.as_ref()),
+            dell_version: component.dell_version.clone(),
+            vendor_version: component.vendor_version.clone(),
+            release_date: component.release_date.clone(),
+            criticality: coded(component.criticality.as_ref()),
+            size: component.size,
+        }
+    }
+
+    fn matches(&self, filter: &str) -> bool {
+        [
+            &self.package_id,
+            &self.name,
+            &self.category,
+            &self.component_type,
+            &self.dell_version,
+            &self.vendor_version,
+            &self.criticality,
+        ]
+        .iter()
+        .any(|field| field.to_lowercase().contains(filter))
+    }
+}
+
+/// 组件列表的排序字段
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SortColumn {
+    #[default]
+    Name,
+    Category,
+    ComponentType,
+    Version,
+    ReleaseDate,
+    Criticality,
+    Size,
+}
+
+impl SortColumn {
+    pub const ALL: [SortColumn; 7] = [
+        SortColumn::Name,
+        SortColumn::Category,
+        SortColumn::ComponentType,
+        SortColumn::Version,
+        SortColumn::ReleaseDate,
+        SortColumn::Criticality,
+        SortColumn::Size,
+    ];
+
+    fn compare(&self, manifest: &Manifest, a: &ComponentRow, b: &ComponentRow) -> Ordering {
+        match self {
+            SortColumn::Name => a.name.cmp(&b.name),
+            SortColumn::Category => a.category.cmp(&b.category),
+            SortColumn::ComponentType => a.component_type.cmp(&b.component_type),
+            SortColumn::Version => compare_versions(&a.dell_version, &b.dell_version),
+            // releaseDate 是 "May 20, 2024" 这样的文本, 按 ISO 格式的 dateTime 排序
+            SortColumn::ReleaseDate => manifest.software_components[a.index]
+                .date_time
+                .cmp(&manifest.software_components[b.index].date_time),
+            SortColumn::Criticality => a.criticality.cmp(&b.criticality),
+            SortColumn::Size => a.size.cmp(&b.size),
+        }
+    }
+}
+
+impl fmt::Display for SortColumn {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            SortColumn::Name => "Name",
+            SortColumn::Category => "Category",
+            SortColumn::ComponentType => "Type",
+            SortColumn::Version => "Version",
+            SortColumn::ReleaseDate => "Release date",
+            SortColumn::Criticality => "Criticality",
+            SortColumn::Size => "Size",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// 过滤和排序条件
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ComponentQuery {
+    /// 不区分大小写, 匹配名称, 分类, 类型, 版本, 重要性或 packageID
+    pub filter: String,
+    pub sort: SortColumn,
+    pub descending: bool,
+}
+
+impl ComponentQuery {
+    pub fn rows(&self, manifest: &Manifest) -> Vec<ComponentRow> {
+        let filter = self.filter.trim().to_lowercase();
+        let mut rows: Vec<ComponentRow> = manifest
+            .software_components
+            .iter()
+            .enumerate()
+            .map(|(index, component)| ComponentRow::new(index, component))
+            .filter(|row| row.matches(&filter))
+            .collect();
+        rows.sort_by(|a, b| {
+            let ordering = self.sort.compare(manifest, a, b);
+            if self.descending {
+                ordering.reverse()
+            } else {
+                ordering
+            }
+        });
+        rows
+    }
+}
+
+/// 选中组件后显示的详细信息
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ComponentDetail {
+    pub name: String,
+    pub path: String,
+    pub description: String,
+    /// 设备名称和 componentID
+    pub devices: Vec<String>,
+    pub system_ids: Vec<String>,
+    pub os_codes: Vec<String>,
+    /// 算法和哈希值
+    pub hashes: Vec<(String, String)>,
+}
+
+impl From<&SoftwareComponent> for ComponentDetail {
+    fn from(component: &SoftwareComponent) -> Self {
+        let to_strings = |values: Vec<&str>| values.into_iter().map(String::from).collect();
+        ComponentDetail {
+            name: component.display_name().to_string(),
+            path: component.path.clone(),
+            description: component.display_description().to_string(),
+            devices: component
+                .devices()
+                .map(|device| {
+                    format!(
+                        "{} ({})",
+                        display_text(&device.display),
+                        device.component_id
+                    )
+                })
+                .collect(),
+            system_ids: to_strings(component.system_ids()),
+            os_codes: to_strings(component.os_codes()),
+            hashes: component
+                .cryptography
+                .iter()
+                .flat_map(|c| c.hashes.iter())
+                .map(|hash| (hash.algorithm.clone(), hash.value.clone()))
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::manifest::{Category, Display};
+
+    fn component(
+        package_id: &str,
+        name: &str,
+        category: &str,
+        date_time: &str,
+        size: u64,
+    ) -> SoftwareComponent {
+        SoftwareComponent {
+            package_id: package_id.into(),
+            name: vec![Display {
+                lang: Some("en".into()),
+                text: name.into(),
+            }],
+            category: Some(Category {
+                value: category.into(),
+                display: vec![Display {
+                    lang: Some("en".into()),
+                    text: category.into(),
+                }],
+            }),
+            date_time: date_time.into(),
+            size: Some(size),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_component_query() {
+        let manifest = Manifest {
+            software_components: vec![
+                component(
+                    "A1",
+                    "Intel Chipset Driver",
+                    "Chipset",
+                    "2024-03-01T00:00:00",
+                    300,
+                ),
+                component("B2", "BIOS Update", "BIOS", "2024-05-01T00:00:00", 100),
+                component(
+                    "C3",
+                    "Realtek Audio Driver",
+                    "Audio",
+                    "2023-12-01T00:00:00",
+                    200,
+                ),
+            ],
+            ..Default::default()
+        };
+        let ids = |rows: Vec<ComponentRow>| -> Vec<String> {
+            rows.into_iter().map(|row| row.package_id).collect()
+        };
+
+        let mut query = ComponentQuery::default();
+        assert_eq!(ids(query.rows(&manifest)), ["B2", "A1", "C3"]);
+
+        query.sort = SortColumn::ReleaseDate;
+        query.descending = true;
+        assert_eq!(ids(query.rows(&manifest)), ["B2", "A1", "C3"]);
+
+        query.sort = SortColumn::Size;
+        query.descending = false;
+        query.filter = " DRIVER ".into();
+        let rows = query.rows(&manifest);
+        assert_eq!(rows[0].index, 2);
+        assert_eq!(ids(rows), ["C3", "A1"]);
+
+        query.filter = "audio".into();
+        assert_eq!(ids(query.rows(&manifest)), ["C3"]);
+
+        // 按数值比较, 1.10.0 比 1.9.0 新
+        let mut manifest = manifest;
+        for (component, version) in manifest
+            .software_components
+            .iter_mut()
+            .zip(["1.10.0", "1.9.0", "1.2.3"])
+        {
+            component.dell_version = version.into();
+        }
+        query.filter.clear();
+        query.sort = SortColumn::Version;
+        assert_eq!(ids(query.rows(&manifest)), ["C3", "B2", "A1"]);
+    }
+}
@@ -0,0 +1,387 @@
+mod huffman;
+mod lzx;
+mod mszip;
+mod writer;
+
+use std::{
+    fs::{self, File},
+    io::{BufReader, Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+};
+
+use crate::error::CatalogError;
+
+pub use writer::CabinetBuilder;
+
+const SIGNATURE: &[u8; 4] = b"MSCF";
+const FLAG_PREV_CABINET: u16 = 0x0001;
+const FLAG_NEXT_CABINET: u16 = 0x0002;
+const FLAG_RESERVE_PRESENT: u16 = 0x0004;
+const ATTRIBUTE_NAME_IS_UTF: u16 = 0x0080;
+
+/// CAB 文件夹使用的压缩方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompressionType {
+    None,
+    MsZip,
+    Quantum,
+    Lzx { window_bits: u32 },
+}
+
+impl CompressionType {
+    fn from_raw(raw: u16) -> Result<Self, CatalogError> {
+        match raw & 0x000f {
+            0 => Ok(CompressionType::None),
+            1 => Ok(CompressionType::MsZip),
+            2 => Ok(CompressionType::Quantum),
+            3 => Ok(CompressionType::Lzx {
+                window_bits: ((raw >> 8) & 0x1f) as u32,
+            }),
+            other => Err(CatalogError::ParseError(format!(
+                "unknown cab compression type {}",
+                other
+            ))),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct CabFolder {
+    first_data_offset: u32,
+    data_count: u16,
+    compression: CompressionType,
+}
+
+/// CAB 中的一个文件
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CabEntry {
+    pub name: String,
+    pub size: u32,
+    pub folder: u16,
+    pub folder_offset: u32,
+    pub date: u16,
+    pub time: u16,
+    pub attributes: u16,
+}
+
+impl CabEntry {
+    /// 解析 DOS 格式的日期时间, 返回 (年, 月, 日, 时, 分, 秒)
+    pub fn date_time(&self) -> (u16, u16, u16, u16, u16, u16) {
+        (
+            (self.date >> 9) + 1980,
+            (self.date >> 5) & 0x0f,
+            self.date & 0x1f,
+            self.time >> 11,
+            (self.time >> 5) & 0x3f,
+            (self.time & 0x1f) * 2,
+        )
+    }
+}
+
+fn read_array<const N: usize, R: Read>(reader: &mut R) -> Result<[u8; N], CatalogError> {
+    let mut buf = [0u8; N];
+    reader.read_exact(&mut buf)?;
+    Ok(buf)
+}
+
+fn read_u8<R: Read>(reader: &mut R) -> Result<u8, CatalogError> {
+    Ok(read_array::<1, R>(reader)?[0])
+}
+
+fn read_u16<R: Read>(reader: &mut R) -> Result<u16, CatalogError> {
+    Ok(u16::from_le_bytes(read_array(reader)?))
+}
+
+fn read_u32<R: Read>(reader: &mut R) -> Result<u32, CatalogError> {
+    Ok(u32::from_le_bytes(read_array(reader)?))
+}
+
+fn read_cstring<R: Read>(reader: &mut R) -> Result<Vec<u8>, CatalogError> {
+    let mut bytes = Vec::new();
+    loop {
+        match read_u8(reader)? {
+            0 => return Ok(bytes),
+            b => bytes.push(b),
+        }
+    }
+}
+
+/// CAB 规范中的校验和算法, 尾部不足 4 字节时按倒序拼接
+pub(crate) fn checksum(data: &[u8], seed: u32) -> u32 {
+    let mut csum = seed;
+    let mut chunks = data.chunks_exact(4);
+    for chunk in &mut chunks {
+        csum ^= u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+    }
+    let mut tail = 0u32;
+    for &b in chunks.remainder() {
+        tail = (tail << 8) | b as u32;
+    }
+    csum ^ tail
+}
+
+/// 只读的 CAB 文件, 支持不压缩、MSZIP 和 LZX
+#[derive(Debug)]
+pub struct Cabinet<R> {
+    reader: R,
+    folders: Vec<CabFolder>,
+    entries: Vec<CabEntry>,
+    data_reserve: u8,
+}
+
+impl Cabinet<BufReader<File>> {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, CatalogError> {
+        Cabinet::new(BufReader::new(File::open(path)?))
+    }
+}
+
+impl<R: Read + Seek> Cabinet<R> {
+    pub fn new(mut reader: R) -> Result<Self, CatalogError> {
+        let signature: [u8; 4] = read_array(&mut reader)?;
+        if &signature != SIGNATURE {
+            return Err(CatalogError::ParseError("not a cabinet file".into()));
+        }
+        let _reserved1 = read_u32(&mut reader)?;
+        let _cabinet_size = read_u32(&mut reader)?;
+        let _reserved2 = read_u32(&mut reader)?;
+        let files_offset = read_u32(&mut reader)?;
+        let _reserved3 = read_u32(&mut reader)?;
+        let _version: [u8; 2] = read_array(&mut reader)?;
+        let folder_count = read_u16(&mut reader)?;
+        let file_count = read_u16(&mut reader)?;
+        let flags = read_u16(&mut reader)?;
+        let _set_id = read_u16(&mut reader)?;
+        let _cabinet_index = read_u16(&mut reader)?;
+
+        let (mut folder_reserve, mut data_reserve) = (0, 0);
+        if flags & FLAG_RESERVE_PRESENT != 0 {
+            let header_reserve = read_u16(&mut reader)?;
+            folder_reserve = read_u8(&mut reader)?;
+            data_reserve = read_u8(&mut reader)?;
+            reader.seek(SeekFrom::Current(header_reserve as i64))?;
+        }
+        if flags & (FLAG_PREV_CABINET | FLAG_NEXT_CABINET) != 0 {
+            return Err(CatalogError::ParseError(
+                "multi-volume cabinets are not supported".into(),
+            ));
+        }
+
+        let mut folders = Vec::with_capacity(folder_count as usize);
+        for _ in 0..folder_count {
+            let first_data_offset = read_u32(&mut reader)?;
+            let data_count = read_u16(&mut reader)?;
+            let compression = CompressionType::from_raw(read_u16(&mut reader)?)?;
+            reader.seek(SeekFrom::Current(folder_reserve as i64))?;
+            folders.push(CabFolder {
+                first_data_offset,
+                data_count,
+                compression,
+            });
+        }
+
+        reader.seek(SeekFrom::Start(files_offset as u64))?;
+        let mut entries = Vec::with_capacity(file_count as usize);
+        for _ in 0..file_count {
+            let size = read_u32(&mut reader)?;
+            let folder_offset = read_u32(&mut reader)?;
+            let folder = read_u16(&mut reader)?;
+            let date = read_u16(&mut reader)?;
+            let time = read_u16(&mut reader)?;
+            let attributes = read_u16(&mut reader)?;
+            let name = read_cstring(&mut reader)?;
+            let name = if attributes & ATTRIBUTE_NAME_IS_UTF != 0 {
+                String::from_utf8_lossy(&name).into_owned()
+            } else {
+                name.iter().map(|&b| b as char).collect()
+            };
+            if folder as usize >= folders.len() {
+                return Err(CatalogError::ParseError(format!(
+                    "\"{}\" is continued from another cabinet",
+                    name
+                )));
+            }
+            entries.push(CabEntry {
+                name,
+                size,
+                folder,
+                folder_offset,
+                date,
+                time,
+                attributes,
+            });
+        }
+
+        Ok(Cabinet {
+            reader,
+            folders,
+            entries,
+            data_reserve,
+        })
+    }
+
+    pub fn entries(&self) -> &[CabEntry] {
+        &self.entries
+    }
+
+    pub fn compression(&self, folder: u16) -> Option<CompressionType> {
+        self.folders.get(folder as usize).map(|f| f.compression)
+    }
+
+    fn read_folder(&mut self, index: u16) -> Result<Vec<u8>, CatalogError> {
+        let folder = self.folders[index as usize].clone();
+        self.reader
+            .seek(SeekFrom::Start(folder.first_data_offset as u64))?;
+
+        let mut blocks = Vec::with_capacity(folder.data_count as usize);
+        for _ in 0..folder.data_count {
+            let expected = read_u32(&mut self.reader)?;
+            let header: [u8; 4] = read_array(&mut self.reader)?;
+            let mut reserve = vec![0u8; self.data_reserve as usize];
+            self.reader.read_exact(&mut reserve)?;
+            let compressed_size = u16::from_le_bytes([header[0], header[1]]);
+            let uncompressed_size = u16::from_le_bytes([header[2], header[3]]);
+            let mut data = vec![0u8; compressed_size as usize];
+            self.reader.read_exact(&mut data)?;
+            if expected != 0 {
+                let mut tail = header.to_vec();
+                tail.extend_from_slice(&reserve);
+                if checksum(&tail, checksum(&data, 0)) != expected {
+                    return Err(CatalogError::ParseError(
+                        "cab data block checksum mismatch".into(),
+                    ));
+                }
+            }
+            blocks.push((data, uncompressed_size as usize));
+        }
+
+        match folder.compression {
+            CompressionType::None => Ok(blocks.into_iter().flat_map(|(data, _)| data).collect()),
+            CompressionType::MsZip => {
+                let mut output = Vec::new();
+                for (data, size) in blocks.iter() {
+                    mszip::decompress_block(data, &mut output, *size)?;
+                }
+                Ok(output)
+            }
+            CompressionType::Lzx { window_bits } => {
+                let frame_sizes: Vec<usize> = blocks.iter().map(|(_, size)| *size).collect();
+                let data: Vec<u8> = blocks.into_iter().flat_map(|(data, _)| data).collect();
+                lzx::decompress(&data, window_bits, &frame_sizes)
+            }
+            CompressionType::Quantum => Err(CatalogError::ParseError(
+                "Quantum compression is not supported".into(),
+            )),
+        }
+    }
+
+    /// 读取指定文件的内容, 文件名不区分大小写
+    pub fn read_entry(&mut self, name: &str) -> Result<Vec<u8>, CatalogError> {
+        let entry = self
+            .entries
+            .iter()
+            .find(|e| e.name.eq_ignore_ascii_case(name))
+            .cloned()
+            .ok_or_else(|| CatalogError::CurrentFileError(name.into()))?;
+        let folder = self.read_folder(entry.folder)?;
+        let start = entry.folder_offset as usize;
+        let end = start + entry.size as usize;
+        folder.get(start..end).map(<[u8]>::to_vec).ok_or_else(|| {
+            CatalogError::ParseError(format!("\"{}\" is outside its cab folder", entry.name))
+        })
+    }
+
+    /// 解压指定文件到 `output_dir`, 返回解压后的实际路径
+    pub fn extract_entry(
+        &mut self,
+        name: &str,
+        output_dir: &Path,
+    ) -> Result<PathBuf, CatalogError> {
+        let data = self.read_entry(name)?;
+        let output_path = output_dir.join(entry_file_name(name)?);
+        fs::create_dir_all(output_dir)?;
+        fs::write(&output_path, data)?;
+        Ok(output_path)
+    }
+}
+
+/// CAB 内的文件名可能带目录, 只取最后一段, 防止写到输出目录之外
+fn entry_file_name(name: &str) -> Result<&str, CatalogError> {
+    name.rsplit(['\\', '/'])
+        .next()
+        .filter(|n| !n.is_empty() && *n != "." && *n != "..")
+        .ok_or_else(|| CatalogError::ParseError(format!("invalid cab entry name \"{}\"", name)))
+}
+
+/// 列出 CAB 中的全部文件
+pub fn list_cab<P: AsRef<Path>>(cab_path: P) -> Result<Vec<CabEntry>, CatalogError> {
+    Ok(Cabinet::open(cab_path)?.entries().to_vec())
+}
+
+/// 解压 CAB 中的全部文件到 `output_dir`
+pub fn extract_cab<P: AsRef<Path>>(
+    cab_path: P,
+    output_dir: &Path,
+) -> Result<Vec<PathBuf>, CatalogError> {
+    let mut cabinet = Cabinet::open(cab_path)?;
+    let names: Vec<String> = cabinet.entries().iter().map(|e| e.name.clone()).collect();
+    names
+        .iter()
+        .map(|name| cabinet.extract_entry(name, output_dir))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CAB_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/../Precision_0CBB.cab");
+    const XML_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/../Precision_0CBB.xml");
+
+    #[test]
+    fn test_list_cab() {
+        let entries = list_cab(CAB_PATH).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].name, "Precision_0CBB.xml");
+        assert_eq!(entries[0].size, 51836);
+        let cabinet = Cabinet::open(CAB_PATH).unwrap();
+        assert_eq!(cabinet.compression(0), Some(CompressionType::MsZip));
+    }
+
+    #[test]
+    fn test_read_entry() {
+        let mut cabinet = Cabinet::open(CAB_PATH).unwrap();
+        let data = cabinet.read_entry("precision_0cbb.xml").unwrap();
+        assert_eq!(data, fs::read(XML_PATH).unwrap());
+        assert!(cabinet.read_entry("missing.xml").is_err());
+    }
+
+    /// `makecab /D CompressionType=LZX /D CompressionMemory=21` 生成的 cab 和原始的 xml
+    const LZX_CAB_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/lzx21.cab");
+    const LZX_XML_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/lzx21.xml");
+
+    #[test]
+    #[ignore = "needs tests/fixtures/lzx21.cab produced by makecab"]
+    fn test_read_lzx_entry() {
+        let mut cabinet = Cabinet::open(LZX_CAB_PATH).unwrap();
+        assert!(matches!(
+            cabinet.compression(0),
+            Some(CompressionType::Lzx { window_bits: 21 })
+        ));
+        let name = cabinet.entries()[0].name.clone();
+        let data = cabinet.read_entry(&name).unwrap();
+        assert_eq!(data, fs::read(LZX_XML_PATH).unwrap());
+    }
+
+    #[test]
+    fn test_checksum() {
+        let data = b"catalog";
+        let value = checksum(data, 0);
+        assert_eq!(value, u32::from_le_bytes(*b"cata") ^ 0x006c6f67);
+    }
+
+    #[test]
+    fn test_entry_file_name() {
+        assert_eq!(entry_file_name("a\\b\\c.xml").unwrap(), "c.xml");
+        assert!(entry_file_name("..").is_err());
+    }
+}
@@ -0,0 +1,67 @@
+use crate::error::CatalogError;
+
+/// 逐位读取的输入源, Huffman 码按最高位先出的顺序读取
+pub(crate) trait BitSource {
+    fn read_bit(&mut self) -> Result<u32, CatalogError>;
+}
+
+/// 规范 Huffman 解码表, 参考 zlib 的 puff 实现
+#[derive(Debug, Default, Clone)]
+pub(crate) struct Huffman {
+    counts: Vec<u16>,
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    /// 由每个符号的码长构造解码表, 码长为 0 的符号不参与编码
+    pub(crate) fn new(lengths: &[u8]) -> Result<Self, CatalogError> {
+        let max_bits = lengths.iter().copied().max().unwrap_or(0) as usize;
+        let mut counts = vec![0u16; max_bits + 1];
+        for &len in lengths {
+            counts[len as usize] += 1;
+        }
+        counts[0] = 0;
+
+        let mut left: i32 = 1;
+        for &count in counts.iter().skip(1) {
+            left <<= 1;
+            left -= count as i32;
+            if left < 0 {
+                return Err(CatalogError::ParseError(
+                    "over-subscribed huffman code".into(),
+                ));
+            }
+        }
+
+        let mut offsets = vec![0u16; max_bits + 2];
+        for len in 1..=max_bits {
+            offsets[len + 1] = offsets[len] + counts[len];
+        }
+        let mut symbols = vec![0u16; lengths.len()];
+        for (symbol, &len) in lengths.iter().enumerate() {
+            if len != 0 {
+                symbols[offsets[len as usize] as usize] = symbol as u16;
+                offsets[len as usize] += 1;
+            }
+        }
+        Ok(Huffman { counts, symbols })
+    }
+
+    pub(crate) fn decode<B: BitSource>(&self, bits: &mut B) -> Result<u16, CatalogError> {
+        let mut code: i32 = 0;
+        let mut first: i32 = 0;
+        let mut index: i32 = 0;
+        for &count in self.counts.iter().skip(1) {
+            code |= bits.read_bit()? as i32;
+            let count = count as i32;
+            if code - count < first {
+                return Ok(self.symbols[(index + (code - first)) as usize]);
+            }
+            index += count;
+            first += count;
+            first <<= 1;
+            code <<= 1;
+        }
+        Err(CatalogError::ParseError("invalid huffman code".into()))
+    }
+}
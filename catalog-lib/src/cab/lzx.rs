@@ -0,0 +1,592 @@
+use crate::error::CatalogError;
+
+use super::{
+    huffman::{BitSource, Huffman},
+    mszip::copy_match,
+};
+
+const NUM_CHARS: usize = 256;
+const NUM_PRIMARY_LENGTHS: u16 = 7;
+const NUM_SECONDARY_LENGTHS: usize = 249;
+const PRETREE_NUM_ELEMENTS: usize = 20;
+const ALIGNED_NUM_ELEMENTS: usize = 8;
+const MIN_MATCH: usize = 2;
+
+const BLOCKTYPE_VERBATIM: u32 = 1;
+const BLOCKTYPE_ALIGNED: u32 = 2;
+const BLOCKTYPE_UNCOMPRESSED: u32 = 3;
+
+fn invalid(message: &str) -> CatalogError {
+    CatalogError::ParseError(format!("LZX: {}", message))
+}
+
+/// 每个 position slot 的附加位数和基准值
+fn position_tables() -> ([u8; 51], [u32; 51]) {
+    let mut extra_bits = [0u8; 51];
+    let mut position_base = [0u32; 51];
+    let mut bits = 0;
+    for i in (0..50).step_by(2) {
+        extra_bits[i] = bits;
+        extra_bits[i + 1] = bits;
+        if i != 0 && bits < 17 {
+            bits += 1;
+        }
+    }
+    extra_bits[50] = 17;
+    let mut base = 0u32;
+    for i in 0..51 {
+        position_base[i] = base;
+        base += 1 << extra_bits[i];
+    }
+    (extra_bits, position_base)
+}
+
+fn position_slots(window_bits: u32) -> Result<usize, CatalogError> {
+    match window_bits {
+        15..=19 => Ok(window_bits as usize * 2),
+        20 => Ok(42),
+        21 => Ok(50),
+        _ => Err(invalid(&format!(
+            "unsupported window size 2^{}",
+            window_bits
+        ))),
+    }
+}
+
+/// LZX 位读取器: 输入按 16 位小端字组织, 每个字内高位优先
+struct BitReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+    bit_buffer: u64,
+    bits_left: u32,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        BitReader {
+            data,
+            pos: 0,
+            bit_buffer: 0,
+            bits_left: 0,
+        }
+    }
+
+    fn ensure(&mut self, need: u32) -> Result<(), CatalogError> {
+        while self.bits_left < need {
+            // 输入末尾允许补零, 最后一帧常常不足一个字
+            if self.pos > self.data.len() + 2 {
+                return Err(invalid("unexpected end of data"));
+            }
+            let lo = self.data.get(self.pos).copied().unwrap_or(0) as u64;
+            let hi = self.data.get(self.pos + 1).copied().unwrap_or(0) as u64;
+            self.pos += 2;
+            self.bit_buffer |= ((hi << 8) | lo) << (48 - self.bits_left);
+            self.bits_left += 16;
+        }
+        Ok(())
+    }
+
+    fn read_bits(&mut self, need: u32) -> Result<u32, CatalogError> {
+        if need == 0 {
+            return Ok(0);
+        }
+        self.ensure(need)?;
+        let value = (self.bit_buffer >> (64 - need)) as u32;
+        self.bit_buffer <<= need;
+        self.bits_left -= need;
+        Ok(value)
+    }
+
+    /// 帧结束时对齐到 16 位边界
+    fn align_word(&mut self) {
+        let drop = self.bits_left % 16;
+        self.bit_buffer <<= drop;
+        self.bits_left -= drop;
+    }
+
+    /// 未压缩块之前的对齐, 丢弃 1 到 16 位
+    fn align_uncompressed(&mut self) {
+        if self.bits_left == 0 {
+            self.pos += 2;
+        }
+        self.bit_buffer = 0;
+        self.bits_left = 0;
+    }
+
+    fn read_bytes(&mut self, len: usize) -> Result<&'a [u8], CatalogError> {
+        let bytes = self
+            .data
+            .get(self.pos..self.pos + len)
+            .ok_or_else(|| invalid("unexpected end of uncompressed block"))?;
+        self.pos += len;
+        Ok(bytes)
+    }
+}
+
+impl BitSource for BitReader<'_> {
+    fn read_bit(&mut self) -> Result<u32, CatalogError> {
+        self.read_bits(1)
+    }
+}
+
+struct LzxDecoder {
+    extra_bits: [u8; 51],
+    position_base: [u32; 51],
+    main_lengths: Vec<u8>,
+    length_lengths: Vec<u8>,
+    main_tree: Huffman,
+    length_tree: Huffman,
+    aligned_tree: Huffman,
+    block_type: u32,
+    block_length: usize,
+    block_remaining: usize,
+    header_read: bool,
+    intel_file_size: i32,
+    intel_started: bool,
+    r: [u32; 3],
+}
+
+impl LzxDecoder {
+    fn new(window_bits: u32) -> Result<Self, CatalogError> {
+        let (extra_bits, position_base) = position_tables();
+        Ok(LzxDecoder {
+            extra_bits,
+            position_base,
+            main_lengths: vec![0; NUM_CHARS + position_slots(window_bits)? * 8],
+            length_lengths: vec![0; NUM_SECONDARY_LENGTHS],
+            main_tree: Huffman::default(),
+            length_tree: Huffman::default(),
+            aligned_tree: Huffman::default(),
+            block_type: 0,
+            block_length: 0,
+            block_remaining: 0,
+            header_read: false,
+            intel_file_size: 0,
+            intel_started: false,
+            r: [1, 1, 1],
+        })
+    }
+
+    /// 用预编码树读取码长, 码长以与上一块的差值编码
+    fn read_lengths(
+        bits: &mut BitReader,
+        lengths: &mut [u8],
+        first: usize,
+        last: usize,
+    ) -> Result<(), CatalogError> {
+        let mut pretree_lengths = [0u8; PRETREE_NUM_ELEMENTS];
+        for len in pretree_lengths.iter_mut() {
+            *len = bits.read_bits(4)? as u8;
+        }
+        let pretree = Huffman::new(&pretree_lengths)?;
+
+        let mut x = first;
+        while x < last {
+            let (z, run) = match pretree.decode(bits)? {
+                17 => (None, bits.read_bits(4)? as usize + 4),
+                18 => (None, bits.read_bits(5)? as usize + 20),
+                19 => {
+                    let run = bits.read_bits(1)? as usize + 4;
+                    let z = pretree.decode(bits)?;
+                    if z > 16 {
+                        return Err(invalid("bad code length delta in run"));
+                    }
+                    (Some(z as u8), run)
+                }
+                z => (Some(z as u8), 1),
+            };
+            if x + run > lengths.len() {
+                return Err(invalid("code length run overflows table"));
+            }
+            // 与 libmspack 相同, 重复的码长只由第一个元素的差值算出一次
+            let value = z.map_or(0, |z| (lengths[x] + 17 - z) % 17);
+            lengths[x..x + run].fill(value);
+            x += run;
+        }
+        Ok(())
+    }
+
+    fn read_block_header(&mut self, bits: &mut BitReader) -> Result<(), CatalogError> {
+        if !self.header_read {
+            if bits.read_bits(1)? == 1 {
+                let high = bits.read_bits(16)?;
+                let low = bits.read_bits(16)?;
+                self.intel_file_size = ((high << 16) | low) as i32;
+            }
+            self.header_read = true;
+        }
+
+        self.block_type = bits.read_bits(3)?;
+        let high = bits.read_bits(16)? as usize;
+        let low = bits.read_bits(8)? as usize;
+        self.block_length = (high << 8) | low;
+        self.block_remaining = self.block_length;
+
+        match self.block_type {
+            BLOCKTYPE_VERBATIM | BLOCKTYPE_ALIGNED => {
+                if self.block_type == BLOCKTYPE_ALIGNED {
+                    let mut aligned_lengths = [0u8; ALIGNED_NUM_ELEMENTS];
+                    for len in aligned_lengths.iter_mut() {
+                        *len = bits.read_bits(3)? as u8;
+                    }
+                    self.aligned_tree = Huffman::new(&aligned_lengths)?;
+                }
+                let main_len = self.main_lengths.len();
+                Self::read_lengths(bits, &mut self.main_lengths, 0, NUM_CHARS)?;
+                Self::read_lengths(bits, &mut self.main_lengths, NUM_CHARS, main_len)?;
+                self.main_tree = Huffman::new(&self.main_lengths)?;
+                if self.main_lengths[0xE8] != 0 {
+                    self.intel_started = true;
+                }
+                Self::read_lengths(bits, &mut self.length_lengths, 0, NUM_SECONDARY_LENGTHS)?;
+                self.length_tree = Huffman::new(&self.length_lengths)?;
+            }
+            BLOCKTYPE_UNCOMPRESSED => {
+                self.intel_started = true;
+                bits.align_uncompressed();
+                let header = bits.read_bytes(12)?;
+                for (i, r) in self.r.iter_mut().enumerate() {
+                    let b = &header[i * 4..i * 4 + 4];
+                    *r = u32::from_le_bytes([b[0], b[1], b[2], b[3]]);
+                }
+            }
+            _ => return Err(invalid(&format!("bad block type {}", self.block_type))),
+        }
+        Ok(())
+    }
+
+    /// 解码一个主树元素, 返回输出的字节数
+    fn decode_element(
+        &mut self,
+        bits: &mut BitReader,
+        output: &mut Vec<u8>,
+    ) -> Result<usize, CatalogError> {
+        let main_element = self.main_tree.decode(bits)?;
+        if (main_element as usize) < NUM_CHARS {
+            output.push(main_element as u8);
+            return Ok(1);
+        }
+
+        let main_element = main_element - NUM_CHARS as u16;
+        let mut match_length = (main_element & 7) as usize;
+        if main_element & 7 == NUM_PRIMARY_LENGTHS {
+            match_length += self.length_tree.decode(bits)? as usize;
+        }
+        match_length += MIN_MATCH;
+
+        let slot = (main_element >> 3) as usize;
+        let match_offset = match slot {
+            0 => self.r[0],
+            1 => {
+                self.r.swap(0, 1);
+                self.r[0]
+            }
+            2 => {
+                self.r.swap(0, 2);
+                self.r[0]
+            }
+            _ => {
+                let extra = self.extra_bits[slot] as u32;
+                let mut offset = self.position_base[slot] - 2;
+                if self.block_type == BLOCKTYPE_ALIGNED && extra >= 3 {
+                    offset += bits.read_bits(extra - 3)? << 3;
+                    offset += self.aligned_tree.decode(bits)? as u32;
+                } else if extra > 0 {
+                    offset += bits.read_bits(extra)?;
+                } else {
+                    offset = 1;
+                }
+                self.r[2] = self.r[1];
+                self.r[1] = self.r[0];
+                self.r[0] = offset;
+                offset
+            }
+        };
+        copy_match(output, match_offset as usize, match_length)?;
+        Ok(match_length)
+    }
+
+    fn decode_frame(
+        &mut self,
+        bits: &mut BitReader,
+        output: &mut Vec<u8>,
+        frame_size: usize,
+    ) -> Result<(), CatalogError> {
+        let frame_end = output.len() + frame_size;
+        while output.len() < frame_end {
+            if self.block_remaining == 0 {
+                self.read_block_header(bits)?;
+            }
+            let run = self.block_remaining.min(frame_end - output.len());
+            if self.block_type == BLOCKTYPE_UNCOMPRESSED {
+                output.extend_from_slice(bits.read_bytes(run)?);
+                self.block_remaining -= run;
+                if self.block_remaining == 0 && self.block_length % 2 == 1 {
+                    bits.pos += 1;
+                }
+                continue;
+            }
+            let mut done = 0;
+            while done < run {
+                done += self.decode_element(bits, output)?;
+            }
+            if done > self.block_remaining || output.len() > frame_end {
+                return Err(invalid("match overruns block or frame"));
+            }
+            self.block_remaining -= done;
+        }
+        bits.align_word();
+        Ok(())
+    }
+
+    /// 还原 x86 CALL 指令(E8)的地址转换
+    fn translate_e8(&self, frame: &mut [u8], mut current: i32) {
+        if !self.intel_started || self.intel_file_size == 0 || frame.len() <= 10 {
+            return;
+        }
+        let end = frame.len() - 10;
+        let mut i = 0;
+        while i < end {
+            if frame[i] != 0xE8 {
+                i += 1;
+                current = current.wrapping_add(1);
+                continue;
+            }
+            let bytes = [frame[i + 1], frame[i + 2], frame[i + 3], frame[i + 4]];
+            let absolute = i32::from_le_bytes(bytes);
+            if absolute >= current.wrapping_neg() && absolute < self.intel_file_size {
+                let relative = if absolute >= 0 {
+                    absolute.wrapping_sub(current)
+                } else {
+                    absolute.wrapping_add(self.intel_file_size)
+                };
+                frame[i + 1..i + 5].copy_from_slice(&relative.to_le_bytes());
+            }
+            i += 5;
+            current = current.wrapping_add(5);
+        }
+    }
+}
+
+/// 解压一个 LZX 文件夹. `data` 为所有 CFDATA 拼接后的数据,
+/// `frame_sizes` 为每个 CFDATA 解压后的大小
+pub(crate) fn decompress(
+    data: &[u8],
+    window_bits: u32,
+    frame_sizes: &[usize],
+) -> Result<Vec<u8>, CatalogError> {
+    let mut decoder = LzxDecoder::new(window_bits)?;
+    let mut bits = BitReader::new(data);
+    let mut window = Vec::with_capacity(frame_sizes.iter().sum());
+    let mut output = Vec::with_capacity(window.capacity());
+    for (index, &frame_size) in frame_sizes.iter().enumerate() {
+        let start = window.len();
+        decoder.decode_frame(&mut bits, &mut window, frame_size)?;
+        let mut frame = window[start..].to_vec();
+        if index < 32768 {
+            decoder.translate_e8(&mut frame, start as i32);
+        }
+        output.extend_from_slice(&frame);
+    }
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 测试用的位写入器, 与 `BitReader` 的顺序相反
+    #[derive(Default)]
+    struct BitWriter {
+        data: Vec<u8>,
+        buffer: u32,
+        count: u32,
+    }
+
+    impl BitWriter {
+        fn write(&mut self, value: u32, bits: u32) {
+            for i in (0..bits).rev() {
+                self.buffer = (self.buffer << 1) | ((value >> i) & 1);
+                self.count += 1;
+                if self.count == 16 {
+                    self.data
+                        .extend_from_slice(&(self.buffer as u16).to_le_bytes());
+                    self.buffer = 0;
+                    self.count = 0;
+                }
+            }
+        }
+
+        fn finish(mut self) -> Vec<u8> {
+            if self.count > 0 {
+                self.write(0, 16 - self.count);
+            }
+            self.data
+        }
+    }
+
+    /// 预编码树中所有符号码长为 5, 码字即符号本身
+    fn write_lengths(writer: &mut BitWriter, lengths: &[u8]) {
+        for _ in 0..PRETREE_NUM_ELEMENTS {
+            writer.write(5, 4);
+        }
+        for &len in lengths {
+            writer.write((17 - len as u32) % 17, 5);
+        }
+    }
+
+    /// 生成一个 verbatim 块: 主树所有元素码长 9, 长度树所有元素码长 8
+    fn compress_verbatim(input: &[u8]) -> Vec<u8> {
+        let (extra_bits, position_base) = position_tables();
+        let slots = position_slots(15).unwrap();
+        let mut writer = BitWriter::default();
+        writer.write(0, 1);
+        writer.write(BLOCKTYPE_VERBATIM, 3);
+        writer.write((input.len() >> 8) as u32, 16);
+        writer.write((input.len() & 0xff) as u32, 8);
+        write_lengths(&mut writer, &[9; NUM_CHARS]);
+        write_lengths(&mut writer, &vec![9; slots * 8]);
+        write_lengths(&mut writer, &[8; NUM_SECONDARY_LENGTHS]);
+
+        let mut r0 = 1usize;
+        let mut pos = 0;
+        while pos < input.len() {
+            let (mut best_len, mut best_offset) = (0, 0);
+            for offset in 1..=pos.min(1000) {
+                let len = (0..(input.len() - pos).min(257))
+                    .take_while(|&i| input[pos + i] == input[pos + i - offset])
+                    .count();
+                if len > best_len {
+                    (best_len, best_offset) = (len, offset);
+                }
+            }
+            if best_len < 3 {
+                writer.write(input[pos] as u32, 9);
+                pos += 1;
+                continue;
+            }
+            let length_header = (best_len - MIN_MATCH).min(7);
+            let (slot, verbatim) = if best_offset == r0 {
+                (0, None)
+            } else {
+                let formatted = best_offset as u32 + 2;
+                let slot = (0..slots)
+                    .rev()
+                    .find(|&s| position_base[s] <= formatted)
+                    .unwrap();
+                r0 = best_offset;
+                (slot, Some(formatted - position_base[slot]))
+            };
+            writer.write((NUM_CHARS + (slot << 3 | length_header)) as u32, 9);
+            if length_header == 7 {
+                writer.write((best_len - MIN_MATCH - 7) as u32, 8);
+            }
+            if let Some(verbatim) = verbatim {
+                writer.write(verbatim, extra_bits[slot] as u32);
+            }
+            pos += best_len;
+        }
+        writer.finish()
+    }
+
+    #[test]
+    fn test_verbatim_block() {
+        let input = b"abcabcabcabc catalog catalog catalog abcabc xyz".repeat(20);
+        let data = compress_verbatim(&input);
+        let output = decompress(&data, 15, &[input.len()]).unwrap();
+        assert_eq!(output, input);
+    }
+
+    #[test]
+    fn test_read_lengths_same_run() {
+        let mut writer = BitWriter::default();
+        for _ in 0..PRETREE_NUM_ELEMENTS {
+            writer.write(5, 4);
+        }
+        // 19, 重复 4 次, 差值 15: (1 + 17 - 15) % 17 = 3
+        writer.write(19, 5);
+        writer.write(0, 1);
+        writer.write(15, 5);
+        writer.write(16, 5);
+        let data = writer.finish();
+        let mut lengths = [1, 2, 3, 4, 5];
+        LzxDecoder::read_lengths(&mut BitReader::new(&data), &mut lengths, 0, 5).unwrap();
+        assert_eq!(lengths, [3, 3, 3, 3, 6]);
+
+        // 重复的差值只能是 0 到 16
+        let mut writer = BitWriter::default();
+        for _ in 0..PRETREE_NUM_ELEMENTS {
+            writer.write(5, 4);
+        }
+        writer.write(19, 5);
+        writer.write(0, 1);
+        writer.write(18, 5);
+        let data = writer.finish();
+        let mut lengths = [0; 4];
+        assert!(LzxDecoder::read_lengths(&mut BitReader::new(&data), &mut lengths, 0, 4).is_err());
+    }
+
+    #[test]
+    fn test_aligned_block() {
+        let literals = b"abcdefghijklmnopqrstuvwxyz0123456789";
+        let mut expected = literals.to_vec();
+        for (offset, length) in [(34, 5), (15, 3)] {
+            for _ in 0..length {
+                expected.push(expected[expected.len() - offset]);
+            }
+        }
+
+        let mut writer = BitWriter::default();
+        writer.write(0, 1);
+        writer.write(BLOCKTYPE_ALIGNED, 3);
+        writer.write(0, 16);
+        writer.write(expected.len() as u32, 8);
+        // 码长不等的对齐树: 0, 10, 110, 1110, 11110, ...
+        for len in [1, 2, 3, 4, 5, 6, 7, 7] {
+            writer.write(len, 3);
+        }
+        // 256 个字符的码长都是 9, 用 19 每次重复 4 个
+        for _ in 0..PRETREE_NUM_ELEMENTS {
+            writer.write(5, 4);
+        }
+        for _ in 0..NUM_CHARS / 4 {
+            writer.write(19, 5);
+            writer.write(0, 1);
+            writer.write(8, 5);
+        }
+        write_lengths(&mut writer, &vec![9; position_slots(15).unwrap() * 8]);
+        write_lengths(&mut writer, &[8; NUM_SECONDARY_LENGTHS]);
+
+        for &c in literals {
+            writer.write(c as u32, 9);
+        }
+        // 偏移 34: slot 10 (基准 32, 4 个附加位), 余数 4 = 1 个普通位 0 + 对齐符号 4
+        writer.write((NUM_CHARS + (10 << 3 | 3)) as u32, 9);
+        writer.write(0, 1);
+        writer.write(0b11110, 5);
+        // 偏移 15: slot 8 (基准 16, 3 个附加位全部来自对齐树), 余数 1
+        writer.write((NUM_CHARS + (8 << 3 | 1)) as u32, 9);
+        writer.write(0b10, 2);
+        let data = writer.finish();
+
+        let output = decompress(&data, 15, &[expected.len()]).unwrap();
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn test_uncompressed_block() {
+        let input = b"odd sized".to_vec();
+        let mut writer = BitWriter::default();
+        writer.write(0, 1);
+        writer.write(BLOCKTYPE_UNCOMPRESSED, 3);
+        writer.write(0, 16);
+        writer.write(input.len() as u32, 8);
+        let mut data = writer.finish();
+        for _ in 0..3 {
+            data.extend_from_slice(&1u32.to_le_bytes());
+        }
+        data.extend_from_slice(&input);
+        let output = decompress(&data, 16, &[input.len()]).unwrap();
+        assert_eq!(output, input);
+    }
+}
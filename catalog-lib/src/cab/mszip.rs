@@ -0,0 +1,273 @@
+use crate::error::CatalogError;
+
+use super::huffman::{BitSource, Huffman};
+
+/// MSZIP 每个数据块的签名
+const SIGNATURE: &[u8; 2] = b"CK";
+
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+const CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+/// deflate 的位读取器, 低位优先
+struct BitReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+    bit_buffer: u32,
+    bit_count: u32,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        BitReader {
+            data,
+            pos: 0,
+            bit_buffer: 0,
+            bit_count: 0,
+        }
+    }
+
+    fn bits(&mut self, need: u32) -> Result<u32, CatalogError> {
+        let mut value = self.bit_buffer;
+        while self.bit_count < need {
+            let byte = *self
+                .data
+                .get(self.pos)
+                .ok_or_else(|| CatalogError::ParseError("unexpected end of MSZIP data".into()))?;
+            self.pos += 1;
+            value |= (byte as u32) << self.bit_count;
+            self.bit_count += 8;
+        }
+        self.bit_buffer = if need >= 32 { 0 } else { value >> need };
+        self.bit_count -= need;
+        Ok(value & ((1u64 << need) - 1) as u32)
+    }
+
+    fn align(&mut self) {
+        self.bit_buffer = 0;
+        self.bit_count = 0;
+    }
+
+    fn read_bytes(&mut self, len: usize) -> Result<&'a [u8], CatalogError> {
+        let bytes = self
+            .data
+            .get(self.pos..self.pos + len)
+            .ok_or_else(|| CatalogError::ParseError("unexpected end of MSZIP data".into()))?;
+        self.pos += len;
+        Ok(bytes)
+    }
+}
+
+impl BitSource for BitReader<'_> {
+    fn read_bit(&mut self) -> Result<u32, CatalogError> {
+        self.bits(1)
+    }
+}
+
+/// 解压一个 CFDATA 块并追加到 `output`, 之前的输出作为历史窗口
+pub(crate) fn decompress_block(
+    block: &[u8],
+    output: &mut Vec<u8>,
+    uncompressed_size: usize,
+) -> Result<(), CatalogError> {
+    if block.len() < 2 || &block[..2] != SIGNATURE {
+        return Err(CatalogError::ParseError("missing MSZIP signature".into()));
+    }
+    let start = output.len();
+    inflate(&block[2..], output)?;
+    if output.len() - start != uncompressed_size {
+        return Err(CatalogError::ParseError(format!(
+            "MSZIP block expanded to {} bytes, expected {}",
+            output.len() - start,
+            uncompressed_size
+        )));
+    }
+    Ok(())
+}
+
+/// 解压一段完整的 deflate 数据, 结果追加到 `output`
+pub(crate) fn inflate(data: &[u8], output: &mut Vec<u8>) -> Result<(), CatalogError> {
+    let mut reader = BitReader::new(data);
+    loop {
+        let last = reader.bits(1)? == 1;
+        match reader.bits(2)? {
+            0 => stored(&mut reader, output)?,
+            1 => {
+                let (literal, distance) = fixed_tables()?;
+                codes(&mut reader, output, &literal, &distance)?
+            }
+            2 => {
+                let (literal, distance) = dynamic_tables(&mut reader)?;
+                codes(&mut reader, output, &literal, &distance)?
+            }
+            _ => {
+                return Err(CatalogError::ParseError(
+                    "invalid deflate block type".into(),
+                ))
+            }
+        }
+        if last {
+            return Ok(());
+        }
+    }
+}
+
+fn stored(reader: &mut BitReader, output: &mut Vec<u8>) -> Result<(), CatalogError> {
+    reader.align();
+    let header = reader.read_bytes(4)?;
+    let len = u16::from_le_bytes([header[0], header[1]]);
+    let nlen = u16::from_le_bytes([header[2], header[3]]);
+    if len != !nlen {
+        return Err(CatalogError::ParseError(
+            "stored block length mismatch".into(),
+        ));
+    }
+    output.extend_from_slice(reader.read_bytes(len as usize)?);
+    Ok(())
+}
+
+fn fixed_tables() -> Result<(Huffman, Huffman), CatalogError> {
+    let mut lengths = [0u8; 288];
+    lengths[..144].fill(8);
+    lengths[144..256].fill(9);
+    lengths[256..280].fill(7);
+    lengths[280..].fill(8);
+    Ok((Huffman::new(&lengths)?, Huffman::new(&[5u8; 30])?))
+}
+
+fn dynamic_tables(reader: &mut BitReader) -> Result<(Huffman, Huffman), CatalogError> {
+    let nlen = reader.bits(5)? as usize + 257;
+    let ndist = reader.bits(5)? as usize + 1;
+    let ncode = reader.bits(4)? as usize + 4;
+    if nlen > 286 || ndist > 30 {
+        return Err(CatalogError::ParseError("bad deflate code lengths".into()));
+    }
+
+    let mut code_lengths = [0u8; 19];
+    for &index in CODE_LENGTH_ORDER.iter().take(ncode) {
+        code_lengths[index] = reader.bits(3)? as u8;
+    }
+    let code_lengths = Huffman::new(&code_lengths)?;
+
+    let mut lengths = vec![0u8; nlen + ndist];
+    let mut index = 0;
+    while index < nlen + ndist {
+        let symbol = code_lengths.decode(reader)?;
+        if symbol < 16 {
+            lengths[index] = symbol as u8;
+            index += 1;
+            continue;
+        }
+        let (value, repeat) = match symbol {
+            16 => {
+                if index == 0 {
+                    return Err(CatalogError::ParseError(
+                        "deflate repeat with no first length".into(),
+                    ));
+                }
+                (lengths[index - 1], 3 + reader.bits(2)? as usize)
+            }
+            17 => (0, 3 + reader.bits(3)? as usize),
+            _ => (0, 11 + reader.bits(7)? as usize),
+        };
+        if index + repeat > nlen + ndist {
+            return Err(CatalogError::ParseError(
+                "too many deflate code lengths".into(),
+            ));
+        }
+        lengths[index..index + repeat].fill(value);
+        index += repeat;
+    }
+    if lengths[256] == 0 {
+        return Err(CatalogError::ParseError(
+            "missing deflate end-of-block code".into(),
+        ));
+    }
+    Ok((
+        Huffman::new(&lengths[..nlen])?,
+        Huffman::new(&lengths[nlen..])?,
+    ))
+}
+
+fn codes(
+    reader: &mut BitReader,
+    output: &mut Vec<u8>,
+    literal: &Huffman,
+    distance: &Huffman,
+) -> Result<(), CatalogError> {
+    loop {
+        let symbol = literal.decode(reader)? as usize;
+        if symbol < 256 {
+            output.push(symbol as u8);
+            continue;
+        }
+        if symbol == 256 {
+            return Ok(());
+        }
+        let symbol = symbol - 257;
+        if symbol >= LENGTH_BASE.len() {
+            return Err(CatalogError::ParseError("invalid deflate length".into()));
+        }
+        let len = LENGTH_BASE[symbol] as usize + reader.bits(LENGTH_EXTRA[symbol] as u32)? as usize;
+        let symbol = distance.decode(reader)? as usize;
+        if symbol >= DISTANCE_BASE.len() {
+            return Err(CatalogError::ParseError("invalid deflate distance".into()));
+        }
+        let dist =
+            DISTANCE_BASE[symbol] as usize + reader.bits(DISTANCE_EXTRA[symbol] as u32)? as usize;
+        copy_match(output, dist, len)?;
+    }
+}
+
+/// 从历史窗口复制匹配内容, 源和目标可以重叠
+pub(crate) fn copy_match(
+    output: &mut Vec<u8>,
+    dist: usize,
+    len: usize,
+) -> Result<(), CatalogError> {
+    if dist == 0 || dist > output.len() {
+        return Err(CatalogError::ParseError(format!(
+            "match distance {} too far back",
+            dist
+        )));
+    }
+    let start = output.len() - dist;
+    for i in 0..len {
+        output.push(output[start + i]);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_inflate_stored_and_fixed() {
+        // 由 zlib 生成的 raw deflate 数据: "hello hello hello"
+        let fixed = [0xcb, 0x48, 0xcd, 0xc9, 0xc9, 0x57, 0xc8, 0x40, 0x90, 0x00];
+        let mut output = Vec::new();
+        inflate(&fixed, &mut output).unwrap();
+        assert_eq!(output, b"hello hello hello");
+
+        let stored = [0x01, 0x03, 0x00, 0xfc, 0xff, b'a', b'b', b'c'];
+        let mut output = Vec::new();
+        inflate(&stored, &mut output).unwrap();
+        assert_eq!(output, b"abc");
+    }
+}
@@ -0,0 +1,242 @@
+use std::{
+    fs::{self, File},
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use flate2::{write::DeflateEncoder, Compression};
+
+use crate::error::CatalogError;
+
+use super::{checksum, CompressionType, ATTRIBUTE_NAME_IS_UTF, SIGNATURE};
+
+/// CFDATA 块的最大解压大小
+const BLOCK_SIZE: usize = 32768;
+const HEADER_SIZE: u32 = 36;
+const FOLDER_SIZE: u32 = 8;
+const DATA_HEADER_SIZE: u32 = 8;
+const ATTRIBUTE_ARCHIVE: u16 = 0x0020;
+
+#[derive(Debug, Clone)]
+struct CabFile {
+    name: String,
+    data: Vec<u8>,
+    date: u16,
+    time: u16,
+}
+
+/// 由 1970 年以来的天数计算公历日期
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+/// 转换为 CAB 使用的 DOS 日期和时间 (UTC)
+pub(crate) fn dos_date_time(time: SystemTime) -> (u16, u16) {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0);
+    let (year, month, day) = civil_from_days(secs.div_euclid(86400));
+    let secs = secs.rem_euclid(86400);
+    let year = year.clamp(1980, 2107);
+    let date = (((year - 1980) << 9) | (month << 5) | day) as u16;
+    let time = (((secs / 3600) << 11) | (((secs / 60) % 60) << 5) | ((secs % 60) / 2)) as u16;
+    (date, time)
+}
+
+/// 生成单文件夹的 CAB 文件, 支持不压缩和 MSZIP
+#[derive(Debug, Clone)]
+pub struct CabinetBuilder {
+    compression: CompressionType,
+    files: Vec<CabFile>,
+}
+
+impl CabinetBuilder {
+    pub fn new(compression: CompressionType) -> Self {
+        CabinetBuilder {
+            compression,
+            files: Vec::new(),
+        }
+    }
+
+    pub fn add_file<S: Into<String>>(&mut self, name: S, data: Vec<u8>) -> &mut Self {
+        let (date, time) = dos_date_time(SystemTime::now());
+        self.files.push(CabFile {
+            name: name.into(),
+            data,
+            date,
+            time,
+        });
+        self
+    }
+
+    /// 加入磁盘上的文件, 使用其文件名和修改时间
+    pub fn add_path(&mut self, path: &Path) -> Result<&mut Self, CatalogError> {
+        let name = path
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .ok_or_else(|| CatalogError::SelectedFileError(format!("{:?}", path)))?;
+        let modified = fs::metadata(path)?.modified()?;
+        let (date, time) = dos_date_time(modified);
+        self.files.push(CabFile {
+            name,
+            data: fs::read(path)?,
+            date,
+            time,
+        });
+        Ok(self)
+    }
+
+    fn compress_block(&self, block: &[u8]) -> Result<Vec<u8>, CatalogError> {
+        match self.compression {
+            CompressionType::None => Ok(block.to_vec()),
+            CompressionType::MsZip => {
+                // 每块都是独立的 deflate 流, 不引用之前块的历史
+                let mut encoder = DeflateEncoder::new(b"CK".to_vec(), Compression::best());
+                encoder.write_all(block)?;
+                Ok(encoder.finish()?)
+            }
+            other => Err(CatalogError::SelectedFileError(format!(
+                "{:?} compression is not supported for writing",
+                other
+            ))),
+        }
+    }
+
+    pub fn write<W: Write>(&self, mut writer: W) -> Result<(), CatalogError> {
+        let folder_data: Vec<u8> = self.files.iter().flat_map(|f| f.data.clone()).collect();
+        let mut blocks = Vec::new();
+        for chunk in folder_data.chunks(BLOCK_SIZE) {
+            let compressed = self.compress_block(chunk)?;
+            if compressed.len() > u16::MAX as usize {
+                return Err(CatalogError::Unexpected);
+            }
+            blocks.push((compressed, chunk.len()));
+        }
+
+        let names: Vec<Vec<u8>> = self
+            .files
+            .iter()
+            .map(|f| f.name.clone().into_bytes())
+            .collect();
+        let files_size: u32 = names.iter().map(|n| 16 + n.len() as u32 + 1).sum();
+        let data_offset = HEADER_SIZE + FOLDER_SIZE + files_size;
+        let data_size: u32 = blocks
+            .iter()
+            .map(|(data, _)| DATA_HEADER_SIZE + data.len() as u32)
+            .sum();
+
+        let mut header = Vec::with_capacity(data_offset as usize);
+        header.extend_from_slice(SIGNATURE);
+        header.extend_from_slice(&0u32.to_le_bytes());
+        header.extend_from_slice(&(data_offset + data_size).to_le_bytes());
+        header.extend_from_slice(&0u32.to_le_bytes());
+        header.extend_from_slice(&(HEADER_SIZE + FOLDER_SIZE).to_le_bytes());
+        header.extend_from_slice(&0u32.to_le_bytes());
+        header.extend_from_slice(&[3, 1]);
+        header.extend_from_slice(&1u16.to_le_bytes());
+        header.extend_from_slice(&(self.files.len() as u16).to_le_bytes());
+        header.extend_from_slice(&0u16.to_le_bytes());
+        header.extend_from_slice(&0u16.to_le_bytes());
+        header.extend_from_slice(&0u16.to_le_bytes());
+
+        let compression: u16 = match self.compression {
+            CompressionType::MsZip => 1,
+            _ => 0,
+        };
+        header.extend_from_slice(&data_offset.to_le_bytes());
+        header.extend_from_slice(&(blocks.len() as u16).to_le_bytes());
+        header.extend_from_slice(&compression.to_le_bytes());
+
+        let mut offset = 0u32;
+        for (file, name) in self.files.iter().zip(names.iter()) {
+            let mut attributes = ATTRIBUTE_ARCHIVE;
+            if !file.name.is_ascii() {
+                attributes |= ATTRIBUTE_NAME_IS_UTF;
+            }
+            header.extend_from_slice(&(file.data.len() as u32).to_le_bytes());
+            header.extend_from_slice(&offset.to_le_bytes());
+            header.extend_from_slice(&0u16.to_le_bytes());
+            header.extend_from_slice(&file.date.to_le_bytes());
+            header.extend_from_slice(&file.time.to_le_bytes());
+            header.extend_from_slice(&attributes.to_le_bytes());
+            header.extend_from_slice(name);
+            header.push(0);
+            offset += file.data.len() as u32;
+        }
+        writer.write_all(&header)?;
+
+        for (data, uncompressed_size) in blocks.iter() {
+            let mut sizes = Vec::with_capacity(4);
+            sizes.extend_from_slice(&(data.len() as u16).to_le_bytes());
+            sizes.extend_from_slice(&(*uncompressed_size as u16).to_le_bytes());
+            let csum = checksum(&sizes, checksum(data, 0));
+            writer.write_all(&csum.to_le_bytes())?;
+            writer.write_all(&sizes)?;
+            writer.write_all(data)?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    pub fn write_to_path(&self, path: &Path) -> Result<PathBuf, CatalogError> {
+        let file = File::create(path)?;
+        self.write(BufWriter::new(file))?;
+        Ok(path.to_path_buf())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::super::Cabinet;
+    use super::*;
+
+    fn round_trip(compression: CompressionType) {
+        let large: Vec<u8> = (0..100_000u32)
+            .flat_map(|i| (i % 251).to_le_bytes())
+            .collect();
+        let mut builder = CabinetBuilder::new(compression);
+        builder
+            .add_file("small.xml", b"<Manifest />".to_vec())
+            .add_file("large.bin", large.clone());
+        let mut buffer = Vec::new();
+        builder.write(&mut buffer).unwrap();
+
+        let mut cabinet = Cabinet::new(Cursor::new(buffer)).unwrap();
+        assert_eq!(cabinet.compression(0), Some(compression));
+        assert_eq!(cabinet.entries().len(), 2);
+        assert_eq!(cabinet.read_entry("small.xml").unwrap(), b"<Manifest />");
+        assert_eq!(cabinet.read_entry("large.bin").unwrap(), large);
+    }
+
+    #[test]
+    fn test_round_trip_uncompressed() {
+        round_trip(CompressionType::None);
+    }
+
+    #[test]
+    fn test_round_trip_mszip() {
+        round_trip(CompressionType::MsZip);
+    }
+
+    #[test]
+    fn test_dos_date_time() {
+        // 2024-09-18 06:31:20 UTC
+        let time = UNIX_EPOCH + std::time::Duration::from_secs(1726641080);
+        let (date, time) = dos_date_time(time);
+        assert_eq!(date, (44 << 9) | (9 << 5) | 18);
+        assert_eq!(time, (6 << 11) | (31 << 5) | 10);
+    }
+}
@@ -0,0 +1,507 @@
+use std::{
+    fs::{self, File},
+    io::BufReader,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use serde::Serialize;
+use xml::reader::{EventReader, XmlEvent};
+
+use crate::{
+    detect::install_dirs,
+    error::CatalogError,
+    launcher::{launch_software, Launcher},
+    registry::RegistryBackend,
+    Software,
+};
+
+/// `/scan -report=<目录>` 生成的报告文件名
+pub const REPORT_FILE_NAME: &str = "DCUApplicableUpdates.xml";
+
+const DCU_CLI_NAME: &str = "dcu-cli.exe";
+
+/// 外部命令的执行结果
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CommandOutput {
+    /// 被信号终止时没有退出码
+    pub code: Option<i32>,
+    pub stdout: String,
+    pub stderr: String,
+}
+
+/// 执行外部命令的接口, 测试中可以换成假的 dcu-cli
+pub trait CommandRunner {
+    fn run(&mut self, program: &Path, args: &[String]) -> Result<CommandOutput, CatalogError>;
+}
+
+/// 直接启动进程并等待结束
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ProcessRunner;
+
+impl CommandRunner for ProcessRunner {
+    fn run(&mut self, program: &Path, args: &[String]) -> Result<CommandOutput, CatalogError> {
+        log::info!("run--{:?} {:?}", program, args);
+        let output = Command::new(program).args(args).output()?;
+        Ok(CommandOutput {
+            code: output.status.code(),
+            stdout: String::from_utf8_lossy(&output.stdout).into_owned(),
+            stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+        })
+    }
+}
+
+/// dcu-cli 的退出码, 见 Dell Command | Update 参考手册
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum DcuExitCode {
+    Success,
+    RebootRequired,
+    FatalError,
+    Error,
+    InvalidSystem,
+    RebootAndScanRequired,
+    AlreadyRunning,
+    UnsupportedSystem,
+    NoUpdateFilters,
+    /// 输入参数错误, 100-113
+    InvalidArguments(i32),
+    NoUpdatesFound,
+    ScanError,
+    ScanCancelled,
+    ScanDownloadError,
+    ApplyResultError,
+    ApplyCancelled,
+    ApplyDownloadError,
+    /// 服务相关的错误, 3000-3005
+    ServiceError(i32),
+    /// 进程被终止, 没有退出码
+    Terminated,
+    Other(i32),
+}
+
+impl DcuExitCode {
+    pub fn from_code(code: Option<i32>) -> Self {
+        let Some(code) = code else {
+            return DcuExitCode::Terminated;
+        };
+        match code {
+            0 => DcuExitCode::Success,
+            1 => DcuExitCode::RebootRequired,
+            2 => DcuExitCode::FatalError,
+            3 => DcuExitCode::Error,
+            4 => DcuExitCode::InvalidSystem,
+            5 => DcuExitCode::RebootAndScanRequired,
+            6 => DcuExitCode::AlreadyRunning,
+            7 => DcuExitCode::UnsupportedSystem,
+            8 => DcuExitCode::NoUpdateFilters,
+            100..=113 => DcuExitCode::InvalidArguments(code),
+            500 => DcuExitCode::NoUpdatesFound,
+            501 => DcuExitCode::ScanError,
+            502 => DcuExitCode::ScanCancelled,
+            503 => DcuExitCode::ScanDownloadError,
+            1000 => DcuExitCode::ApplyResultError,
+            1001 => DcuExitCode::ApplyCancelled,
+            1002 => DcuExitCode::ApplyDownloadError,
+            3000..=3005 => DcuExitCode::ServiceError(code),
+            _ => DcuExitCode::Other(code),
+        }
+    }
+
+    /// 命令正常完成, 包括需要重启和没有可用更新
+    pub fn is_success(&self) -> bool {
+        matches!(
+            self,
+            DcuExitCode::Success
+                | DcuExitCode::RebootRequired
+                | DcuExitCode::RebootAndScanRequired
+                | DcuExitCode::NoUpdatesFound
+        )
+    }
+
+    pub fn reboot_required(&self) -> bool {
+        matches!(
+            self,
+            DcuExitCode::RebootRequired | DcuExitCode::RebootAndScanRequired
+        )
+    }
+}
+
+/// 扫描报告中的一个可用更新
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct ApplicableUpdate {
+    /// 对应 catalog 中的 releaseID
+    pub release: String,
+    pub name: String,
+    pub version: String,
+    pub date: String,
+    pub urgency: String,
+    pub update_type: String,
+    pub category: String,
+    pub file: String,
+    pub size: Option<u64>,
+}
+
+impl ApplicableUpdate {
+    fn set(&mut self, field: &str, value: String) {
+        match field {
+            "release" => self.release = value,
+            "name" => self.name = value,
+            "version" => self.version = value,
+            "date" => self.date = value,
+            "urgency" => self.urgency = value,
+            "type" => self.update_type = value,
+            "category" => self.category = value,
+            "file" => self.file = value,
+            "bytes" | "size" => self.size = value.parse().ok(),
+            _ => {}
+        }
+    }
+}
+
+/// 解析 `DCUApplicableUpdates.xml`
+pub fn parse_report<R: std::io::Read>(reader: R) -> Result<Vec<ApplicableUpdate>, CatalogError> {
+    let mut updates = Vec::new();
+    let mut current: Option<ApplicableUpdate> = None;
+    let mut field: Option<String> = None;
+    let mut text = String::new();
+    for event in EventReader::new(reader) {
+        match event? {
+            XmlEvent::StartElement { name, .. } => {
+                if name.local_name == "update" {
+                    current = Some(ApplicableUpdate::default());
+                } else if current.is_some() {
+                    field = Some(name.local_name);
+                    text.clear();
+                }
+            }
+            XmlEvent::Characters(s) | XmlEvent::CData(s) => text.push_str(&s),
+            XmlEvent::EndElement { name } => {
+                if name.local_name == "update" {
+                    updates.extend(current.take());
+                } else if let (Some(update), Some(f)) = (current.as_mut(), field.take()) {
+                    update.set(&f, text.trim().to_string());
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(updates)
+}
+
+/// `/scan` 和 `/applyUpdates` 共用的参数
+#[derive(Debug, Clone, Default)]
+pub struct DcuOptions {
+    /// 使用指定的 catalog, 不给出时使用注册表中配置的 catalog
+    pub catalog: Option<PathBuf>,
+    /// 例如 `bios`, `driver`, `firmware`
+    pub update_types: Vec<String>,
+    /// 报告和日志所在的目录
+    pub output_dir: PathBuf,
+}
+
+impl DcuOptions {
+    fn args(&self, command: &str, log_name: &str) -> Vec<String> {
+        let mut args = vec![
+            command.to_string(),
+            "-silent".to_string(),
+            format!("-outputLog={}", self.output_dir.join(log_name).display()),
+        ];
+        if let Some(catalog) = &self.catalog {
+            args.push(format!("-catalogLocation={}", catalog.display()));
+        }
+        if !self.update_types.is_empty() {
+            args.push(format!("-updateType={}", self.update_types.join(",")));
+        }
+        args
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ScanResult {
+    pub exit_code: DcuExitCode,
+    pub updates: Vec<ApplicableUpdate>,
+    /// 没有可用更新时 dcu-cli 不生成报告
+    pub report: Option<PathBuf>,
+    pub log: PathBuf,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ApplyResult {
+    pub exit_code: DcuExitCode,
+    pub reboot_required: bool,
+    pub log: PathBuf,
+}
+
+/// 通过 dcu-cli 扫描和安装更新
+pub struct DcuCli<R: CommandRunner> {
+    program: PathBuf,
+    runner: R,
+}
+
+impl<R: CommandRunner> DcuCli<R> {
+    pub fn new(program: PathBuf, runner: R) -> Self {
+        DcuCli { program, runner }
+    }
+
+    /// 在 DCU 的安装目录中查找 dcu-cli.exe, 只支持 DCU
+    pub fn locate(
+        registry: &dyn RegistryBackend,
+        software: &Software,
+        runner: R,
+    ) -> Result<Self, CatalogError> {
+        if !matches!(software, Software::DellCommandUpdate { .. }) {
+            return Err(CatalogError::SelectedFileError(format!(
+                "dcu-cli is not available for {}",
+                software.app_name()
+            )));
+        }
+        let program = install_dirs(registry, software.kind())?
+            .into_iter()
+            .map(|dir| dir.join(DCU_CLI_NAME))
+            .find(|path| path.is_file())
+            .ok_or_else(|| CatalogError::CurrentFileError(DCU_CLI_NAME.into()))?;
+        Ok(DcuCli::new(program, runner))
+    }
+
+    pub fn program(&self) -> &Path {
+        &self.program
+    }
+
+    fn run(&mut self, args: Vec<String>) -> Result<DcuExitCode, CatalogError> {
+        let output = self.runner.run(&self.program, &args)?;
+        let exit_code = DcuExitCode::from_code(output.code);
+        log::debug!("dcu-cli--{:?}--{}", exit_code, output.stdout.trim());
+        Ok(exit_code)
+    }
+
+    pub fn scan(&mut self, options: &DcuOptions) -> Result<ScanResult, CatalogError> {
+        fs::create_dir_all(&options.output_dir)?;
+        let report_path = options.output_dir.join(REPORT_FILE_NAME);
+        // 旧报告会被误认为本次的结果
+        if report_path.is_file() {
+            fs::remove_file(&report_path)?;
+        }
+        let mut args = options.args("/scan", "scan.log");
+        args.push(format!("-report={}", options.output_dir.display()));
+        let exit_code = self.run(args)?;
+        let (updates, report) = if report_path.is_file() {
+            let file = BufReader::new(File::open(&report_path)?);
+            (parse_report(file)?, Some(report_path))
+        } else {
+            (Vec::new(), None)
+        };
+        Ok(ScanResult {
+            exit_code,
+            updates,
+            report,
+            log: options.output_dir.join("scan.log"),
+        })
+    }
+
+    /// 安装更新, 不自动重启
+    pub fn apply(&mut self, options: &DcuOptions) -> Result<ApplyResult, CatalogError> {
+        fs::create_dir_all(&options.output_dir)?;
+        let mut args = options.args("/applyUpdates", "apply.log");
+        args.push("-reboot=disable".to_string());
+        let exit_code = self.run(args)?;
+        Ok(ApplyResult {
+            exit_code,
+            reboot_required: exit_code.reboot_required(),
+            log: options.output_dir.join("apply.log"),
+        })
+    }
+}
+
+/// 注册后检查更新: DCU 用 dcu-cli 扫描, DU 或扫描失败时打开界面由用户点击检查
+///
+/// catalog 已经写入, 扫描和启动失败时只记录日志, 没有扫描结果时返回 `None`
+pub fn scan_or_launch<R: CommandRunner>(
+    dcu: Result<DcuCli<R>, CatalogError>,
+    options: &DcuOptions,
+    launcher: &mut dyn Launcher,
+    registry: &dyn RegistryBackend,
+    software: &Software,
+) -> Option<ScanResult> {
+    match dcu.and_then(|mut dcu| dcu.scan(options)) {
+        Ok(result) if result.exit_code.is_success() => return Some(result),
+        Ok(result) => log::warn!("scan_or_launch--{:?}", result.exit_code),
+        Err(e) => log::info!("scan_or_launch--{}", e),
+    }
+    if let Err(e) = launch_software(launcher, registry, software, false) {
+        log::warn!("scan_or_launch--{}", e);
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{launcher::LaunchTarget, registry::MemoryRegistry};
+
+    const REPORT: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<updates>
+  <update>
+    <release>MG49X</release>
+    <name>Dell Precision 3590 System BIOS</name>
+    <version>1.6.1</version>
+    <date>2024-09-10</date>
+    <urgency>Urgent</urgency>
+    <type>BIOS</type>
+    <category>BIOS</category>
+    <file>Precision_3590_1.6.1.exe</file>
+    <bytes>31285016</bytes>
+  </update>
+</updates>"#;
+
+    /// 记录参数并按预设的退出码返回, `/scan` 时写入报告
+    struct FakeRunner {
+        code: i32,
+        args: Vec<Vec<String>>,
+    }
+
+    impl CommandRunner for FakeRunner {
+        fn run(&mut self, _: &Path, args: &[String]) -> Result<CommandOutput, CatalogError> {
+            self.args.push(args.to_vec());
+            if let Some(dir) = args.iter().find_map(|a| a.strip_prefix("-report=")) {
+                fs::write(Path::new(dir).join(REPORT_FILE_NAME), REPORT)?;
+            }
+            Ok(CommandOutput {
+                code: Some(self.code),
+                ..Default::default()
+            })
+        }
+    }
+
+    #[test]
+    fn test_parse_report() {
+        let updates = parse_report(REPORT.as_bytes()).unwrap();
+        assert_eq!(updates.len(), 1);
+        assert_eq!(updates[0].release, "MG49X");
+        assert_eq!(updates[0].update_type, "BIOS");
+        assert_eq!(updates[0].size, Some(31285016));
+        assert!(parse_report("<updates/>".as_bytes()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_scan_and_apply() {
+        let dir = tempfile::tempdir().unwrap();
+        let options = DcuOptions {
+            catalog: Some(PathBuf::from(r"C:\Catalogs\_Precision_0CBB.xml")),
+            update_types: vec!["bios".into(), "driver".into()],
+            output_dir: dir.path().to_path_buf(),
+        };
+        let runner = FakeRunner {
+            code: 0,
+            args: Vec::new(),
+        };
+        let mut dcu = DcuCli::new(PathBuf::from("dcu-cli.exe"), runner);
+        let result = dcu.scan(&options).unwrap();
+        assert_eq!(result.exit_code, DcuExitCode::Success);
+        assert_eq!(result.updates[0].name, "Dell Precision 3590 System BIOS");
+        assert_eq!(result.report, Some(dir.path().join(REPORT_FILE_NAME)));
+        let args = &dcu.runner.args[0];
+        assert_eq!(args[0], "/scan");
+        assert!(args.contains(&r"-catalogLocation=C:\Catalogs\_Precision_0CBB.xml".to_string()));
+        assert!(args.contains(&"-updateType=bios,driver".to_string()));
+
+        dcu.runner.code = 1;
+        let result = dcu.apply(&options).unwrap();
+        assert!(result.exit_code.is_success());
+        assert!(result.reboot_required);
+        assert!(dcu.runner.args[1].contains(&"-reboot=disable".to_string()));
+    }
+
+    /// 记录启动方式, 总是成功
+    #[derive(Default)]
+    struct RecordingLauncher {
+        launched: Vec<LaunchTarget>,
+    }
+
+    impl Launcher for RecordingLauncher {
+        fn launch(&mut self, target: &LaunchTarget) -> Result<(), CatalogError> {
+            self.launched.push(target.clone());
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_scan_or_launch() {
+        let dir = tempfile::tempdir().unwrap();
+        let options = DcuOptions {
+            output_dir: dir.path().to_path_buf(),
+            ..Default::default()
+        };
+        let registry = MemoryRegistry::new();
+        let dcu = Software::DellCommandUpdate {
+            app_name: "Dell Command Update".into(),
+        };
+        let cli = |code| {
+            Ok(DcuCli::new(
+                PathBuf::from("dcu-cli.exe"),
+                FakeRunner {
+                    code,
+                    args: Vec::new(),
+                },
+            ))
+        };
+
+        let mut launcher = RecordingLauncher::default();
+        let result = scan_or_launch(cli(500), &options, &mut launcher, &registry, &dcu).unwrap();
+        assert_eq!(result.exit_code, DcuExitCode::NoUpdatesFound);
+        assert!(launcher.launched.is_empty());
+
+        // 扫描出错时打开界面
+        let result = scan_or_launch(cli(3001), &options, &mut launcher, &registry, &dcu);
+        assert_eq!(result, None);
+        assert_eq!(launcher.launched.len(), 1);
+
+        // DU 没有 dcu-cli
+        let du = Software::DellUpdate {
+            app_name: "Dell Update".into(),
+        };
+        let located = DcuCli::locate(&registry, &du, ProcessRunner);
+        assert!(scan_or_launch(located, &options, &mut launcher, &registry, &du).is_none());
+        assert_eq!(launcher.launched.len(), 2);
+    }
+
+    #[test]
+    fn test_exit_codes() {
+        assert_eq!(
+            DcuExitCode::from_code(Some(500)),
+            DcuExitCode::NoUpdatesFound
+        );
+        assert!(DcuExitCode::from_code(Some(500)).is_success());
+        assert_eq!(
+            DcuExitCode::from_code(Some(106)),
+            DcuExitCode::InvalidArguments(106)
+        );
+        assert!(!DcuExitCode::from_code(Some(3001)).is_success());
+        assert_eq!(DcuExitCode::from_code(None), DcuExitCode::Terminated);
+    }
+
+    /// 用 shell 脚本冒充 dcu-cli, 检查真实进程的参数和退出码
+    #[cfg(unix)]
+    #[test]
+    fn test_fake_dcu_cli_script() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let script = dir.path().join("dcu-cli");
+        fs::write(
+            &script,
+            "#!/bin/sh\nfor arg in \"$@\"; do\n  case \"$arg\" in\n    -report=*) printf '<updates/>' > \"${arg#-report=}/DCUApplicableUpdates.xml\" ;;\n  esac\ndone\nexit 1\n",
+        )
+        .unwrap();
+        fs::set_permissions(&script, fs::Permissions::from_mode(0o755)).unwrap();
+
+        let options = DcuOptions {
+            output_dir: dir.path().join("report"),
+            ..Default::default()
+        };
+        let result = DcuCli::new(script, ProcessRunner).scan(&options).unwrap();
+        // unix 的退出码只有 8 位, 脚本无法返回 500
+        assert_eq!(result.exit_code, DcuExitCode::RebootRequired);
+        assert!(result.updates.is_empty());
+        assert!(result.report.is_some());
+    }
+}
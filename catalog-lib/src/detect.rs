@@ -0,0 +1,630 @@
+use std::{fmt, path::PathBuf, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{error::CatalogError, registry::RegistryBackend, Software};
+
+/// 已安装程序的卸载信息, 64 位和 32 位各一份
+pub(crate) const UNINSTALL_KEYS: [&str; 2] = [
+    r"SOFTWARE\Microsoft\Windows\CurrentVersion\Uninstall",
+    r"SOFTWARE\WOW6432Node\Microsoft\Windows\CurrentVersion\Uninstall",
+];
+
+/// DU 和 DCU 共用的后台服务, 负责读取 catalog
+pub const SERVICE_NAME: &str = "DellClientManagementService";
+
+/// Dell 的更新客户端
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum ClientKind {
+    DellCommandUpdate,
+    DellUpdate,
+}
+
+impl ClientKind {
+    /// 都安装时默认优先使用 DCU
+    pub const ALL: [ClientKind; 2] = [ClientKind::DellCommandUpdate, ClientKind::DellUpdate];
+
+    pub fn software(&self) -> Software {
+        let app_name = self.to_string();
+        match self {
+            ClientKind::DellCommandUpdate => Software::DellCommandUpdate { app_name },
+            ClientKind::DellUpdate => Software::DellUpdate { app_name },
+        }
+    }
+}
+
+impl fmt::Display for ClientKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ClientKind::DellCommandUpdate => "Dell Command Update",
+            ClientKind::DellUpdate => "Dell Update",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for ClientKind {
+    type Err = CatalogError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().replace([' ', '-', '_', '|'], "").as_str() {
+            "dcu" | "dellcommandupdate" => Ok(ClientKind::DellCommandUpdate),
+            "du" | "dellupdate" => Ok(ClientKind::DellUpdate),
+            _ => Err(CatalogError::Config(format!("unknown client {}", s))),
+        }
+    }
+}
+
+/// 客户端的安装信息
+pub(crate) struct ClientInfo {
+    /// 卸载信息中 DisplayName 的前缀
+    pub display_name: &'static str,
+    pub exe_name: &'static str,
+    pub install_dirs: [&'static str; 2],
+    pub aumid: &'static str,
+    /// 支持 CustomCatalogPaths 的最低版本
+    pub min_version: &'static str,
+}
+
+pub(crate) fn client_info(kind: ClientKind) -> ClientInfo {
+    match kind {
+        ClientKind::DellCommandUpdate => ClientInfo {
+            display_name: "Dell Command | Update",
+            exe_name: "DellCommandUpdate.exe",
+            install_dirs: [
+                r"C:\Program Files\Dell\CommandUpdate",
+                r"C:\Program Files (x86)\Dell\CommandUpdate",
+            ],
+            aumid: "DellInc.DellCommandUpdate_htrsf667h5kn2!App",
+            min_version: "3.0",
+        },
+        ClientKind::DellUpdate => ClientInfo {
+            display_name: "Dell Update",
+            exe_name: "DellUpdate.exe",
+            install_dirs: [
+                r"C:\Program Files (x86)\Dell\Update",
+                r"C:\Program Files\Dell\Update",
+            ],
+            aumid: "DellInc.DellUpdate_htrsf667h5kn2!App",
+            min_version: "3.0",
+        },
+    }
+}
+
+/// 卸载信息中的一项
+struct UninstallEntry {
+    version: Option<String>,
+    install_location: Option<PathBuf>,
+}
+
+fn non_empty(value: Option<crate::registry::RegValue>) -> Option<String> {
+    value
+        .as_ref()
+        .and_then(|v| v.as_str())
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(String::from)
+}
+
+/// DisplayName 以 `display_name` 开头的卸载信息
+fn uninstall_entries(
+    registry: &dyn RegistryBackend,
+    display_name: &str,
+) -> Result<Vec<UninstallEntry>, CatalogError> {
+    let mut entries = Vec::new();
+    for uninstall_key in UNINSTALL_KEYS {
+        for subkey in registry.subkeys(uninstall_key)? {
+            let key = format!(r"{}\{}", uninstall_key, subkey);
+            if !non_empty(registry.read_value(&key, "DisplayName")?)
+                .is_some_and(|n| n.starts_with(display_name))
+            {
+                continue;
+            }
+            entries.push(UninstallEntry {
+                version: non_empty(registry.read_value(&key, "DisplayVersion")?),
+                install_location: non_empty(registry.read_value(&key, "InstallLocation")?)
+                    .map(PathBuf::from),
+            });
+        }
+    }
+    Ok(entries)
+}
+
+/// 可能的安装目录, 卸载信息中记录的在前, 不检查是否存在
+pub(crate) fn install_dirs(
+    registry: &dyn RegistryBackend,
+    kind: ClientKind,
+) -> Result<Vec<PathBuf>, CatalogError> {
+    let info = client_info(kind);
+    let mut dirs: Vec<PathBuf> = uninstall_entries(registry, info.display_name)?
+        .into_iter()
+        .filter_map(|e| e.install_location)
+        .collect();
+    for dir in info.install_dirs.iter().map(PathBuf::from) {
+        if !dirs.contains(&dir) {
+            dirs.push(dir);
+        }
+    }
+    Ok(dirs)
+}
+
+/// 按数字逐段比较版本号, 非数字部分忽略
+pub fn compare_versions(a: &str, b: &str) -> std::cmp::Ordering {
+    let parse = |v: &str| -> Vec<u64> {
+        v.split(['.', '-', ' '])
+            .map(|part| {
+                part.chars()
+                    .take_while(char::is_ascii_digit)
+                    .collect::<String>()
+                    .parse()
+                    .unwrap_or(0)
+            })
+            .collect()
+    };
+    let (a, b) = (parse(a), parse(b));
+    for i in 0..a.len().max(b.len()) {
+        let ordering = a.get(i).unwrap_or(&0).cmp(b.get(i).unwrap_or(&0));
+        if ordering.is_ne() {
+            return ordering;
+        }
+    }
+    std::cmp::Ordering::Equal
+}
+
+/// Windows 服务的状态
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ServiceState {
+    Running,
+    Stopped,
+    /// 正在启动, 停止, 暂停等中间状态
+    Pending,
+    NotInstalled,
+    /// 无法查询, 例如不在 Windows 上
+    Unknown,
+}
+
+/// 查询服务状态的接口, 测试中可以替换
+pub trait ServiceQuery {
+    fn state(&self, name: &str) -> Result<ServiceState, CatalogError>;
+}
+
+/// 启动和停止服务, 都会等到服务进入目标状态
+pub trait ServiceControl: ServiceQuery {
+    fn stop(&mut self, name: &str) -> Result<(), CatalogError>;
+
+    fn start(&mut self, name: &str) -> Result<(), CatalogError>;
+}
+
+/// 通过服务控制管理器查询和控制, 非 Windows 平台总是 `Unknown`, 控制时什么也不做
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemServices;
+
+#[cfg(windows)]
+mod win {
+    use std::{thread, time::Duration};
+
+    use windows::{
+        core::PCWSTR,
+        Win32::{
+            Foundation::ERROR_SERVICE_DOES_NOT_EXIST,
+            System::Services::{
+                CloseServiceHandle, ControlService, OpenSCManagerW, OpenServiceW,
+                QueryServiceStatus, StartServiceW, SC_HANDLE, SC_MANAGER_CONNECT,
+                SERVICE_CONTINUE_PENDING, SERVICE_CONTROL_STOP, SERVICE_PAUSE_PENDING,
+                SERVICE_QUERY_STATUS, SERVICE_RUNNING, SERVICE_START, SERVICE_START_PENDING,
+                SERVICE_STATUS, SERVICE_STATUS_CURRENT_STATE, SERVICE_STOP, SERVICE_STOPPED,
+                SERVICE_STOP_PENDING,
+            },
+        },
+    };
+
+    use super::ServiceState;
+    use crate::error::CatalogError;
+
+    /// 等待服务进入目标状态的最长时间
+    const WAIT_TIMEOUT: Duration = Duration::from_secs(30);
+
+    /// 打开的服务句柄, 离开作用域时关闭
+    struct ServiceHandle(SC_HANDLE);
+
+    impl Drop for ServiceHandle {
+        fn drop(&mut self) {
+            unsafe {
+                let _ = CloseServiceHandle(self.0);
+            }
+        }
+    }
+
+    /// 服务不存在时返回 `None`
+    fn open(name: &str, access: u32) -> Result<Option<ServiceHandle>, CatalogError> {
+        let service_name: Vec<u16> = name.encode_utf16().chain(std::iter::once(0)).collect();
+        unsafe {
+            let manager = ServiceHandle(OpenSCManagerW(
+                PCWSTR::null(),
+                PCWSTR::null(),
+                SC_MANAGER_CONNECT,
+            )?);
+            match OpenServiceW(manager.0, PCWSTR::from_raw(service_name.as_ptr()), access) {
+                Ok(service) => Ok(Some(ServiceHandle(service))),
+                Err(e) if e.code() == ERROR_SERVICE_DOES_NOT_EXIST.to_hresult() => Ok(None),
+                Err(e) => Err(e.into()),
+            }
+        }
+    }
+
+    fn current_state(
+        service: &ServiceHandle,
+    ) -> Result<SERVICE_STATUS_CURRENT_STATE, CatalogError> {
+        let mut status = SERVICE_STATUS::default();
+        unsafe { QueryServiceStatus(service.0, &mut status)? };
+        Ok(status.dwCurrentState)
+    }
+
+    fn wait_for(
+        service: &ServiceHandle,
+        name: &str,
+        target: SERVICE_STATUS_CURRENT_STATE,
+    ) -> Result<(), CatalogError> {
+        let step = Duration::from_millis(500);
+        let mut waited = Duration::ZERO;
+        while current_state(service)? != target {
+            if waited >= WAIT_TIMEOUT {
+                return Err(CatalogError::InstallError(format!(
+                    "service {} did not reach state {} in time",
+                    name, target.0
+                )));
+            }
+            thread::sleep(step);
+            waited += step;
+        }
+        Ok(())
+    }
+
+    pub fn state(name: &str) -> Result<ServiceState, CatalogError> {
+        let Some(service) = open(name, SERVICE_QUERY_STATUS)? else {
+            return Ok(ServiceState::NotInstalled);
+        };
+        Ok(match current_state(&service)? {
+            SERVICE_RUNNING => ServiceState::Running,
+            SERVICE_STOPPED => ServiceState::Stopped,
+            _ => ServiceState::Pending,
+        })
+    }
+
+    /// 等到服务离开启动, 继续, 暂停等中间状态, 这些状态下不接受停止请求
+    fn wait_settled(
+        service: &ServiceHandle,
+        name: &str,
+    ) -> Result<SERVICE_STATUS_CURRENT_STATE, CatalogError> {
+        let step = Duration::from_millis(500);
+        let mut waited = Duration::ZERO;
+        loop {
+            let state = current_state(service)?;
+            if ![
+                SERVICE_START_PENDING,
+                SERVICE_CONTINUE_PENDING,
+                SERVICE_PAUSE_PENDING,
+            ]
+            .contains(&state)
+            {
+                return Ok(state);
+            }
+            if waited >= WAIT_TIMEOUT {
+                return Err(CatalogError::InstallError(format!(
+                    "service {} stayed in state {}",
+                    name, state.0
+                )));
+            }
+            thread::sleep(step);
+            waited += step;
+        }
+    }
+
+    pub fn stop(name: &str) -> Result<(), CatalogError> {
+        let Some(service) = open(name, SERVICE_STOP | SERVICE_QUERY_STATUS)? else {
+            return Ok(());
+        };
+        let state = wait_settled(&service, name)?;
+        // 正在停止时不需要再次请求
+        if state != SERVICE_STOPPED && state != SERVICE_STOP_PENDING {
+            let mut status = SERVICE_STATUS::default();
+            unsafe { ControlService(service.0, SERVICE_CONTROL_STOP, &mut status)? };
+        }
+        wait_for(&service, name, SERVICE_STOPPED)
+    }
+
+    pub fn start(name: &str) -> Result<(), CatalogError> {
+        let service = open(name, SERVICE_START | SERVICE_QUERY_STATUS)?
+            .ok_or_else(|| CatalogError::CurrentFileError(format!("service {}", name)))?;
+        if current_state(&service)? != SERVICE_RUNNING {
+            unsafe { StartServiceW(service.0, None)? };
+        }
+        wait_for(&service, name, SERVICE_RUNNING)
+    }
+}
+
+impl ServiceQuery for SystemServices {
+    #[cfg(windows)]
+    fn state(&self, name: &str) -> Result<ServiceState, CatalogError> {
+        win::state(name)
+    }
+
+    #[cfg(not(windows))]
+    fn state(&self, _name: &str) -> Result<ServiceState, CatalogError> {
+        Ok(ServiceState::Unknown)
+    }
+}
+
+impl ServiceControl for SystemServices {
+    #[cfg(windows)]
+    fn stop(&mut self, name: &str) -> Result<(), CatalogError> {
+        log::info!("stop service--{}", name);
+        win::stop(name)
+    }
+
+    #[cfg(windows)]
+    fn start(&mut self, name: &str) -> Result<(), CatalogError> {
+        log::info!("start service--{}", name);
+        win::start(name)
+    }
+
+    #[cfg(not(windows))]
+    fn stop(&mut self, _name: &str) -> Result<(), CatalogError> {
+        Ok(())
+    }
+
+    #[cfg(not(windows))]
+    fn start(&mut self, _name: &str) -> Result<(), CatalogError> {
+        Ok(())
+    }
+}
+
+/// 一个已安装的客户端
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct InstalledClient {
+    pub kind: ClientKind,
+    pub version: Option<String>,
+    pub install_dir: Option<PathBuf>,
+    pub service: ServiceState,
+    /// 版本未知时认为支持
+    pub supports_custom_catalog: bool,
+}
+
+impl InstalledClient {
+    pub fn software(&self) -> Software {
+        self.kind.software()
+    }
+
+    /// 检查能否用于安装 catalog, 不能时说明原因
+    pub fn check(&self) -> Result<(), CatalogError> {
+        let reject = |reason: String| CatalogError::ClientRejected {
+            client: self.kind.to_string(),
+            reason,
+        };
+        if !self.supports_custom_catalog {
+            return Err(reject(format!(
+                "version {} is older than {}, which is required for custom catalogs",
+                self.version.as_deref().unwrap_or_default(),
+                client_info(self.kind).min_version
+            )));
+        }
+        match self.service {
+            ServiceState::Running | ServiceState::Unknown => Ok(()),
+            state => Err(reject(format!(
+                "service {} is not running ({:?})",
+                SERVICE_NAME, state
+            ))),
+        }
+    }
+}
+
+/// 列出所有已安装的客户端, Clients 下有对应的键或者有卸载信息都算安装
+pub fn detect_clients(
+    registry: &dyn RegistryBackend,
+    services: &dyn ServiceQuery,
+) -> Result<Vec<InstalledClient>, CatalogError> {
+    let mut clients = Vec::new();
+    let mut service: Option<ServiceState> = None;
+    for kind in ClientKind::ALL {
+        let info = client_info(kind);
+        let entries = uninstall_entries(registry, info.display_name)?;
+        if entries.is_empty() && !registry.key_exists(kind.software().client_key())? {
+            continue;
+        }
+        let version = entries.iter().find_map(|e| e.version.clone());
+        let install_dir = entries
+            .iter()
+            .find_map(|e| e.install_location.clone())
+            .or_else(|| {
+                info.install_dirs
+                    .iter()
+                    .map(PathBuf::from)
+                    .find(|dir| dir.is_dir())
+            });
+        let supports_custom_catalog = version
+            .as_deref()
+            .is_none_or(|v| compare_versions(v, info.min_version).is_ge());
+        let service = match service {
+            Some(state) => state,
+            None => *service.insert(services.state(SERVICE_NAME)?),
+        };
+        clients.push(InstalledClient {
+            kind,
+            version,
+            install_dir,
+            service,
+            supports_custom_catalog,
+        });
+    }
+    log::debug!("detect_clients--{:?}", clients);
+    Ok(clients)
+}
+
+/// 选择要使用的客户端: 指定了 `preferred` 时只用它, 否则按 DCU, DU 的顺序选第一个可用的
+pub fn select_client(
+    clients: Vec<InstalledClient>,
+    preferred: Option<ClientKind>,
+) -> Result<InstalledClient, CatalogError> {
+    if clients.is_empty() {
+        return Err(CatalogError::NoClientInstalled);
+    }
+    if let Some(kind) = preferred {
+        let client = clients
+            .into_iter()
+            .find(|c| c.kind == kind)
+            .ok_or_else(|| CatalogError::ClientRejected {
+                client: kind.to_string(),
+                reason: "it is not installed".into(),
+            })?;
+        client.check()?;
+        return Ok(client);
+    }
+    let mut errors = Vec::new();
+    for client in clients {
+        match client.check() {
+            Ok(()) => return Ok(client),
+            Err(e) => errors.push(e),
+        }
+    }
+    // 都不可用时只有一个就直接返回它的原因
+    if errors.len() == 1 {
+        return Err(errors.remove(0));
+    }
+    Err(CatalogError::ClientRejected {
+        client: "Dell Update and Dell Command Update".into(),
+        reason: errors
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join("; "),
+    })
+}
+
+/// 检测并选择客户端
+pub fn choose_client(
+    registry: &dyn RegistryBackend,
+    services: &dyn ServiceQuery,
+    preferred: Option<ClientKind>,
+) -> Result<InstalledClient, CatalogError> {
+    select_client(detect_clients(registry, services)?, preferred)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cmp::Ordering;
+
+    use super::*;
+    use crate::registry::{MemoryRegistry, RegValue};
+
+    struct FixedServices(ServiceState);
+
+    impl ServiceQuery for FixedServices {
+        fn state(&self, _name: &str) -> Result<ServiceState, CatalogError> {
+            Ok(self.0)
+        }
+    }
+
+    fn install(registry: &mut MemoryRegistry, id: &str, name: &str, version: &str) {
+        let key = format!(r"{}\{}", UNINSTALL_KEYS[0], id);
+        registry
+            .write_value(&key, "DisplayName", &RegValue::Sz(name.into()))
+            .unwrap();
+        registry
+            .write_value(&key, "DisplayVersion", &RegValue::Sz(version.into()))
+            .unwrap();
+        registry
+            .write_value(
+                &key,
+                "InstallLocation",
+                &RegValue::Sz(format!(r"D:\{}", id)),
+            )
+            .unwrap();
+    }
+
+    #[test]
+    fn test_compare_versions() {
+        assert_eq!(compare_versions("5.4.0", "3.0"), Ordering::Greater);
+        assert_eq!(compare_versions("2.4.1.23", "3.0"), Ordering::Less);
+        assert_eq!(compare_versions("3.0.0", "3"), Ordering::Equal);
+        assert_eq!(compare_versions("4.10", "4.9"), Ordering::Greater);
+    }
+
+    #[test]
+    fn test_detect_clients() {
+        let mut registry = MemoryRegistry::new();
+        install(
+            &mut registry,
+            "DCU",
+            "Dell Command | Update for Windows Universal",
+            "5.4.0",
+        );
+        registry
+            .create_key(ClientKind::DellUpdate.software().client_key())
+            .unwrap();
+        let clients = detect_clients(&registry, &FixedServices(ServiceState::Running)).unwrap();
+        assert_eq!(clients.len(), 2);
+        assert_eq!(clients[0].kind, ClientKind::DellCommandUpdate);
+        assert_eq!(clients[0].version.as_deref(), Some("5.4.0"));
+        assert_eq!(clients[0].install_dir, Some(PathBuf::from(r"D:\DCU")));
+        assert!(clients[0].supports_custom_catalog);
+        assert_eq!(clients[1].kind, ClientKind::DellUpdate);
+        assert_eq!(clients[1].version, None);
+
+        let chosen = select_client(clients.clone(), None).unwrap();
+        assert_eq!(chosen.kind, ClientKind::DellCommandUpdate);
+        let chosen = select_client(clients, Some(ClientKind::DellUpdate)).unwrap();
+        assert_eq!(chosen.kind, ClientKind::DellUpdate);
+
+        assert!(matches!(
+            choose_client(
+                &MemoryRegistry::new(),
+                &FixedServices(ServiceState::Running),
+                None
+            ),
+            Err(CatalogError::NoClientInstalled)
+        ));
+    }
+
+    #[test]
+    fn test_reject_clients() {
+        let mut registry = MemoryRegistry::new();
+        install(&mut registry, "DCU", "Dell Command | Update", "2.4.0");
+        install(&mut registry, "DU", "Dell Update", "4.9.0");
+
+        // DCU 太旧, 自动选择时退到 DU
+        let running = FixedServices(ServiceState::Running);
+        let chosen = choose_client(&registry, &running, None).unwrap();
+        assert_eq!(chosen.kind, ClientKind::DellUpdate);
+        let error = choose_client(&registry, &running, Some(ClientKind::DellCommandUpdate))
+            .unwrap_err()
+            .to_string();
+        assert!(
+            error.contains("version 2.4.0 is older than 3.0"),
+            "{}",
+            error
+        );
+
+        let stopped = FixedServices(ServiceState::Stopped);
+        let error = choose_client(&registry, &stopped, Some(ClientKind::DellUpdate))
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("is not running"), "{}", error);
+        assert!(matches!(
+            choose_client(&registry, &stopped, None),
+            Err(CatalogError::ClientRejected { .. })
+        ));
+    }
+
+    #[test]
+    fn test_client_kind_from_str() {
+        assert_eq!(
+            "dcu".parse::<ClientKind>().unwrap(),
+            ClientKind::DellCommandUpdate
+        );
+        assert_eq!(
+            "Dell Update".parse::<ClientKind>().unwrap(),
+            ClientKind::DellUpdate
+        );
+        assert!("other".parse::<ClientKind>().is_err());
+    }
+}
@@ -0,0 +1,419 @@
+use std::{collections::BTreeSet, fmt::Write};
+
+use serde::Serialize;
+
+use crate::manifest::{Manifest, SoftwareComponent};
+
+/// 组件的简要信息
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ComponentSummary {
+    pub package_id: String,
+    pub name: String,
+    pub dell_version: String,
+    pub path: String,
+}
+
+impl From<&SoftwareComponent> for ComponentSummary {
+    fn from(component: &SoftwareComponent) -> Self {
+        ComponentSummary {
+            package_id: component.package_id.clone(),
+            name: component.display_name().to_string(),
+            dell_version: component.dell_version.clone(),
+            path: component.path.clone(),
+        }
+    }
+}
+
+/// 新旧组件是怎样对应起来的
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum MatchedBy {
+    PackageId,
+    Identifier,
+    /// 支持的设备中有相同的 componentID
+    ComponentId,
+}
+
+/// 一组值中新增和删除的部分
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct SetChange {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+}
+
+impl SetChange {
+    fn new<'a>(
+        old: impl IntoIterator<Item = &'a str>,
+        new: impl IntoIterator<Item = &'a str>,
+    ) -> Self {
+        let old: BTreeSet<&str> = old.into_iter().collect();
+        let new: BTreeSet<&str> = new.into_iter().collect();
+        SetChange {
+            added: new.difference(&old).map(|s| s.to_string()).collect(),
+            removed: old.difference(&new).map(|s| s.to_string()).collect(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty()
+    }
+}
+
+/// 同一算法的哈希值变化, 新增或删除的算法一侧为空
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct HashChange {
+    pub algorithm: String,
+    pub old: Option<String>,
+    pub new: Option<String>,
+}
+
+/// 两个 catalog 中对应组件的变化
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ComponentChange {
+    pub package_id: String,
+    /// packageID 不同时旧组件的 packageID
+    pub old_package_id: Option<String>,
+    pub name: String,
+    pub matched_by: MatchedBy,
+    pub old_version: String,
+    pub new_version: String,
+    pub systems: SetChange,
+    pub os_codes: SetChange,
+    pub hashes: Vec<HashChange>,
+}
+
+impl ComponentChange {
+    fn new(old: &SoftwareComponent, new: &SoftwareComponent, matched_by: MatchedBy) -> Self {
+        let hashes = |component: &SoftwareComponent| -> Vec<(String, String)> {
+            component
+                .cryptography
+                .iter()
+                .flat_map(|c| c.hashes.iter())
+                .map(|hash| (hash.algorithm.to_lowercase(), hash.value.clone()))
+                .collect()
+        };
+        let (old_hashes, new_hashes) = (hashes(old), hashes(new));
+        let algorithms: BTreeSet<&str> = old_hashes
+            .iter()
+            .chain(new_hashes.iter())
+            .map(|(algorithm, _)| algorithm.as_str())
+            .collect();
+        let find = |hashes: &[(String, String)], algorithm: &str| {
+            hashes
+                .iter()
+                .find(|(a, _)| a == algorithm)
+                .map(|(_, value)| value.clone())
+        };
+        ComponentChange {
+            package_id: new.package_id.clone(),
+            old_package_id: (old.package_id != new.package_id).then(|| old.package_id.clone()),
+            name: new.display_name().to_string(),
+            matched_by,
+            old_version: old.dell_version.clone(),
+            new_version: new.dell_version.clone(),
+            systems: SetChange::new(old.system_ids(), new.system_ids()),
+            os_codes: SetChange::new(old.os_codes(), new.os_codes()),
+            hashes: algorithms
+                .into_iter()
+                .map(|algorithm| HashChange {
+                    algorithm: algorithm.to_string(),
+                    old: find(&old_hashes, algorithm),
+                    new: find(&new_hashes, algorithm),
+                })
+                .filter(|change| change.old != change.new)
+                .collect(),
+        }
+    }
+
+    fn detail_lines(&self) -> Vec<String> {
+        let mut lines = Vec::new();
+        if let Some(old_package_id) = &self.old_package_id {
+            lines.push(format!(
+                "packageID: {} -> {} (matched by {:?})",
+                old_package_id, self.package_id, self.matched_by
+            ));
+        }
+        if self.version_changed() {
+            lines.push(format!(
+                "dellVersion: {} -> {}",
+                self.old_version, self.new_version
+            ));
+        }
+        for (title, change) in [("systems", &self.systems), ("OS", &self.os_codes)] {
+            if !change.added.is_empty() {
+                lines.push(format!("{} added: {}", title, change.added.join(", ")));
+            }
+            if !change.removed.is_empty() {
+                lines.push(format!("{} removed: {}", title, change.removed.join(", ")));
+            }
+        }
+        for hash in &self.hashes {
+            lines.push(format!(
+                "{}: {} -> {}",
+                hash.algorithm,
+                hash.old.as_deref().unwrap_or("-"),
+                hash.new.as_deref().unwrap_or("-")
+            ));
+        }
+        lines
+    }
+
+    pub fn version_changed(&self) -> bool {
+        self.old_version != self.new_version
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.old_package_id.is_none()
+            && !self.version_changed()
+            && self.systems.is_empty()
+            && self.os_codes.is_empty()
+            && self.hashes.is_empty()
+    }
+}
+
+/// 两个 catalog 之间的组件差异
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct CatalogDiff {
+    pub old_version: String,
+    pub new_version: String,
+    pub added: Vec<ComponentSummary>,
+    pub removed: Vec<ComponentSummary>,
+    pub updated: Vec<ComponentChange>,
+}
+
+impl CatalogDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.updated.is_empty()
+    }
+
+    /// 适合在终端中阅读的文本
+    pub fn to_text(&self) -> String {
+        let mut out = String::new();
+        let _ = writeln!(out, "Catalog {} -> {}", self.old_version, self.new_version);
+        if self.is_empty() {
+            let _ = writeln!(out, "No changes");
+            return out;
+        }
+        for (title, components) in [("Added", &self.added), ("Removed", &self.removed)] {
+            if components.is_empty() {
+                continue;
+            }
+            let _ = writeln!(out, "\n{} ({}):", title, components.len());
+            for c in components {
+                let _ = writeln!(out, "  {} {} {}", c.package_id, c.name, c.dell_version);
+            }
+        }
+        if !self.updated.is_empty() {
+            let _ = writeln!(out, "\nUpdated ({}):", self.updated.len());
+            for change in &self.updated {
+                let _ = writeln!(out, "  {} {}", change.package_id, change.name);
+                for line in change.detail_lines() {
+                    let _ = writeln!(out, "    {}", line);
+                }
+            }
+        }
+        out
+    }
+
+    /// 可以直接放进发布说明的 Markdown
+    pub fn to_markdown(&self) -> String {
+        let mut out = String::new();
+        let _ = writeln!(
+            out,
+            "## Catalog {} → {}\n",
+            self.old_version, self.new_version
+        );
+        if self.is_empty() {
+            let _ = writeln!(out, "No changes.");
+            return out;
+        }
+        for (title, components) in [("Added", &self.added), ("Removed", &self.removed)] {
+            if components.is_empty() {
+                continue;
+            }
+            let _ = writeln!(out, "### {} ({})\n", title, components.len());
+            let _ = writeln!(out, "| Package | Name | Version |\n| --- | --- | --- |");
+            for c in components {
+                let _ = writeln!(
+                    out,
+                    "| {} | {} | {} |",
+                    c.package_id,
+                    escape_markdown(&c.name),
+                    c.dell_version
+                );
+            }
+            out.push('\n');
+        }
+        if !self.updated.is_empty() {
+            let _ = writeln!(out, "### Updated ({})\n", self.updated.len());
+            for change in &self.updated {
+                let _ = writeln!(
+                    out,
+                    "- **{}** ({})",
+                    escape_markdown(&change.name),
+                    change.package_id
+                );
+                for line in change.detail_lines() {
+                    let _ = writeln!(out, "  - {}", escape_markdown(&line));
+                }
+            }
+        }
+        out
+    }
+}
+
+fn escape_markdown(text: &str) -> String {
+    text.replace('|', "\\|")
+}
+
+fn component_ids(component: &SoftwareComponent) -> BTreeSet<&str> {
+    component
+        .devices()
+        .map(|device| device.component_id.as_str())
+        .filter(|id| !id.is_empty())
+        .collect()
+}
+
+impl MatchedBy {
+    const ALL: [MatchedBy; 3] = [
+        MatchedBy::PackageId,
+        MatchedBy::Identifier,
+        MatchedBy::ComponentId,
+    ];
+
+    fn matches(&self, old: &SoftwareComponent, new: &SoftwareComponent) -> bool {
+        match self {
+            MatchedBy::PackageId => old.package_id == new.package_id,
+            MatchedBy::Identifier => !new.identifier.is_empty() && old.identifier == new.identifier,
+            MatchedBy::ComponentId => !component_ids(old).is_disjoint(&component_ids(new)),
+        }
+    }
+}
+
+/// 比较两个 catalog, 依次按 packageID, identifier 和设备的 componentID 对应组件
+pub fn diff_manifests(old: &Manifest, new: &Manifest) -> CatalogDiff {
+    let mut diff = CatalogDiff {
+        old_version: old.version.clone(),
+        new_version: new.version.clone(),
+        ..Default::default()
+    };
+    let mut matched = vec![false; old.software_components.len()];
+    let mut pairs: Vec<Option<(usize, MatchedBy)>> = vec![None; new.software_components.len()];
+    // 所有组件按 packageID 配对之后才尝试下一种方式, 避免被其他组件按 componentID 提前占用
+    for matched_by in MatchedBy::ALL {
+        for (component, pair) in new.software_components.iter().zip(pairs.iter_mut()) {
+            if pair.is_some() {
+                continue;
+            }
+            let found = old
+                .software_components
+                .iter()
+                .enumerate()
+                .position(|(index, c)| !matched[index] && matched_by.matches(c, component));
+            if let Some(index) = found {
+                matched[index] = true;
+                *pair = Some((index, matched_by));
+            }
+        }
+    }
+    for (component, pair) in new.software_components.iter().zip(pairs) {
+        match pair {
+            None => diff.added.push(component.into()),
+            Some((index, matched_by)) => {
+                let change =
+                    ComponentChange::new(&old.software_components[index], component, matched_by);
+                if !change.is_empty() {
+                    diff.updated.push(change);
+                }
+            }
+        }
+    }
+    diff.removed = old
+        .software_components
+        .iter()
+        .zip(matched)
+        .filter(|(_, matched)| !matched)
+        .map(|(component, _)| component.into())
+        .collect();
+    diff
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_diff_manifests() {
+        let old = Manifest::from_path(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/../Precision_0CBB.xml"
+        ))
+        .unwrap();
+        assert!(diff_manifests(&old, &old).is_empty());
+
+        let mut new = old.clone();
+        let mut bios = new.software_components.remove(1);
+        new.software_components[0].dell_version = "9.9.9".into();
+        bios.package_id = "NEW01".into();
+        bios.identifier = "00000000-0000-0000-0000-000000000000".into();
+        bios.supported_devices = None;
+        bios.supported_dch_devices = None;
+        new.software_components.push(bios);
+
+        let diff = diff_manifests(&old, &new);
+        assert_eq!(diff.added.len(), 1);
+        assert_eq!(diff.added[0].package_id, "NEW01");
+        assert_eq!(diff.removed.len(), 1);
+        assert_eq!(
+            diff.removed[0].package_id,
+            old.software_components[1].package_id
+        );
+        assert_eq!(diff.updated.len(), 1);
+        assert_eq!(diff.updated[0].new_version, "9.9.9");
+        assert_eq!(
+            diff.updated[0].old_version,
+            old.software_components[0].dell_version
+        );
+        assert!(diff.to_text().contains("dellVersion"));
+        assert!(diff.to_markdown().contains("### Added (1)"));
+    }
+
+    #[test]
+    fn test_diff_matches_by_identifier() {
+        let old = Manifest::from_path(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/../Precision_0CBB.xml"
+        ))
+        .unwrap();
+        let mut new = old.clone();
+        let component = &mut new.software_components[1];
+        component.package_id = "NEW02".into();
+        if let Some(hash) = component
+            .cryptography
+            .as_mut()
+            .and_then(|c| c.hashes.first_mut())
+        {
+            hash.value = "changed".into();
+        }
+        if let Some(systems) = component.supported_systems.as_mut() {
+            systems.brands.clear();
+        }
+
+        let diff = diff_manifests(&old, &new);
+        assert!(diff.added.is_empty() && diff.removed.is_empty());
+        let change = &diff.updated[0];
+        assert_eq!(change.matched_by, MatchedBy::Identifier);
+        assert_eq!(
+            change.old_package_id.as_deref(),
+            Some(old.software_components[1].package_id.as_str())
+        );
+        assert!(!change.version_changed());
+        let mut system_ids = old.software_components[1].system_ids();
+        system_ids.sort();
+        system_ids.dedup();
+        assert_eq!(change.systems.removed, system_ids);
+        assert!(change
+            .hashes
+            .iter()
+            .any(|h| h.new.as_deref() == Some("changed")));
+    }
+}
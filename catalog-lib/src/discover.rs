@@ -0,0 +1,277 @@
+use std::{
+    cmp::Reverse,
+    fmt, fs,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use serde::Serialize;
+use walkdir::{DirEntry, WalkDir};
+
+use crate::{error::CatalogError, is_cab_path, is_ic_path, load_manifest_header, CatalogInfo};
+
+/// 查找 catalog 和 inventory collector 时的范围
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiscoverOptions {
+    /// 相对起始目录的最大深度, 起始目录中的文件深度为 1
+    pub max_depth: usize,
+    /// 匹配文件名或相对路径时跳过, 例如 `target`, `**/*.bak.cab`
+    pub ignore: Vec<String>,
+}
+
+impl Default for DiscoverOptions {
+    fn default() -> Self {
+        DiscoverOptions {
+            max_depth: 3,
+            ignore: vec!["target".into(), ".git".into(), "node_modules".into()],
+        }
+    }
+}
+
+/// 找到的 catalog, 无法读取时只有路径
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct CatalogCandidate {
+    pub path: PathBuf,
+    pub version: Option<String>,
+    pub release_id: Option<String>,
+    pub date_time: Option<String>,
+    pub modified: Option<SystemTime>,
+}
+
+/// 找到的 inventory collector
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct IcCandidate {
+    pub path: PathBuf,
+    /// PE 资源中的文件版本, 例如 `5.3.0.12`
+    pub file_version: Option<String>,
+    pub modified: Option<SystemTime>,
+}
+
+/// 所有候选, 都按从新到旧排列
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct Discovery {
+    pub catalogs: Vec<CatalogCandidate>,
+    pub ics: Vec<IcCandidate>,
+}
+
+impl fmt::Display for CatalogCandidate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.path.display())?;
+        match (&self.version, &self.date_time) {
+            (Some(version), Some(date_time)) => write!(f, " ({}, {})", version, date_time),
+            (Some(version), None) => write!(f, " ({})", version),
+            (None, Some(date_time)) => write!(f, " ({})", date_time),
+            (None, None) => Ok(()),
+        }
+    }
+}
+
+impl fmt::Display for IcCandidate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.path.display())?;
+        if let Some(version) = &self.file_version {
+            write!(f, " ({})", version)?;
+        }
+        Ok(())
+    }
+}
+
+impl CatalogCandidate {
+    fn read(path: PathBuf) -> Self {
+        let modified = modified_time(&path);
+        match load_manifest_header(&path) {
+            Ok(header) => CatalogCandidate {
+                path,
+                version: Some(header.version),
+                release_id: Some(header.release_id),
+                date_time: Some(header.date_time),
+                modified,
+            },
+            Err(e) => {
+                log::warn!("discover catalog--{:?}-{}", path, e);
+                CatalogCandidate {
+                    path,
+                    version: None,
+                    release_id: None,
+                    date_time: None,
+                    modified,
+                }
+            }
+        }
+    }
+}
+
+impl IcCandidate {
+    fn read(path: PathBuf) -> Self {
+        let file_version = fs::read(&path)
+            .ok()
+            .and_then(|data| file_version(&data))
+            .map(|v| format!("{}.{}.{}.{}", v[0], v[1], v[2], v[3]));
+        IcCandidate {
+            modified: modified_time(&path),
+            file_version,
+            path,
+        }
+    }
+
+    fn version_key(&self) -> Vec<u32> {
+        self.file_version
+            .iter()
+            .flat_map(|v| v.split('.'))
+            .filter_map(|part| part.parse().ok())
+            .collect()
+    }
+}
+
+impl Discovery {
+    /// 使用排在最前面的 catalog 和 inventory collector
+    pub fn catalog_info(&self) -> CatalogInfo {
+        CatalogInfo {
+            cab_path: self.catalogs.first().map(|c| c.path.clone()),
+            ic_path: self.ics.first().map(|ic| ic.path.clone()),
+            ..Default::default()
+        }
+    }
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+/// `VS_FIXEDFILEINFO` 中的 `dwFileVersionMS`/`dwFileVersionLS`, 签名之后依次是结构版本和这两个字段
+fn file_version(data: &[u8]) -> Option<[u16; 4]> {
+    const SIGNATURE: [u8; 4] = 0xFEEF04BDu32.to_le_bytes();
+    let start = data.windows(4).position(|w| w == SIGNATURE)?;
+    let fields = data.get(start + 8..start + 16)?;
+    let word = |i: usize| u16::from_le_bytes([fields[i], fields[i + 1]]);
+    Some([word(2), word(0), word(6), word(4)])
+}
+
+fn ignore_set(patterns: &[String]) -> Result<GlobSet, CatalogError> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        let glob = Glob::new(pattern)
+            .map_err(|e| CatalogError::Config(format!("ignore pattern {}: {}", pattern, e)))?;
+        builder.add(glob);
+    }
+    builder
+        .build()
+        .map_err(|e| CatalogError::Config(e.to_string()))
+}
+
+fn is_ignored(ignore: &GlobSet, root: &Path, entry: &DirEntry) -> bool {
+    entry.depth() > 0
+        && (ignore.is_match(entry.file_name())
+            || entry
+                .path()
+                .strip_prefix(root)
+                .is_ok_and(|relative| ignore.is_match(relative)))
+}
+
+/// 在 `root` 中查找所有 catalog 和 inventory collector
+pub fn discover(root: &Path, options: &DiscoverOptions) -> Result<Discovery, CatalogError> {
+    let ignore = ignore_set(&options.ignore)?;
+    let mut discovery = Discovery::default();
+    for entry in WalkDir::new(root)
+        .max_depth(options.max_depth)
+        .into_iter()
+        .filter_entry(|e| !is_ignored(&ignore, root, e))
+        .filter_map(Result::ok)
+        .filter(|e| e.file_type().is_file())
+    {
+        let path = entry.into_path();
+        if is_cab_path(&path) {
+            discovery.catalogs.push(CatalogCandidate::read(path));
+        } else if is_ic_path(&path) {
+            discovery.ics.push(IcCandidate::read(path));
+        }
+    }
+    // dateTime 是同一格式的 ISO 8601 时间, 可以直接按字符串比较
+    discovery
+        .catalogs
+        .sort_by_key(|c| Reverse((c.date_time.clone(), c.modified)));
+    discovery
+        .ics
+        .sort_by_key(|ic| Reverse((ic.version_key(), ic.modified)));
+    log::debug!(
+        "discover--{} catalogs, {} ics",
+        discovery.catalogs.len(),
+        discovery.ics.len()
+    );
+    Ok(discovery)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{cab::CompressionType, xml_to_cab};
+
+    fn write_catalog(cab_path: &Path, version: &str, date_time: &str) {
+        let xml = format!(
+            r#"<?xml version="1.0" encoding="utf-8"?><Manifest xmlns="openmanage/cm/dm" baseLocation="downloads.dell.com" dateTime="{}" identifier="id" releaseID="{}" version="{}"></Manifest>"#,
+            date_time, version, version
+        );
+        let xml_path = cab_path.with_extension("xml");
+        fs::create_dir_all(cab_path.parent().unwrap()).unwrap();
+        fs::write(&xml_path, xml).unwrap();
+        xml_to_cab(&xml_path, CompressionType::None).unwrap();
+    }
+
+    /// 只包含 `VS_FIXEDFILEINFO` 开头部分的假文件
+    fn write_ic(path: &Path, version: [u16; 4]) {
+        let mut data = b"MZ padding".to_vec();
+        data.extend_from_slice(&0xFEEF04BDu32.to_le_bytes());
+        data.extend_from_slice(&0x0001_0000u32.to_le_bytes());
+        data.extend_from_slice(&((version[0] as u32) << 16 | version[1] as u32).to_le_bytes());
+        data.extend_from_slice(&((version[2] as u32) << 16 | version[3] as u32).to_le_bytes());
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, data).unwrap();
+    }
+
+    #[test]
+    fn test_discover() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        write_catalog(
+            &root.join("old.cab"),
+            "2024.01",
+            "2024-01-10T00:00:00+00:00",
+        );
+        write_catalog(
+            &root.join("a/new.cab"),
+            "2024.05",
+            "2024-05-20T05:58:26+00:00",
+        );
+        write_catalog(&root.join("target/debug/skip.cab"), "2025.01", "2025-01-01");
+        write_catalog(&root.join("a/b/c/deep.cab"), "2025.02", "2025-02-01");
+        fs::write(root.join("broken.cab"), b"not a catalog").unwrap();
+        write_ic(&root.join("invcolpc_old.exe"), [5, 2, 0, 1]);
+        write_ic(&root.join("a/InvColPC.exe"), [5, 10, 0, 3]);
+
+        let discovery = discover(root, &DiscoverOptions::default()).unwrap();
+        let names: Vec<_> = discovery
+            .catalogs
+            .iter()
+            .map(|c| c.path.file_name().unwrap().to_str().unwrap())
+            .collect();
+        assert_eq!(names, ["new.cab", "old.cab", "broken.cab"]);
+        assert_eq!(discovery.catalogs[0].release_id.as_deref(), Some("2024.05"));
+        assert_eq!(discovery.catalogs[2].version, None);
+        assert_eq!(discovery.ics[0].path, root.join("a/InvColPC.exe"));
+        assert_eq!(discovery.ics[0].file_version.as_deref(), Some("5.10.0.3"));
+
+        let info = discovery.catalog_info();
+        assert_eq!(info.cab_path, Some(root.join("a/new.cab")));
+        assert_eq!(info.ic_path, Some(root.join("a/InvColPC.exe")));
+
+        let options = DiscoverOptions {
+            max_depth: 1,
+            ignore: vec!["*.exe".into()],
+        };
+        let discovery = discover(root, &options).unwrap();
+        assert_eq!(discovery.catalogs.len(), 2);
+        assert!(discovery.ics.is_empty());
+        assert_eq!(discovery.catalog_info().ic_path, None);
+    }
+}
@@ -22,9 +22,46 @@ pub enum CatalogError {
     #[error("Failed to parse catalog: {0}")]
     InvalidCatalog(String),
 
+    #[error("Invalid registry value {name}: {reason}")]
+    RegistryValue { name: String, reason: String },
+
+    #[error("Invalid configuration: {0}")]
+    Config(String),
+
+    #[error("Invalid hardware inventory: {0}")]
+    InvalidInventory(String),
+
+    #[error("Neither Dell Update nor Dell Command Update is installed")]
+    NoClientInstalled,
+
+    #[error("{client} cannot be used: {reason}")]
+    ClientRejected { client: String, reason: String },
+
+    #[error("Failed to install {0}")]
+    InstallError(String),
+
+    #[error("Staged packages do not match the catalog: {0}")]
+    PackageMismatch(String),
+
+    #[error("HTTP server error: {0}")]
+    ServerError(String),
+
+    #[error("Failed to launch {0}")]
+    LaunchError(String),
+
+    #[error(transparent)]
+    XmlReadError(#[from] xml::reader::Error),
+
+    #[error(transparent)]
+    XmlWriteError(#[from] xml::writer::Error),
+
+    #[error(transparent)]
+    JsonError(#[from] serde_json::Error),
+
     #[error(transparent)]
     IcedError(#[from] iced::Error),
 
+    #[cfg(windows)]
     #[error(transparent)]
     WindsowsError(#[from] windows::core::Error),
 }
@@ -0,0 +1,165 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::CatalogError,
+    manifest::{CodedValue, Manifest, SoftwareComponent},
+    rewrite::{element_spans, remove_spans},
+};
+
+/// 保留还是删除匹配的组件
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FilterMode {
+    #[default]
+    Keep,
+    Drop,
+}
+
+/// 组件过滤条件, 不同字段之间是 "且", 同一字段的多个值之间是 "或", 空字段不限制
+///
+/// 分类, 类型和重要性可以写 `value` 也可以写显示文本, 都不区分大小写
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ComponentFilter {
+    #[serde(default)]
+    pub mode: FilterMode,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub package_ids: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub categories: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub component_types: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub criticalities: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub package_types: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub os_codes: Vec<String>,
+}
+
+/// 过滤后的 xml 文本和保留, 删除的 packageID
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FilterOutcome {
+    pub text: String,
+    pub kept: Vec<String>,
+    pub removed: Vec<String>,
+}
+
+fn any_eq<'a>(wanted: &[String], values: impl IntoIterator<Item = &'a str>) -> bool {
+    let values: Vec<&str> = values.into_iter().collect();
+    wanted
+        .iter()
+        .any(|w| values.iter().any(|v| v.eq_ignore_ascii_case(w)))
+}
+
+fn coded_matches(wanted: &[String], value: Option<&CodedValue>) -> bool {
+    wanted.is_empty() || value.is_some_and(|v| any_eq(wanted, [v.value.as_str(), v.text()]))
+}
+
+impl ComponentFilter {
+    /// 组件是否满足所有条件
+    pub fn matches(&self, component: &SoftwareComponent) -> bool {
+        (self.package_ids.is_empty() || any_eq(&self.package_ids, [component.package_id.as_str()]))
+            && coded_matches(&self.categories, component.category.as_ref())
+            && coded_matches(&self.component_types, component.component_type.as_ref())
+            && coded_matches(&self.criticalities, component.criticality.as_ref())
+            && (self.package_types.is_empty()
+                || any_eq(&self.package_types, [component.package_type.as_str()]))
+            && (self.os_codes.is_empty() || any_eq(&self.os_codes, component.os_codes()))
+    }
+
+    /// 过滤后是否保留该组件
+    pub fn keeps(&self, component: &SoftwareComponent) -> bool {
+        match self.mode {
+            FilterMode::Keep => self.matches(component),
+            FilterMode::Drop => !self.matches(component),
+        }
+    }
+
+    /// 从 xml 文本中删除不保留的 `SoftwareComponent`, 其余内容原样保留
+    pub fn apply(&self, text: &str) -> Result<FilterOutcome, CatalogError> {
+        // 文本已经解码, 去掉声明中的 `encoding="utf-16"` 再解析
+        let body = match text.find("?>") {
+            Some(end) if text.trim_start().starts_with("<?xml") => &text[end + 2..],
+            _ => text,
+        };
+        let manifest = Manifest::from_reader(body.as_bytes())?;
+        let spans = element_spans(text, "SoftwareComponent")?;
+        if spans.len() != manifest.software_components.len() {
+            return Err(CatalogError::InvalidCatalog(format!(
+                "found {} SoftwareComponent tags but parsed {} components",
+                spans.len(),
+                manifest.software_components.len()
+            )));
+        }
+        let mut kept = Vec::new();
+        let mut removed = Vec::new();
+        let mut removed_spans = Vec::new();
+        for (component, span) in manifest.software_components.iter().zip(spans) {
+            if self.keeps(component) {
+                kept.push(component.package_id.clone());
+            } else {
+                removed.push(component.package_id.clone());
+                removed_spans.push(span);
+            }
+        }
+        log::debug!("filter--kept {}, removed {}", kept.len(), removed.len());
+        Ok(FilterOutcome {
+            text: remove_spans(text, &removed_spans),
+            kept,
+            removed,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rewrite::XmlText;
+
+    #[test]
+    fn test_filter_catalog() {
+        let document = XmlText::from_path(
+            concat!(env!("CARGO_MANIFEST_DIR"), "/../Precision_0CBB.xml").as_ref(),
+        )
+        .unwrap();
+        let manifest = Manifest::from_path(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/../Precision_0CBB.xml"
+        ))
+        .unwrap();
+        let first = &manifest.software_components[0];
+        let second = &manifest.software_components[1];
+
+        let filter = ComponentFilter {
+            categories: vec![first.category.as_ref().unwrap().text().to_uppercase()],
+            package_ids: vec![first.package_id.to_lowercase()],
+            ..Default::default()
+        };
+        let outcome = filter.apply(&document.text).unwrap();
+        assert_eq!(outcome.kept, [first.package_id.as_str()]);
+        assert_eq!(outcome.removed, [second.package_id.as_str()]);
+        let filtered = Manifest::from_reader(
+            XmlText {
+                text: outcome.text.clone(),
+                ..document.clone()
+            }
+            .to_bytes()
+            .as_slice(),
+        )
+        .unwrap();
+        assert_eq!(filtered.software_components, std::slice::from_ref(first));
+        assert_eq!(filtered.version, manifest.version);
+
+        let drop_all = ComponentFilter {
+            mode: FilterMode::Drop,
+            ..Default::default()
+        };
+        let outcome = drop_all.apply(&document.text).unwrap();
+        assert!(outcome.kept.is_empty());
+        assert!(!outcome.text.contains("<SoftwareComponent"));
+
+        let json = r#"{"mode": "drop", "os_codes": ["nonexistent"]}"#;
+        let filter: ComponentFilter = serde_json::from_str(json).unwrap();
+        assert_eq!(filter.apply(&document.text).unwrap().removed.len(), 0);
+    }
+}
@@ -0,0 +1,426 @@
+use std::{
+    fmt,
+    fs::File,
+    io::{self, BufReader, Read},
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use data_encoding::BASE64;
+use serde::{Deserialize, Serialize};
+use sha2::{Sha256, Sha384};
+use sha3::{Digest, Sha3_384};
+
+use crate::error::CatalogError;
+
+/// catalog 哈希使用的摘要算法
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum HashAlgorithm {
+    Sha256,
+    /// .NET `SHA384CryptoServiceProvider`, 旧 Python 工具使用
+    Sha384,
+    #[default]
+    Sha3_384,
+}
+
+impl HashAlgorithm {
+    pub const ALL: [HashAlgorithm; 3] = [
+        HashAlgorithm::Sha256,
+        HashAlgorithm::Sha384,
+        HashAlgorithm::Sha3_384,
+    ];
+
+    pub fn digest(&self, data: &[u8]) -> Vec<u8> {
+        match self {
+            HashAlgorithm::Sha256 => Sha256::digest(data).to_vec(),
+            HashAlgorithm::Sha384 => Sha384::digest(data).to_vec(),
+            HashAlgorithm::Sha3_384 => Sha3_384::digest(data).to_vec(),
+        }
+    }
+
+    pub fn digest_reader<R: Read>(&self, reader: &mut R) -> Result<Vec<u8>, io::Error> {
+        match self {
+            HashAlgorithm::Sha256 => digest_reader_with(Sha256::new(), reader),
+            HashAlgorithm::Sha384 => digest_reader_with(Sha384::new(), reader),
+            HashAlgorithm::Sha3_384 => digest_reader_with(Sha3_384::new(), reader),
+        }
+    }
+}
+
+/// 用给定的摘要算法读完 `reader`
+pub(crate) fn digest_reader_with<D: Digest + io::Write, R: Read>(
+    mut hasher: D,
+    reader: &mut R,
+) -> Result<Vec<u8>, io::Error> {
+    io::copy(reader, &mut hasher)?;
+    Ok(hasher.finalize().to_vec())
+}
+
+impl fmt::Display for HashAlgorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            HashAlgorithm::Sha256 => "SHA-256",
+            HashAlgorithm::Sha384 => "SHA-384",
+            HashAlgorithm::Sha3_384 => "SHA3-384",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for HashAlgorithm {
+    type Err = CatalogError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().replace(['-', '_'], "").as_str() {
+            "sha256" => Ok(HashAlgorithm::Sha256),
+            "sha384" => Ok(HashAlgorithm::Sha384),
+            "sha3384" => Ok(HashAlgorithm::Sha3_384),
+            _ => Err(CatalogError::Config(format!(
+                "unknown hash algorithm {}",
+                s
+            ))),
+        }
+    }
+}
+
+/// 摘要算法和 base64 是否保留末尾的 `=`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HashFormat {
+    pub algorithm: HashAlgorithm,
+    pub padded: bool,
+}
+
+impl HashFormat {
+    pub fn new(algorithm: HashAlgorithm, padded: bool) -> Self {
+        HashFormat { algorithm, padded }
+    }
+
+    /// 所有支持的组合
+    pub fn all() -> Vec<HashFormat> {
+        HashAlgorithm::ALL
+            .iter()
+            .flat_map(|&algorithm| [false, true].map(|padded| HashFormat::new(algorithm, padded)))
+            .collect()
+    }
+
+    pub fn encode(&self, digest: &[u8]) -> String {
+        let base64 = BASE64.encode(digest);
+        if self.padded {
+            base64
+        } else {
+            base64.trim_end_matches('=').to_string()
+        }
+    }
+
+    pub fn hash_file(&self, path: &Path) -> Result<String, io::Error> {
+        let mut reader = BufReader::new(File::open(path)?);
+        Ok(self.encode(&self.algorithm.digest_reader(&mut reader)?))
+    }
+}
+
+impl fmt::Display for HashFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let padding = if self.padded { "padded" } else { "unpadded" };
+        write!(f, "{} ({})", self.algorithm, padding)
+    }
+}
+
+/// 计算 catalog 文件的 SHA3-384, base64 编码并去掉末尾的 `=`
+pub fn catalog_hash(path: &Path) -> Result<String, io::Error> {
+    HashFormat::default().hash_file(path)
+}
+
+/// 找出与注册表中记录的哈希一致的算法, 都不一致时返回空列表
+///
+/// `registry_value` 可以是完整的 CustomCatalogHashValues, 也可以只是哈希本身
+pub fn verify_catalog_hash(
+    path: &Path,
+    registry_value: &str,
+) -> Result<Vec<HashFormat>, CatalogError> {
+    let stored = match CatalogHashValues::from_json(registry_value) {
+        Ok(values) => values
+            .get(&path.to_string_lossy())
+            .map(String::from)
+            .ok_or_else(|| {
+                CatalogError::SelectedFileError(format!(
+                    "{:?} is not in CustomCatalogHashValues",
+                    path
+                ))
+            })?,
+        Err(_) => registry_value.trim().to_string(),
+    };
+    let data = std::fs::read(path)?;
+    let mut matches = Vec::new();
+    for algorithm in HashAlgorithm::ALL {
+        let digest = algorithm.digest(&data);
+        let unpadded = HashFormat::new(algorithm, false);
+        let padded = HashFormat::new(algorithm, true);
+        if unpadded.encode(&digest) == stored {
+            matches.push(unpadded);
+        } else if padded.encode(&digest) == stored {
+            // 长度是 3 的倍数时两者相同, 只会走到上一个分支
+            matches.push(padded);
+        }
+    }
+    log::debug!("verify_catalog_hash--{:?}--{:?}", path, matches);
+    Ok(matches)
+}
+
+/// `CustomCatalogHashValues` 中的一项, `Key` 是 catalog 路径
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CatalogHashValue {
+    #[serde(rename = "Key")]
+    pub key: String,
+    #[serde(rename = "Value")]
+    pub value: String,
+}
+
+/// 注册表 `CustomCatalogHashValues` 的内容
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CatalogHashValues {
+    #[serde(rename = "CatalogHashValues")]
+    pub values: Vec<CatalogHashValue>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum OneOrMany {
+    One(CatalogHashValue),
+    Many(Vec<CatalogHashValue>),
+}
+
+#[derive(Deserialize)]
+struct HashValuesObject {
+    #[serde(rename = "CatalogHashValues")]
+    values: OneOrMany,
+}
+
+/// 兼容旧 Python 工具写入的 `[{"CatalogHashValues": {...}}]`
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum HashValuesJson {
+    Object(HashValuesObject),
+    List(Vec<HashValuesObject>),
+}
+
+impl CatalogHashValues {
+    /// 计算多个 catalog 的哈希, 例如实验室里每个平台一个 catalog
+    pub fn from_catalogs<P: AsRef<Path>>(paths: &[P]) -> Result<Self, CatalogError> {
+        Self::from_catalogs_with(paths, HashFormat::default())
+    }
+
+    pub fn from_catalogs_with<P: AsRef<Path>>(
+        paths: &[P],
+        format: HashFormat,
+    ) -> Result<Self, CatalogError> {
+        let mut values = CatalogHashValues::default();
+        for path in paths {
+            values.add_catalog_with(path.as_ref(), format)?;
+        }
+        Ok(values)
+    }
+
+    /// 解析注册表中已有的值, 空字符串视为没有记录
+    pub fn from_json(json: &str) -> Result<Self, CatalogError> {
+        if json
+            .trim_matches(|c: char| c.is_whitespace() || c == '\0')
+            .is_empty()
+        {
+            return Ok(CatalogHashValues::default());
+        }
+        let objects = match serde_json::from_str(json.trim_end_matches('\0'))? {
+            HashValuesJson::Object(object) => vec![object],
+            HashValuesJson::List(list) => list,
+        };
+        let mut values = CatalogHashValues::default();
+        for object in objects {
+            match object.values {
+                OneOrMany::One(value) => values.insert(value.key, value.value),
+                OneOrMany::Many(many) => {
+                    for value in many {
+                        values.insert(value.key, value.value);
+                    }
+                }
+            }
+        }
+        Ok(values)
+    }
+
+    pub fn to_json(&self) -> Result<String, CatalogError> {
+        Ok(serde_json::to_string(self)?)
+    }
+
+    /// Windows 路径不区分大小写
+    fn position(&self, key: &str) -> Option<usize> {
+        self.values
+            .iter()
+            .position(|v| v.key.to_lowercase() == key.to_lowercase())
+    }
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.position(key).map(|i| self.values[i].value.as_str())
+    }
+
+    /// 加入或替换同一路径的哈希
+    pub fn insert<K: Into<String>, V: Into<String>>(&mut self, key: K, value: V) {
+        let key = key.into();
+        let value = value.into();
+        match self.position(&key) {
+            Some(i) => self.values[i].value = value,
+            None => self.values.push(CatalogHashValue { key, value }),
+        }
+    }
+
+    pub fn add_catalog(&mut self, path: &Path) -> Result<&mut Self, CatalogError> {
+        self.add_catalog_with(path, HashFormat::default())
+    }
+
+    pub fn add_catalog_with(
+        &mut self,
+        path: &Path,
+        format: HashFormat,
+    ) -> Result<&mut Self, CatalogError> {
+        let hash = format.hash_file(path)?;
+        log::debug!("add_catalog--{:?}--{}", path, hash);
+        self.insert(path.to_string_lossy(), hash);
+        Ok(self)
+    }
+
+    /// 合并另一组哈希, 相同路径以 `other` 为准
+    pub fn merge(&mut self, other: &CatalogHashValues) -> &mut Self {
+        for value in other.values.iter() {
+            self.insert(value.key.clone(), value.value.clone());
+        }
+        self
+    }
+
+    pub fn keys(&self) -> Vec<PathBuf> {
+        self.values.iter().map(|v| PathBuf::from(&v.key)).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_catalog_hash() {
+        let path = Path::new(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/../_Precision_0CBB.xml"
+        ));
+        let hash = catalog_hash(path).unwrap();
+        assert_eq!(hash.len(), 64);
+        assert!(!hash.ends_with('='));
+    }
+
+    #[test]
+    fn test_hash_formats() {
+        let expected = [
+            // python: base64(hashlib.sha256(b"abc").digest())
+            (
+                HashAlgorithm::Sha256,
+                "ungWv48Bz+pBQUDeXa4iI7ADYaOWF3qctBD/YfIAFa0=",
+            ),
+            (
+                HashAlgorithm::Sha384,
+                "ywB1P0WjXou1oD1pmsZQBycsMqsO3tFjGotgWkP/W+2AhgcroefMI1i67KE0yCWn",
+            ),
+            (
+                HashAlgorithm::Sha3_384,
+                "7AFJgohRb8kmRZ9Y4satjfm0c8sPwIwlltp88OSb5LKY2IzqknrH9Tnx7fIoN20l",
+            ),
+        ];
+        for (algorithm, base64) in expected {
+            let digest = algorithm.digest(b"abc");
+            assert_eq!(HashFormat::new(algorithm, true).encode(&digest), base64);
+            assert_eq!(
+                HashFormat::new(algorithm, false).encode(&digest),
+                base64.trim_end_matches('=')
+            );
+            assert_eq!(algorithm.digest_reader(&mut &b"abc"[..]).unwrap(), digest);
+            assert_eq!(
+                algorithm.to_string().parse::<HashAlgorithm>().unwrap(),
+                algorithm
+            );
+        }
+    }
+
+    #[test]
+    fn test_verify_catalog_hash() {
+        let path = Path::new(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/../_Precision_0CBB.xml"
+        ));
+        let sha256 = HashFormat::new(HashAlgorithm::Sha256, false);
+        let mut values = CatalogHashValues::default();
+        values.add_catalog_with(path, sha256).unwrap();
+        let json = values.to_json().unwrap();
+        assert_eq!(verify_catalog_hash(path, &json).unwrap(), vec![sha256]);
+
+        let sha384 = HashFormat::new(HashAlgorithm::Sha384, true)
+            .hash_file(path)
+            .unwrap();
+        assert_eq!(
+            verify_catalog_hash(path, &sha384).unwrap(),
+            vec![HashFormat::new(HashAlgorithm::Sha384, false)]
+        );
+        assert!(verify_catalog_hash(path, "bogus").unwrap().is_empty());
+        assert!(verify_catalog_hash(path, r#"{"CatalogHashValues":[]}"#).is_err());
+    }
+
+    #[test]
+    fn test_json_escaping() {
+        let mut values = CatalogHashValues::default();
+        values.insert(r"C:\Program Files\Dell Catalogs\_Precision_0CBB.xml", "abc");
+        values.insert(r"\\lab-server\共享\目录\_Latitude.xml", "def");
+        values.insert(r#"D:\odd "quoted" name.xml"#, "ghi");
+        let json = values.to_json().unwrap();
+        assert_eq!(
+            json,
+            r#"{"CatalogHashValues":[{"Key":"C:\\Program Files\\Dell Catalogs\\_Precision_0CBB.xml","Value":"abc"},{"Key":"\\\\lab-server\\共享\\目录\\_Latitude.xml","Value":"def"},{"Key":"D:\\odd \"quoted\" name.xml","Value":"ghi"}]}"#
+        );
+        assert_eq!(CatalogHashValues::from_json(&json).unwrap(), values);
+    }
+
+    #[test]
+    fn test_merge() {
+        let mut existing = CatalogHashValues::from_json(
+            r#"{"CatalogHashValues":[{"Key":"C:\\a.xml","Value":"old"},{"Key":"C:\\b.xml","Value":"keep"}]}"#,
+        )
+        .unwrap();
+        let mut new = CatalogHashValues::default();
+        new.insert(r"c:\A.xml", "new");
+        new.insert(r"C:\c.xml", "added");
+        existing.merge(&new);
+        assert_eq!(existing.values.len(), 3);
+        assert_eq!(existing.get(r"C:\a.xml"), Some("new"));
+        assert_eq!(existing.get(r"C:\b.xml"), Some("keep"));
+        assert_eq!(existing.get(r"C:\c.xml"), Some("added"));
+    }
+
+    #[test]
+    fn test_from_json_legacy_and_empty() {
+        let legacy = CatalogHashValues::from_json(
+            r#"[{"CatalogHashValues": {"Key": "C:\\tmp\\_Precision_0CBB.xml", "Value": "xyz"}}]"#,
+        )
+        .unwrap();
+        assert_eq!(legacy.get(r"C:\tmp\_Precision_0CBB.xml"), Some("xyz"));
+        assert_eq!(
+            CatalogHashValues::from_json("\0").unwrap(),
+            CatalogHashValues::default()
+        );
+        assert!(CatalogHashValues::from_json("not json").is_err());
+    }
+
+    #[test]
+    fn test_from_catalogs() {
+        let paths = [
+            concat!(env!("CARGO_MANIFEST_DIR"), "/../Precision_0CBB.xml"),
+            concat!(env!("CARGO_MANIFEST_DIR"), "/../_Precision_0CBB.xml"),
+        ];
+        let values = CatalogHashValues::from_catalogs(&paths).unwrap();
+        assert_eq!(values.values.len(), 2);
+        assert_ne!(values.values[0].value, values.values[1].value);
+    }
+}
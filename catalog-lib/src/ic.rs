@@ -0,0 +1,322 @@
+use std::{
+    fs::{self, File},
+    io::BufReader,
+    path::{Path, PathBuf},
+};
+
+use data_encoding::HEXLOWER;
+use serde::Serialize;
+
+use crate::{
+    detect::{ServiceControl, ServiceState, SERVICE_NAME},
+    error::CatalogError,
+    hash::HashAlgorithm,
+};
+
+/// DU/DCU 使用的 inventory collector 文件名
+pub const IC_FILE_NAME: &str = "InvColPC.exe";
+
+/// DU/DCU 服务的安装目录
+pub const DEFAULT_SERVICE_DIR: &str = r"C:\Program Files (x86)\Dell\UpdateService\Service";
+
+/// 一次安装或还原的结果
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct IcInstallResult {
+    pub target: PathBuf,
+    pub backup: Option<PathBuf>,
+    /// 目标文件的 SHA-256 (hex)
+    pub sha256: String,
+    pub service_restarted: bool,
+}
+
+/// 在服务停止期间替换 inventory collector, 第一次替换前备份原文件
+pub struct IcInstaller<'a> {
+    root: PathBuf,
+    services: &'a mut dyn ServiceControl,
+}
+
+fn sha256_file(path: &Path) -> Result<String, CatalogError> {
+    let mut reader = BufReader::new(File::open(path)?);
+    Ok(HEXLOWER.encode(&HashAlgorithm::Sha256.digest_reader(&mut reader)?))
+}
+
+/// 复制后重新计算哈希, 不一致时返回错误
+fn copy_verified(from: &Path, to: &Path, expected: &str) -> Result<(), CatalogError> {
+    fs::copy(from, to)?;
+    let actual = sha256_file(to)?;
+    if actual != expected {
+        return Err(CatalogError::InstallError(format!(
+            "{:?}: hash mismatch, expected {} but got {}",
+            to, expected, actual
+        )));
+    }
+    Ok(())
+}
+
+impl<'a> IcInstaller<'a> {
+    pub fn new(root: impl Into<PathBuf>, services: &'a mut dyn ServiceControl) -> Self {
+        IcInstaller {
+            root: root.into(),
+            services,
+        }
+    }
+
+    pub fn target(&self) -> PathBuf {
+        self.root.join(IC_FILE_NAME)
+    }
+
+    pub fn backup_path(&self) -> PathBuf {
+        self.root.join(format!("{}.bak", IC_FILE_NAME))
+    }
+
+    /// 服务正在运行或处于中间状态时先停止, 返回是否需要重新启动
+    fn stop_service(&mut self) -> Result<bool, CatalogError> {
+        match self.services.state(SERVICE_NAME)? {
+            // 中间状态可能是正在启动, 同样要停止, 替换后按运行处理
+            ServiceState::Running | ServiceState::Pending => {
+                self.services.stop(SERVICE_NAME)?;
+                Ok(true)
+            }
+            ServiceState::Stopped | ServiceState::NotInstalled | ServiceState::Unknown => Ok(false),
+        }
+    }
+
+    /// 执行 `swap` 前后停止和启动服务, `swap` 失败时也会重新启动服务
+    fn with_service_stopped<T>(
+        &mut self,
+        swap: impl FnOnce(&Self) -> Result<T, CatalogError>,
+    ) -> Result<(T, bool), CatalogError> {
+        let restart = self.stop_service()?;
+        let result = swap(self);
+        let started = if restart {
+            self.services.start(SERVICE_NAME)
+        } else {
+            Ok(())
+        };
+        match (result, started) {
+            (Ok(value), Ok(())) => Ok((value, restart)),
+            (Err(e), Ok(())) | (Ok(_), Err(e)) => Err(e),
+            // 两个错误都要报告, 替换失败的原因更重要
+            (Err(e), Err(start)) => Err(CatalogError::InstallError(format!(
+                "{}; restarting {} also failed: {}",
+                e, SERVICE_NAME, start
+            ))),
+        }
+    }
+
+    /// 安装 `source`, 校验失败时还原原文件
+    pub fn install(&mut self, source: &Path) -> Result<IcInstallResult, CatalogError> {
+        log::info!("install_ic--{:?}", source);
+        if !source.is_file() {
+            return Err(CatalogError::SelectedFileError(format!(
+                "{:?} does not exist",
+                source
+            )));
+        }
+        if !self.root.is_dir() {
+            return Err(CatalogError::CurrentFileError(format!("{:?}", self.root)));
+        }
+        let sha256 = sha256_file(source)?;
+        let (backup, service_restarted) = self.with_service_stopped(|installer| {
+            let target = installer.target();
+            let backup = installer.backup_path();
+            if target.is_file() && !backup.exists() {
+                fs::copy(&target, &backup)?;
+            }
+            if let Err(e) = copy_verified(source, &target, &sha256) {
+                if !backup.is_file() {
+                    return Err(e);
+                }
+                // 还原失败时两个错误都要报告, 原来的错误更重要
+                if let Err(restore) = fs::copy(&backup, &target) {
+                    log::error!("install_ic--restore {:?} failed: {}", target, restore);
+                    return Err(CatalogError::InstallError(format!(
+                        "{}; restoring {:?} from backup also failed: {}",
+                        e, target, restore
+                    )));
+                }
+                return Err(e);
+            }
+            Ok(backup.is_file().then_some(backup))
+        })?;
+        Ok(IcInstallResult {
+            target: self.target(),
+            backup,
+            sha256,
+            service_restarted,
+        })
+    }
+
+    /// 用备份还原原来的 inventory collector 并删除备份
+    pub fn restore(&mut self) -> Result<IcInstallResult, CatalogError> {
+        let backup = self.backup_path();
+        log::info!("restore_ic--{:?}", backup);
+        if !backup.is_file() {
+            return Err(CatalogError::CurrentFileError(format!("{:?}", backup)));
+        }
+        let sha256 = sha256_file(&backup)?;
+        let ((), service_restarted) = self.with_service_stopped(|installer| {
+            copy_verified(&backup, &installer.target(), &sha256)
+        })?;
+        fs::remove_file(&backup)?;
+        Ok(IcInstallResult {
+            target: self.target(),
+            backup: None,
+            sha256,
+            service_restarted,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::detect::ServiceQuery;
+
+    /// 记录启动和停止的调用
+    struct RecordingServices {
+        state: ServiceState,
+        calls: Vec<&'static str>,
+        fail_start: bool,
+    }
+
+    impl RecordingServices {
+        fn new(state: ServiceState) -> Self {
+            RecordingServices {
+                state,
+                calls: Vec::new(),
+                fail_start: false,
+            }
+        }
+    }
+
+    impl ServiceQuery for RecordingServices {
+        fn state(&self, _name: &str) -> Result<ServiceState, CatalogError> {
+            Ok(self.state)
+        }
+    }
+
+    impl ServiceControl for RecordingServices {
+        fn stop(&mut self, _name: &str) -> Result<(), CatalogError> {
+            self.calls.push("stop");
+            self.state = ServiceState::Stopped;
+            Ok(())
+        }
+
+        fn start(&mut self, _name: &str) -> Result<(), CatalogError> {
+            self.calls.push("start");
+            if self.fail_start {
+                return Err(CatalogError::InstallError("service start".into()));
+            }
+            self.state = ServiceState::Running;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_install_and_restore() {
+        let root = tempfile::tempdir().unwrap();
+        let source_dir = tempfile::tempdir().unwrap();
+        let source = source_dir.path().join("custom.exe");
+        fs::write(root.path().join(IC_FILE_NAME), b"original").unwrap();
+        fs::write(&source, b"custom").unwrap();
+
+        let mut services = RecordingServices::new(ServiceState::Running);
+        let mut installer = IcInstaller::new(root.path(), &mut services);
+        let result = installer.install(&source).unwrap();
+        assert_eq!(result.target, root.path().join(IC_FILE_NAME));
+        assert_eq!(result.sha256, sha256_file(&source).unwrap());
+        assert!(result.service_restarted);
+        assert_eq!(fs::read(&result.target).unwrap(), b"custom");
+        let backup = result.backup.unwrap();
+        assert_eq!(fs::read(&backup).unwrap(), b"original");
+
+        // 再次安装不会覆盖最初的备份
+        fs::write(&source, b"custom v2").unwrap();
+        installer.install(&source).unwrap();
+        assert_eq!(fs::read(&backup).unwrap(), b"original");
+
+        let restored = installer.restore().unwrap();
+        assert_eq!(fs::read(&restored.target).unwrap(), b"original");
+        assert!(!backup.exists());
+        assert!(matches!(
+            installer.restore(),
+            Err(CatalogError::CurrentFileError(_))
+        ));
+        assert_eq!(
+            services.calls,
+            ["stop", "start", "stop", "start", "stop", "start"]
+        );
+    }
+
+    #[test]
+    fn test_install_without_running_service() {
+        let root = tempfile::tempdir().unwrap();
+        let source = root.path().join("custom.exe");
+        fs::write(&source, b"custom").unwrap();
+
+        let mut services = RecordingServices::new(ServiceState::Stopped);
+        let result = IcInstaller::new(root.path(), &mut services)
+            .install(&source)
+            .unwrap();
+        assert!(!result.service_restarted);
+        assert_eq!(result.backup, None);
+        assert!(services.calls.is_empty());
+
+        let mut services = RecordingServices::new(ServiceState::Running);
+        let missing = IcInstaller::new(root.path().join("missing"), &mut services).install(&source);
+        assert!(matches!(missing, Err(CatalogError::CurrentFileError(_))));
+        assert!(services.calls.is_empty());
+    }
+
+    #[test]
+    fn test_install_with_pending_service() {
+        let root = tempfile::tempdir().unwrap();
+        let source = root.path().join("custom.exe");
+        fs::write(&source, b"custom").unwrap();
+
+        let mut services = RecordingServices::new(ServiceState::Pending);
+        let result = IcInstaller::new(root.path(), &mut services)
+            .install(&source)
+            .unwrap();
+        assert!(result.service_restarted);
+        assert_eq!(services.calls, ["stop", "start"]);
+    }
+
+    #[test]
+    fn test_install_and_restore_both_fail() {
+        let root = tempfile::tempdir().unwrap();
+        let source_dir = tempfile::tempdir().unwrap();
+        let source = source_dir.path().join("custom.exe");
+        fs::write(&source, b"custom").unwrap();
+        // 目标是目录, 复制和还原都会失败
+        fs::create_dir(root.path().join(IC_FILE_NAME)).unwrap();
+        fs::write(
+            root.path().join(format!("{}.bak", IC_FILE_NAME)),
+            b"original",
+        )
+        .unwrap();
+
+        let mut services = RecordingServices::new(ServiceState::Running);
+        let result = IcInstaller::new(root.path(), &mut services).install(&source);
+        let Err(CatalogError::InstallError(message)) = result else {
+            panic!("unexpected result {:?}", result);
+        };
+        assert!(message.contains("restoring"), "{}", message);
+        assert_eq!(services.calls, ["stop", "start"]);
+    }
+
+    #[test]
+    fn test_swap_and_restart_both_fail() {
+        let root = tempfile::tempdir().unwrap();
+        let mut services = RecordingServices::new(ServiceState::Running);
+        services.fail_start = true;
+        let mut installer = IcInstaller::new(root.path(), &mut services);
+        let result: Result<((), bool), _> =
+            installer.with_service_stopped(|_| Err(CatalogError::InstallError("swap".into())));
+        let message = result.unwrap_err().to_string();
+        assert!(message.contains("swap"), "{}", message);
+        assert!(message.contains("service start"), "{}", message);
+        assert_eq!(services.calls, ["stop", "start"]);
+    }
+}
@@ -0,0 +1,438 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    applicability::{HardwareInventory, PciDevice},
+    error::CatalogError,
+    registry::RegistryBackend,
+};
+
+#[cfg(windows)]
+mod win;
+
+/// Windows 上 BIOS 和机型信息所在的键, 与 WMI 的 `Win32_BIOS`/`Win32_ComputerSystem` 来源相同
+pub const BIOS_KEY: &str = r"HARDWARE\DESCRIPTION\System\BIOS";
+
+/// 设备驱动的安装信息, `Driver` 属性是这个键下的相对路径
+pub const DRIVER_CLASS_KEY: &str = r"SYSTEM\CurrentControlSet\Control\Class";
+
+/// 设备上安装的驱动
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InstalledDriver {
+    /// 设备描述
+    pub device: String,
+    /// 设备的第一个硬件 ID
+    pub hardware_id: String,
+    /// Windows 上是驱动提供商, Linux 上是内核模块名
+    pub provider: String,
+    pub version: String,
+}
+
+/// 本机的硬件和驱动信息
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Inventory {
+    /// 采集方式: `windows`, `sysfs` 或 `fixture`
+    pub collector: String,
+    /// Dell 的 systemID, 即 SMBIOS 中的 SKU
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub system_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub manufacturer: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub product_name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bios_version: Option<String>,
+    /// DU 使用的 osCode 无法从系统中直接得到, 需要时手动指定
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub os_code: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub os_arch: Option<String>,
+    #[serde(default)]
+    pub pci_devices: Vec<PciDevice>,
+    #[serde(default)]
+    pub hardware_ids: Vec<String>,
+    #[serde(default)]
+    pub drivers: Vec<InstalledDriver>,
+}
+
+impl Inventory {
+    pub fn from_path(path: &Path) -> Result<Self, CatalogError> {
+        Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), CatalogError> {
+        fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// 适用性判断需要的部分
+    pub fn hardware(&self) -> HardwareInventory {
+        HardwareInventory {
+            system_id: self.system_id.clone(),
+            os_code: self.os_code.clone(),
+            os_arch: self.os_arch.clone(),
+            pci_devices: self.pci_devices.clone(),
+            hardware_ids: self.hardware_ids.clone(),
+        }
+    }
+
+    fn add_pci_device(&mut self, device: PciDevice) {
+        if !self.pci_devices.contains(&device) {
+            self.pci_devices.push(device);
+        }
+    }
+}
+
+/// 采集本机信息的方式
+pub trait InventoryCollector {
+    fn collect(&self) -> Result<Inventory, CatalogError>;
+}
+
+/// 当前系统默认的采集方式
+pub fn default_collector() -> Box<dyn InventoryCollector> {
+    #[cfg(windows)]
+    {
+        Box::new(WindowsCollector)
+    }
+    #[cfg(not(windows))]
+    {
+        Box::new(SysfsCollector::default())
+    }
+}
+
+/// 与 catalog 中 `osArch` 相同的写法
+fn os_arch() -> Option<String> {
+    match std::env::consts::ARCH {
+        "x86_64" => Some("x64".into()),
+        "x86" => Some("x86".into()),
+        "aarch64" => Some("arm64".into()),
+        _ => None,
+    }
+}
+
+/// 从 json 文件读取, 用于测试和离线分析
+#[derive(Debug, Clone)]
+pub struct FixtureCollector {
+    pub path: PathBuf,
+}
+
+impl InventoryCollector for FixtureCollector {
+    fn collect(&self) -> Result<Inventory, CatalogError> {
+        let mut inventory = Inventory::from_path(&self.path)?;
+        inventory.collector = "fixture".into();
+        Ok(inventory)
+    }
+}
+
+/// Linux 上从 `/sys/bus/pci/devices` 和 `/sys/class/dmi/id` 读取, `root` 可以指向测试用的目录
+#[derive(Debug, Clone)]
+pub struct SysfsCollector {
+    pub root: PathBuf,
+}
+
+impl Default for SysfsCollector {
+    fn default() -> Self {
+        SysfsCollector { root: "/".into() }
+    }
+}
+
+/// 文件内容去掉空白, 不存在或为空时返回 `None`
+fn read_trimmed(path: &Path) -> Option<String> {
+    let text = fs::read_to_string(path).ok()?;
+    let text = text.trim();
+    (!text.is_empty()).then(|| text.to_string())
+}
+
+/// sysfs 中的 ID 形如 `0x8086`
+fn read_id(path: &Path) -> Option<String> {
+    let id = read_trimmed(path)?;
+    Some(id.trim_start_matches("0x").to_uppercase())
+}
+
+impl SysfsCollector {
+    fn read_pci_device(dir: &Path, inventory: &mut Inventory) {
+        let (Some(vendor_id), Some(device_id)) =
+            (read_id(&dir.join("vendor")), read_id(&dir.join("device")))
+        else {
+            return;
+        };
+        let device = PciDevice {
+            vendor_id,
+            device_id,
+            sub_vendor_id: read_id(&dir.join("subsystem_vendor")).unwrap_or_default(),
+            sub_device_id: read_id(&dir.join("subsystem_device")).unwrap_or_default(),
+        };
+        let hardware_id = format!(
+            r"PCI\VEN_{}&DEV_{}&SUBSYS_{}{}",
+            device.vendor_id, device.device_id, device.sub_device_id, device.sub_vendor_id
+        );
+        // driver 是指向 /sys/bus/pci/drivers/<名称> 的链接
+        let driver = fs::canonicalize(dir.join("driver"))
+            .ok()
+            .and_then(|path| Some(path.file_name()?.to_string_lossy().into_owned()));
+        if let Some(provider) = driver {
+            inventory.drivers.push(InstalledDriver {
+                device: device.to_string(),
+                hardware_id,
+                version: read_trimmed(&dir.join("driver/module/version")).unwrap_or_default(),
+                provider,
+            });
+        }
+        inventory.add_pci_device(device);
+    }
+}
+
+impl InventoryCollector for SysfsCollector {
+    fn collect(&self) -> Result<Inventory, CatalogError> {
+        let dmi = self.root.join("sys/class/dmi/id");
+        let mut inventory = Inventory {
+            collector: "sysfs".into(),
+            system_id: read_trimmed(&dmi.join("product_sku")),
+            manufacturer: read_trimmed(&dmi.join("sys_vendor")),
+            product_name: read_trimmed(&dmi.join("product_name")),
+            bios_version: read_trimmed(&dmi.join("bios_version")),
+            os_arch: os_arch(),
+            ..Default::default()
+        };
+        let pci_root = self.root.join("sys/bus/pci/devices");
+        let mut dirs: Vec<PathBuf> = match fs::read_dir(&pci_root) {
+            Ok(entries) => entries
+                .filter_map(Result::ok)
+                .map(|entry| entry.path())
+                .collect(),
+            Err(e) => {
+                log::warn!("SysfsCollector--{:?}: {}", pci_root, e);
+                Vec::new()
+            }
+        };
+        dirs.sort();
+        for dir in dirs.iter() {
+            Self::read_pci_device(dir, &mut inventory);
+        }
+        log::debug!(
+            "SysfsCollector--systemID {:?}, {} PCI devices",
+            inventory.system_id,
+            inventory.pci_devices.len()
+        );
+        Ok(inventory)
+    }
+}
+
+/// SetupAPI 中的一个设备
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PnpDevice {
+    pub description: String,
+    pub hardware_ids: Vec<String>,
+    /// `SPDRP_DRIVER`, 如 `{4d36e97d-e325-11ce-bfc1-08002be10318}\0012`
+    pub driver_key: Option<String>,
+}
+
+fn read_string(
+    registry: &dyn RegistryBackend,
+    key: &str,
+    name: &str,
+) -> Result<Option<String>, CatalogError> {
+    Ok(registry
+        .read_value(key, name)?
+        .and_then(|value| value.as_str().map(|s| s.trim().to_string()))
+        .filter(|s| !s.is_empty()))
+}
+
+/// 由注册表中的 BIOS 信息和 SetupAPI 列出的设备组成 `Inventory`
+pub fn inventory_from_devices(
+    registry: &dyn RegistryBackend,
+    devices: &[PnpDevice],
+) -> Result<Inventory, CatalogError> {
+    let mut inventory = Inventory {
+        collector: "windows".into(),
+        system_id: read_string(registry, BIOS_KEY, "SystemSKU")?,
+        manufacturer: read_string(registry, BIOS_KEY, "SystemManufacturer")?,
+        product_name: read_string(registry, BIOS_KEY, "SystemProductName")?,
+        bios_version: read_string(registry, BIOS_KEY, "BIOSVersion")?,
+        os_arch: os_arch(),
+        ..Default::default()
+    };
+    for device in devices.iter() {
+        for id in device.hardware_ids.iter() {
+            if !inventory.hardware_ids.contains(id) {
+                inventory.hardware_ids.push(id.clone());
+            }
+        }
+        // 第一个硬件 ID 最具体, 带有 SUBSYS
+        if let Some(pci) = device
+            .hardware_ids
+            .iter()
+            .find_map(|id| PciDevice::from_hardware_id(id))
+        {
+            inventory.add_pci_device(pci);
+        }
+        let Some(driver_key) = &device.driver_key else {
+            continue;
+        };
+        let key = format!(r"{}\{}", DRIVER_CLASS_KEY, driver_key);
+        let Some(version) = read_string(registry, &key, "DriverVersion")? else {
+            continue;
+        };
+        inventory.drivers.push(InstalledDriver {
+            device: device.description.clone(),
+            hardware_id: device.hardware_ids.first().cloned().unwrap_or_default(),
+            provider: read_string(registry, &key, "ProviderName")?.unwrap_or_default(),
+            version,
+        });
+    }
+    log::debug!(
+        "inventory_from_devices--systemID {:?}, {} devices, {} drivers",
+        inventory.system_id,
+        devices.len(),
+        inventory.drivers.len()
+    );
+    Ok(inventory)
+}
+
+/// Windows 上用 SetupAPI 列出当前存在的设备, 从注册表读取 BIOS 和驱动版本
+#[cfg(windows)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct WindowsCollector;
+
+#[cfg(windows)]
+impl InventoryCollector for WindowsCollector {
+    fn collect(&self) -> Result<Inventory, CatalogError> {
+        let devices = win::present_devices()?;
+        inventory_from_devices(&crate::registry::WindowsRegistry, &devices)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::registry::{MemoryRegistry, RegValue};
+
+    fn write(path: &Path, text: &str) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, text).unwrap();
+    }
+
+    #[test]
+    fn test_sysfs_collector() {
+        let root = tempfile::tempdir().unwrap();
+        let dmi = root.path().join("sys/class/dmi/id");
+        write(&dmi.join("product_sku"), "0CBB\n");
+        write(&dmi.join("sys_vendor"), "Dell Inc.\n");
+        write(&dmi.join("bios_version"), "1.8.0\n");
+        write(&dmi.join("product_name"), "\n");
+        let pci = root.path().join("sys/bus/pci/devices");
+        let me = pci.join("0000:00:16.0");
+        write(&me.join("vendor"), "0x8086\n");
+        write(&me.join("device"), "0x7e70\n");
+        write(&me.join("subsystem_vendor"), "0x1028\n");
+        write(&me.join("subsystem_device"), "0x0cbb\n");
+        write(&me.join("driver/module/version"), "5.0\n");
+        // 驱动目录名就是驱动名称, 真实的 sysfs 中是链接
+        let bridge = pci.join("0000:00:00.0");
+        write(&bridge.join("vendor"), "0x8086\n");
+        write(&bridge.join("device"), "0x7d01\n");
+        write(&pci.join("broken/vendor"), "0x8086\n");
+
+        let inventory = SysfsCollector {
+            root: root.path().to_path_buf(),
+        }
+        .collect()
+        .unwrap();
+        assert_eq!(inventory.system_id.as_deref(), Some("0CBB"));
+        assert_eq!(inventory.manufacturer.as_deref(), Some("Dell Inc."));
+        assert_eq!(inventory.bios_version.as_deref(), Some("1.8.0"));
+        assert_eq!(inventory.product_name, None);
+        let devices: Vec<String> = inventory
+            .pci_devices
+            .iter()
+            .map(|d| d.to_string())
+            .collect();
+        assert_eq!(devices, ["8086:7D01", "8086:7E70:1028:0CBB"]);
+        assert_eq!(inventory.drivers.len(), 1);
+        assert_eq!(
+            inventory.drivers[0].hardware_id,
+            r"PCI\VEN_8086&DEV_7E70&SUBSYS_0CBB1028"
+        );
+        assert_eq!(inventory.drivers[0].provider, "driver");
+        assert_eq!(inventory.drivers[0].version, "5.0");
+    }
+
+    #[test]
+    fn test_inventory_from_devices() {
+        let mut registry = MemoryRegistry::new();
+        registry
+            .write_value(BIOS_KEY, "SystemSKU", &RegValue::Sz("0CBB".into()))
+            .unwrap();
+        registry
+            .write_value(BIOS_KEY, "BIOSVersion", &RegValue::Sz("1.8.0".into()))
+            .unwrap();
+        let class = r"{4d36e97d-e325-11ce-bfc1-08002be10318}\0012";
+        let key = format!(r"{}\{}", DRIVER_CLASS_KEY, class);
+        registry
+            .write_value(&key, "DriverVersion", &RegValue::Sz("2406.5.5.0".into()))
+            .unwrap();
+        registry
+            .write_value(&key, "ProviderName", &RegValue::Sz("Intel".into()))
+            .unwrap();
+        let devices = [
+            PnpDevice {
+                description: "Intel(R) Management Engine Interface".into(),
+                hardware_ids: vec![
+                    r"PCI\VEN_8086&DEV_7E70&SUBSYS_0CBB1028&REV_20".into(),
+                    r"PCI\VEN_8086&DEV_7E70&SUBSYS_0CBB1028".into(),
+                    r"PCI\VEN_8086&DEV_7E70".into(),
+                ],
+                driver_key: Some(class.into()),
+            },
+            PnpDevice {
+                description: "Intel(R) iCLS Client".into(),
+                hardware_ids: vec![r"SWC\PROVIDER_INTEL_COMPONENT_ICLSCLIENT".into()],
+                driver_key: Some("missing".into()),
+            },
+        ];
+
+        let inventory = inventory_from_devices(&registry, &devices).unwrap();
+        assert_eq!(inventory.system_id.as_deref(), Some("0CBB"));
+        assert_eq!(inventory.manufacturer, None);
+        assert_eq!(inventory.pci_devices.len(), 1);
+        assert_eq!(inventory.hardware_ids.len(), 4);
+        assert_eq!(
+            inventory.drivers,
+            [InstalledDriver {
+                device: "Intel(R) Management Engine Interface".into(),
+                hardware_id: devices[0].hardware_ids[0].clone(),
+                provider: "Intel".into(),
+                version: "2406.5.5.0".into(),
+            }]
+        );
+        let hardware = inventory.hardware();
+        assert_eq!(hardware.pci_devices, inventory.pci_devices);
+        assert_eq!(hardware.system_id.as_deref(), Some("0CBB"));
+    }
+
+    #[test]
+    fn test_fixture_collector() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("inventory.json");
+        let inventory = Inventory {
+            collector: "sysfs".into(),
+            system_id: Some("0CBB".into()),
+            os_code: Some("W10P4".into()),
+            pci_devices: vec![PciDevice::parse("8086:7E70").unwrap()],
+            ..Default::default()
+        };
+        inventory.save(&path).unwrap();
+        let collected = FixtureCollector { path: path.clone() }.collect().unwrap();
+        assert_eq!(collected.collector, "fixture");
+        assert_eq!(collected.pci_devices, inventory.pci_devices);
+        // 适用性判断可以直接读取完整的 inventory 文件
+        assert_eq!(
+            HardwareInventory::from_path(&path).unwrap(),
+            inventory.hardware()
+        );
+    }
+}
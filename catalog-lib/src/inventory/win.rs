@@ -0,0 +1,115 @@
+use windows::{
+    core::PCWSTR,
+    Win32::{
+        Devices::DeviceAndDriverInstallation::{
+            SetupDiDestroyDeviceInfoList, SetupDiEnumDeviceInfo, SetupDiGetClassDevsW,
+            SetupDiGetDeviceRegistryPropertyW, DIGCF_ALLCLASSES, DIGCF_PRESENT, HDEVINFO,
+            SETUP_DI_REGISTRY_PROPERTY, SPDRP_DEVICEDESC, SPDRP_DRIVER, SPDRP_HARDWAREID,
+            SP_DEVINFO_DATA,
+        },
+        Foundation::{ERROR_NO_MORE_ITEMS, HWND},
+    },
+};
+
+use super::PnpDevice;
+use crate::error::CatalogError;
+
+/// 设备列表句柄, 离开作用域时释放
+struct DeviceInfoList(HDEVINFO);
+
+impl Drop for DeviceInfoList {
+    fn drop(&mut self) {
+        unsafe {
+            let _ = SetupDiDestroyDeviceInfoList(self.0);
+        }
+    }
+}
+
+/// 读取设备属性的原始字节, 属性不存在时返回 `None`
+fn property_bytes(
+    list: &DeviceInfoList,
+    data: &SP_DEVINFO_DATA,
+    property: SETUP_DI_REGISTRY_PROPERTY,
+) -> Option<Vec<u8>> {
+    let mut size = 0u32;
+    // 第一次调用只取所需的长度, 会返回 ERROR_INSUFFICIENT_BUFFER
+    let _ = unsafe {
+        SetupDiGetDeviceRegistryPropertyW(list.0, data, property, None, None, Some(&mut size))
+    };
+    if size == 0 {
+        return None;
+    }
+    let mut buffer = vec![0u8; size as usize];
+    unsafe {
+        SetupDiGetDeviceRegistryPropertyW(
+            list.0,
+            data,
+            property,
+            None,
+            Some(&mut buffer),
+            Some(&mut size),
+        )
+    }
+    .ok()?;
+    buffer.truncate(size as usize);
+    Some(buffer)
+}
+
+/// REG_SZ 或 REG_MULTI_SZ 中以 0 分隔的字符串
+fn utf16_strings(bytes: &[u8]) -> Vec<String> {
+    let units: Vec<u16> = bytes
+        .chunks_exact(2)
+        .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+        .collect();
+    units
+        .split(|&unit| unit == 0)
+        .filter(|part| !part.is_empty())
+        .map(String::from_utf16_lossy)
+        .collect()
+}
+
+fn property_string(
+    list: &DeviceInfoList,
+    data: &SP_DEVINFO_DATA,
+    property: SETUP_DI_REGISTRY_PROPERTY,
+) -> Option<String> {
+    property_bytes(list, data, property).and_then(|bytes| utf16_strings(&bytes).into_iter().next())
+}
+
+/// 当前存在的所有设备 (包括 `SWC\` 软件组件)
+pub fn present_devices() -> Result<Vec<PnpDevice>, CatalogError> {
+    let list = DeviceInfoList(unsafe {
+        SetupDiGetClassDevsW(
+            None,
+            PCWSTR::null(),
+            HWND::default(),
+            DIGCF_PRESENT | DIGCF_ALLCLASSES,
+        )
+    }?);
+    let mut devices = Vec::new();
+    for index in 0.. {
+        let mut data = SP_DEVINFO_DATA {
+            cbSize: std::mem::size_of::<SP_DEVINFO_DATA>() as u32,
+            ..Default::default()
+        };
+        if let Err(e) = unsafe { SetupDiEnumDeviceInfo(list.0, index, &mut data) } {
+            if e.code() == ERROR_NO_MORE_ITEMS.to_hresult() {
+                break;
+            }
+            return Err(e.into());
+        }
+        let hardware_ids = property_bytes(&list, &data, SPDRP_HARDWAREID)
+            .map(|bytes| utf16_strings(&bytes))
+            .unwrap_or_default();
+        if hardware_ids.is_empty() {
+            continue;
+        }
+        devices.push(PnpDevice {
+            description: property_string(&list, &data, SPDRP_DEVICEDESC).unwrap_or_default(),
+            hardware_ids,
+            driver_key: property_string(&list, &data, SPDRP_DRIVER),
+        });
+    }
+    log::debug!("present_devices--{}", devices.len());
+    Ok(devices)
+}
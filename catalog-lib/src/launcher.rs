@@ -0,0 +1,275 @@
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    detect::{client_info, install_dirs},
+    error::CatalogError,
+    registry::RegistryBackend,
+    Software,
+};
+
+/// 启动 DU/DCU 的一种方式
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", content = "target")]
+pub enum LaunchTarget {
+    /// 传统安装版的可执行文件
+    Executable(PathBuf),
+    /// 商店版 (UWP) 的 AppUserModelID
+    AppUserModelId(String),
+    /// Win+S 搜索并输入名称, 只在明确允许时使用
+    Keystroke(String),
+}
+
+/// 启动程序的接口, 测试中可以替换为模拟实现
+pub trait Launcher {
+    fn launch(&mut self, target: &LaunchTarget) -> Result<(), CatalogError>;
+}
+
+/// 按优先级列出启动客户端的方式: 卸载信息中的安装目录, 默认安装目录, 商店版, 最后是可选的按键方式
+pub fn launch_targets(
+    registry: &dyn RegistryBackend,
+    software: &Software,
+    keystroke_fallback: bool,
+) -> Result<Vec<LaunchTarget>, CatalogError> {
+    let info = client_info(software.kind());
+    let mut targets: Vec<LaunchTarget> = install_dirs(registry, software.kind())?
+        .into_iter()
+        .map(|dir| LaunchTarget::Executable(dir.join(info.exe_name)))
+        .collect();
+    targets.push(LaunchTarget::AppUserModelId(info.aumid.to_string()));
+    if keystroke_fallback {
+        targets.push(LaunchTarget::Keystroke(software.app_name().to_string()));
+    }
+    Ok(targets)
+}
+
+/// 依次尝试每种方式, 返回成功启动时使用的方式
+pub fn launch_software(
+    launcher: &mut dyn Launcher,
+    registry: &dyn RegistryBackend,
+    software: &Software,
+    keystroke_fallback: bool,
+) -> Result<LaunchTarget, CatalogError> {
+    let mut errors = Vec::new();
+    for target in launch_targets(registry, software, keystroke_fallback)? {
+        match launcher.launch(&target) {
+            Ok(()) => {
+                log::info!("launch_software--{:?}", target);
+                return Ok(target);
+            }
+            Err(e) => errors.push(format!("{:?}: {}", target, e)),
+        }
+    }
+    Err(CatalogError::LaunchError(format!(
+        "{}: {}",
+        software.app_name(),
+        errors.join("; ")
+    )))
+}
+
+/// 直接启动进程或激活商店应用
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemLauncher;
+
+impl SystemLauncher {
+    fn launch_executable(path: &Path) -> Result<(), CatalogError> {
+        if !path.is_file() {
+            return Err(CatalogError::LaunchError(format!(
+                "{:?} does not exist",
+                path
+            )));
+        }
+        std::process::Command::new(path).spawn()?;
+        Ok(())
+    }
+
+    #[cfg(windows)]
+    fn launch_app(aumid: &str) -> Result<(), CatalogError> {
+        use windows::{
+            core::PCWSTR,
+            Win32::{
+                System::Com::{
+                    CoCreateInstance, CoInitializeEx, CLSCTX_LOCAL_SERVER, COINIT_APARTMENTTHREADED,
+                },
+                UI::Shell::{ApplicationActivationManager, IApplicationActivationManager, AO_NONE},
+            },
+        };
+
+        let aumid: Vec<u16> = aumid.encode_utf16().chain(std::iter::once(0)).collect();
+        unsafe {
+            let _ = CoInitializeEx(None, COINIT_APARTMENTTHREADED);
+            let manager: IApplicationActivationManager =
+                CoCreateInstance(&ApplicationActivationManager, None, CLSCTX_LOCAL_SERVER)?;
+            manager.ActivateApplication(
+                PCWSTR::from_raw(aumid.as_ptr()),
+                PCWSTR::null(),
+                AO_NONE,
+            )?;
+        }
+        Ok(())
+    }
+
+    #[cfg(windows)]
+    fn launch_keystroke(name: &str) -> Result<(), CatalogError> {
+        use std::{thread, time::Duration};
+
+        use enigo::{Direction, Enigo, Key, Keyboard, Settings};
+
+        let error = |e: &dyn std::fmt::Display| CatalogError::LaunchError(e.to_string());
+        let mut enigo = Enigo::new(&Settings::default()).map_err(|e| error(&e))?;
+        enigo
+            .key(Key::Meta, Direction::Press)
+            .map_err(|e| error(&e))?;
+        enigo
+            .key(Key::Unicode('s'), Direction::Click)
+            .map_err(|e| error(&e))?;
+        enigo
+            .key(Key::Meta, Direction::Release)
+            .map_err(|e| error(&e))?;
+        thread::sleep(Duration::from_millis(500));
+        enigo.text(name).map_err(|e| error(&e))?;
+        thread::sleep(Duration::from_millis(500));
+        enigo
+            .key(Key::Return, Direction::Click)
+            .map_err(|e| error(&e))?;
+        Ok(())
+    }
+}
+
+impl Launcher for SystemLauncher {
+    fn launch(&mut self, target: &LaunchTarget) -> Result<(), CatalogError> {
+        match target {
+            LaunchTarget::Executable(path) => Self::launch_executable(path),
+            #[cfg(windows)]
+            LaunchTarget::AppUserModelId(aumid) => Self::launch_app(aumid),
+            #[cfg(windows)]
+            LaunchTarget::Keystroke(name) => Self::launch_keystroke(name),
+            #[cfg(not(windows))]
+            _ => Err(CatalogError::LaunchError(format!(
+                "{:?} is only available on Windows",
+                target
+            ))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        detect::UNINSTALL_KEYS,
+        registry::{MemoryRegistry, RegValue},
+    };
+
+    /// 只有 `available` 中的方式能启动成功
+    struct MockLauncher {
+        available: Vec<LaunchTarget>,
+        attempts: Vec<LaunchTarget>,
+    }
+
+    impl Launcher for MockLauncher {
+        fn launch(&mut self, target: &LaunchTarget) -> Result<(), CatalogError> {
+            self.attempts.push(target.clone());
+            if self.available.contains(target) {
+                Ok(())
+            } else {
+                Err(CatalogError::LaunchError("not found".into()))
+            }
+        }
+    }
+
+    fn dcu() -> Software {
+        Software::DellCommandUpdate {
+            app_name: "Dell Command Update".into(),
+        }
+    }
+
+    #[test]
+    fn test_launch_targets() {
+        let mut registry = MemoryRegistry::new();
+        let key = format!(
+            r"{}\{{5669AB71-1302-4412-8DA1-CB69CD7B7324}}",
+            UNINSTALL_KEYS[1]
+        );
+        registry
+            .write_value(
+                &key,
+                "DisplayName",
+                &RegValue::Sz("Dell Command | Update for Windows Universal".into()),
+            )
+            .unwrap();
+        registry
+            .write_value(
+                &key,
+                "InstallLocation",
+                &RegValue::Sz(r"D:\Apps\DCU".into()),
+            )
+            .unwrap();
+        let other = format!(r"{}\Other", UNINSTALL_KEYS[0]);
+        registry
+            .write_value(&other, "DisplayName", &RegValue::Sz("Dell Update".into()))
+            .unwrap();
+        registry
+            .write_value(
+                &other,
+                "InstallLocation",
+                &RegValue::Sz(r"D:\Apps\DU".into()),
+            )
+            .unwrap();
+
+        let targets = launch_targets(&registry, &dcu(), false).unwrap();
+        assert_eq!(
+            targets,
+            [
+                LaunchTarget::Executable(
+                    PathBuf::from(r"D:\Apps\DCU").join("DellCommandUpdate.exe")
+                ),
+                LaunchTarget::Executable(
+                    PathBuf::from(r"C:\Program Files\Dell\CommandUpdate")
+                        .join("DellCommandUpdate.exe")
+                ),
+                LaunchTarget::Executable(
+                    PathBuf::from(r"C:\Program Files (x86)\Dell\CommandUpdate")
+                        .join("DellCommandUpdate.exe")
+                ),
+                LaunchTarget::AppUserModelId("DellInc.DellCommandUpdate_htrsf667h5kn2!App".into()),
+            ]
+        );
+        assert_eq!(
+            launch_targets(&registry, &dcu(), true).unwrap().last(),
+            Some(&LaunchTarget::Keystroke("Dell Command Update".into()))
+        );
+    }
+
+    #[test]
+    fn test_launch_software() {
+        let registry = MemoryRegistry::new();
+        let app =
+            LaunchTarget::AppUserModelId("DellInc.DellCommandUpdate_htrsf667h5kn2!App".into());
+        let mut launcher = MockLauncher {
+            available: vec![app.clone()],
+            attempts: Vec::new(),
+        };
+        assert_eq!(
+            launch_software(&mut launcher, &registry, &dcu(), false).unwrap(),
+            app
+        );
+        assert_eq!(launcher.attempts.len(), 3);
+
+        let keystroke = LaunchTarget::Keystroke("Dell Command Update".into());
+        let mut launcher = MockLauncher {
+            available: vec![keystroke.clone()],
+            attempts: Vec::new(),
+        };
+        assert!(matches!(
+            launch_software(&mut launcher, &registry, &dcu(), false),
+            Err(CatalogError::LaunchError(_))
+        ));
+        assert!(!launcher.attempts.contains(&keystroke));
+        assert_eq!(
+            launch_software(&mut launcher, &registry, &dcu(), true).unwrap(),
+            keystroke
+        );
+    }
+}
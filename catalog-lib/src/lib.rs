@@ -1,39 +1,72 @@
+pub mod applicability;
+pub mod browse;
+pub mod cab;
+pub mod dcu;
+pub mod detect;
+pub mod diff;
+pub mod discover;
 pub mod error;
+pub mod filter;
+pub mod hash;
+pub mod ic;
+pub mod inventory;
+pub mod launcher;
+pub mod manifest;
+pub mod merge;
+pub mod registry;
+pub mod rewrite;
+pub mod server;
+pub mod stage;
+pub mod verify;
 // pub mod test_xml;
+#[cfg(windows)]
+use std::ptr::null_mut;
 use std::{
-    borrow::Cow,
     env::{self},
     ffi::OsStr,
-    fs::{copy, File},
-    io::{self, BufReader, BufWriter},
-    path::PathBuf,
-    process::Command,
-    ptr::null_mut,
+    fs::{self, File},
+    io::{BufReader, BufWriter, Read, Seek, Write},
+    path::{self, Path, PathBuf},
 };
 
-use data_encoding::BASE64;
+use cab::{Cabinet, CabinetBuilder, CompressionType};
+use dcu::ScanResult;
+#[cfg(windows)]
+use dcu::{scan_or_launch, DcuCli, DcuOptions, ProcessRunner};
+use detect::{
+    choose_client, detect_clients, ClientKind, InstalledClient, ServiceControl, ServiceQuery,
+    SystemServices,
+};
+use discover::{discover, DiscoverOptions, Discovery};
 use error::CatalogError;
+use filter::ComponentFilter;
+use hash::{CatalogHashValues, HashFormat};
+use ic::{IcInstallResult, IcInstaller, DEFAULT_SERVICE_DIR};
 use iced::Size;
-use sha3::{Digest, Sha3_384};
-use walkdir::WalkDir;
-use windows::{
-    core::PCWSTR,
-    Win32::{
-        Foundation::RECT,
-        System::{
-            Com::{
-                CoCreateInstance, CoInitializeEx, CoUninitialize, CLSCTX_INPROC_SERVER,
-                COINIT_APARTMENTTHREADED,
-            },
-            Registry::{self, HKEY, HKEY_LOCAL_MACHINE, KEY_ALL_ACCESS, REG_SZ, REG_VALUE_TYPE},
-        },
-        UI::{
-            Shell::{FileOpenDialog, IFileOpenDialog, SIGDN_FILESYSPATH},
-            WindowsAndMessaging::{GetDesktopWindow, GetForegroundWindow, GetWindowRect},
-        },
+#[cfg(windows)]
+use launcher::SystemLauncher;
+use manifest::{Manifest, ManifestHeader};
+use merge::{merge_manifests, MergeOptions, MergeReport};
+use registry::{RegValue, RegistryBackend, RegistrySnapshot, SERVICE_KEY};
+#[cfg(windows)]
+use registry::{SnapshotRegistry, WindowsRegistry};
+use rewrite::{RewriteRules, XmlText, RULES_FILE_NAME};
+use serde::Serialize;
+use server::{CatalogServer, ServeOptions};
+use stage::{stage_packages, MissingPackage, SourceIndex, StagedPackage, MISSING_FILE_NAME};
+use verify::PackageReport;
+#[cfg(windows)]
+use windows::Win32::{
+    Foundation::RECT,
+    System::Com::{
+        CoCreateInstance, CoInitializeEx, CoUninitialize, CLSCTX_INPROC_SERVER,
+        COINIT_APARTMENTTHREADED,
+    },
+    UI::{
+        Shell::{FileOpenDialog, IFileOpenDialog, SIGDN_FILESYSPATH},
+        WindowsAndMessaging::{GetDesktopWindow, GetForegroundWindow, GetWindowRect},
     },
 };
-use xml::{attribute::Attribute, reader::EventReader, writer::XmlEvent, EventWriter};
 
 #[derive(Debug, Default, Clone)]
 pub struct WindowSize {
@@ -42,6 +75,7 @@ pub struct WindowSize {
 }
 
 /// 获取桌面窗口的大小
+#[cfg(windows)]
 pub fn get_window_size() -> Size {
     // 获取桌面窗口句柄
     let desktop_window = unsafe { GetDesktopWindow() };
@@ -67,7 +101,15 @@ pub fn get_window_size() -> Size {
     }
 }
 
-fn filename_to_lower_string(file_path: &PathBuf) -> String {
+#[cfg(not(windows))]
+pub fn get_window_size() -> Size {
+    Size {
+        width: 800.0,
+        height: 600.0,
+    }
+}
+
+fn filename_to_lower_string(file_path: &Path) -> String {
     file_path
         .file_name()
         .and_then(OsStr::to_str)
@@ -76,15 +118,16 @@ fn filename_to_lower_string(file_path: &PathBuf) -> String {
         .to_lowercase()
 }
 
-pub fn is_cab_path(file_path: &PathBuf) -> bool {
+pub fn is_cab_path(file_path: &Path) -> bool {
     filename_to_lower_string(file_path).ends_with("cab")
 }
 
-pub fn is_ic_path(file_path: &PathBuf) -> bool {
+pub fn is_ic_path(file_path: &Path) -> bool {
     let filename = filename_to_lower_string(file_path);
     filename.ends_with("exe") && filename.contains("invc")
 }
 
+#[cfg(windows)]
 pub fn open_file_dialog() -> Result<PathBuf, CatalogError> {
     let res = unsafe {
         let _ = CoInitializeEx(Some(null_mut()), COINIT_APARTMENTTHREADED);
@@ -103,78 +146,110 @@ pub fn open_file_dialog() -> Result<PathBuf, CatalogError> {
     Ok(res)
 }
 
+#[cfg(not(windows))]
+pub fn open_file_dialog() -> Result<PathBuf, CatalogError> {
+    Err(CatalogError::SelectedFileError(
+        "File dialog is only available on Windows".into(),
+    ))
+}
+
 #[derive(Debug, Default, Clone)]
 pub struct CatalogInfo {
-    pub cab_path: PathBuf,
-    pub ic_path: PathBuf,
+    pub cab_path: Option<PathBuf>,
+    /// 为空时保留 DU/DCU 自带的 inventory collector
+    pub ic_path: Option<PathBuf>,
+    /// DU 和 DCU 都安装时用户选择的客户端, `None` 时自动选择
+    pub client: Option<ClientKind>,
+    /// 写入 CustomCatalogHashValues 的哈希格式
+    pub hash_format: HashFormat,
+    /// 更新包缺少或不一致时停止注册, 默认只在结果中提示
+    pub require_staged_packages: bool,
 }
 
-impl From<(PathBuf, PathBuf)> for CatalogInfo {
-    fn from(catalog_info: (PathBuf, PathBuf)) -> Self {
-        CatalogInfo {
-            cab_path: catalog_info.0,
-            ic_path: catalog_info.1,
-        }
+/// `handle` 完成后需要提示用户的信息
+#[derive(Debug, Default, Clone)]
+pub struct HandleReport {
+    /// catalog 旁边更新包的检查结果, `baseLocation` 指向其他位置时为 `None`
+    pub packages: Option<PackageReport>,
+    /// dcu-cli 的扫描结果, DU 或扫描失败时打开了客户端界面
+    pub scan: Option<ScanResult>,
+}
+
+/// 列出 `current_dir` 中所有的 catalog 和 inventory collector, 由调用方选择
+pub async fn get_catalog_and_ic_paths(
+    current_dir: PathBuf,
+    options: DiscoverOptions,
+) -> Result<Discovery, CatalogError> {
+    log::debug!("{:?}", current_dir);
+    discover(&current_dir, &options)
+}
+
+/// cab 中第一个 xml 文件的名称
+fn catalog_entry_name<R: Read + Seek>(cabinet: &Cabinet<R>) -> Result<String, CatalogError> {
+    cabinet
+        .entries()
+        .iter()
+        .map(|entry| entry.name.clone())
+        .find(|name| name.to_lowercase().ends_with(".xml"))
+        .ok_or_else(|| CatalogError::CurrentFileError(".xml".into()))
+}
+
+/// 从 cab 中解压 catalog xml 到 `output_dir`, 返回解压后的实际路径
+pub fn cab_to_xml(cab_path: &Path, output_dir: &Path) -> Result<PathBuf, CatalogError> {
+    let mut cabinet = Cabinet::open(cab_path)?;
+    let xml_name = catalog_entry_name(&cabinet)?;
+    let xml_path = cabinet.extract_entry(&xml_name, output_dir)?;
+    log::debug!("cab_to_xml--{:?}", xml_path);
+    Ok(xml_path)
+}
+
+/// 把改写后的 catalog xml 打包成同名的 cab, 放在 xml 旁边
+pub fn xml_to_cab(xml_path: &Path, compression: CompressionType) -> Result<PathBuf, CatalogError> {
+    let cab_path = xml_path.with_extension("cab");
+    CabinetBuilder::new(compression)
+        .add_path(xml_path)?
+        .write_to_path(&cab_path)?;
+    log::debug!("xml_to_cab--{:?}", cab_path);
+    Ok(cab_path)
+}
+
+/// 读取 catalog, 可以是 xml 也可以是 cab
+pub fn load_manifest(path: &Path) -> Result<Manifest, CatalogError> {
+    if is_cab_path(path) {
+        let mut cabinet = Cabinet::open(path)?;
+        let xml_name = catalog_entry_name(&cabinet)?;
+        let data = cabinet.read_entry(&xml_name)?;
+        Manifest::from_reader(data.as_slice())
+    } else {
+        Manifest::from_path(path)
     }
 }
-pub async fn get_catalog_and_ic_paths(current_dir: PathBuf) -> Result<CatalogInfo, CatalogError> {
-    println!("{:?}", current_dir);
-    let mut cab_files = Vec::new();
-    let mut exe_files = Vec::new();
-    for entry in WalkDir::new(current_dir)
-        .into_iter()
-        .filter_map(Result::ok)
-        .filter(|e| !e.file_type().is_dir())
-    {
-        let file_path = entry.path().to_path_buf();
-        // let f_name = String::from(entry.path().to_string_lossy());
-        if is_cab_path(&file_path) {
-            cab_files.push(file_path);
-        } else if is_ic_path(&file_path) {
-            exe_files.push(file_path);
-        }
+
+/// 只读取 catalog 根元素上的版本信息
+pub fn load_manifest_header(path: &Path) -> Result<ManifestHeader, CatalogError> {
+    if is_cab_path(path) {
+        let mut cabinet = Cabinet::open(path)?;
+        let xml_name = catalog_entry_name(&cabinet)?;
+        let data = cabinet.read_entry(&xml_name)?;
+        ManifestHeader::from_reader(data.as_slice())
+    } else {
+        ManifestHeader::from_reader(BufReader::new(File::open(path)?))
     }
-    println!("get_catalog_and_ic_paths--{:?}-{:?}", cab_files, exe_files);
-    match (cab_files.as_slice(), exe_files.as_slice()) {
-        ([cab_file], [exe_file]) => Ok((cab_file.clone(), exe_file.clone()).into()),
-        ([cab_file], []) => Ok((cab_file.clone(), "No invc.exe file found".into()).into()),
-        ([], [exe_file]) => Ok(("No .cab file found".into(), exe_file.clone()).into()),
-        ([], []) => Err(CatalogError::CurrentFileError(
-            "No .cab file and invc.exe file found".into(),
-        )),
-        (cab_files, exe_files) if cab_files.len() > 1 || exe_files.len() > 1 => Err(
-            CatalogError::CurrentFileError("Multiple .cab and invc.exe files found".into()),
-        ),
-        (_, _) => Err(CatalogError::Unexpected),
-    }
-}
-
-pub fn cab_to_xml(cab_path: &PathBuf) -> Result<PathBuf, CatalogError> {
-    let cmd_str = format!("expand.exe -R {}", cab_path.to_string_lossy());
-    println!("cab_to_xml-cmd_str: {}", cmd_str);
-    let output = Command::new("cmd")
-        // .creation_flags(CREATE_NO_WINDOW.0)
-        .arg("/c")
-        .arg(cmd_str)
-        .output()
-        .map_err(|e| io::Error::new(io::ErrorKind::Other, format!("cmd exec error: {}", e)))?;
-
-    if !output.status.success() {
-        return Err(CatalogError::ParseError("cmd command failed".into()));
-    }
-
-    let xml_path = format!(
-        "{}.xml",
-        filename_to_lower_string(cab_path).trim_end_matches(".cab")
-    );
-    Ok(xml_path.into())
 }
 
-fn handle_xml(xml_path: PathBuf) -> Result<PathBuf, CatalogError> {
+/// 读取 catalog 旁边的改写规则, 没有时使用默认规则
+pub fn load_rules_near(path: &Path) -> Result<RewriteRules, CatalogError> {
+    let dir = path
+        .parent()
+        .map(Path::to_path_buf)
+        .unwrap_or_else(get_cur_path);
+    RewriteRules::load_or_default(&dir.join(RULES_FILE_NAME))
+}
+
+/// 按改写规则修改 xml, 保存为同目录下的 `_<文件名>`
+pub fn handle_xml(xml_path: PathBuf, rules: &RewriteRules) -> Result<PathBuf, CatalogError> {
     // println!("handle_xml--{:?}", xml_path);
-    let input_file = File::open(&xml_path)?;
-    let input_reader = BufReader::new(input_file);
-    let reader = EventReader::new(input_reader);
+    let mut document = XmlText::from_path(&xml_path)?;
     let mut output_xml_path = xml_path.parent().unwrap().to_path_buf();
     let file_name = xml_path.file_name().unwrap();
     output_xml_path.push(Into::<PathBuf>::into(format!(
@@ -182,302 +257,612 @@ fn handle_xml(xml_path: PathBuf) -> Result<PathBuf, CatalogError> {
         file_name.to_string_lossy()
     )));
     // println!("output_xml_path--{:?}", output_xml_path);
-    let output_file = File::create(&output_xml_path)?;
-    let output_writer = BufWriter::new(output_file);
-    let mut event_writer = EventWriter::new(BufWriter::new(output_writer));
-
-    for event in reader {
-        match event {
-            Ok(event) => {
-                if let Some(w_event) = event.as_writer_event() {
-                    match w_event {
-                        XmlEvent::StartElement {
-                            name,
-                            ref attributes,
-                            ref namespace,
-                        } => {
-                            let mut new_attributes = Vec::new();
-                            // println!("StartElement-{}", name.local_name);
-                            if name.local_name == "Manifest" {
-                                // in_maniface = true;
-                                new_attributes.clear();
-                                for attr in attributes.iter() {
-                                    if attr.name.local_name == "baseLocation" {
-                                        new_attributes.push(Attribute::new(attr.name, ""));
-                                    } else {
-                                        new_attributes.push(*attr);
-                                    }
-                                }
-                                match event_writer.write(XmlEvent::StartElement {
-                                    name,
-                                    attributes: Cow::Owned(new_attributes),
-                                    namespace: namespace.clone(),
-                                }) {
-                                    Ok(_) => {}
-                                    Err(e) => {
-                                        eprintln!("Error: StartElement-Manifest--{}", e)
-                                    }
-                                };
-                            } else if name.local_name == "SoftwareComponent" {
-                                // in_software = true;
-                                // 修改 path 属性
-                                new_attributes.clear();
-                                for attr in attributes.iter() {
-                                    if attr.name.local_name == "path" {
-                                        let new_value =
-                                            attr.value.split("/").nth(2).unwrap_or_default();
-                                        println!("SoftwareComponent-new_value--{}", new_value);
-                                        new_attributes.push(Attribute::new(attr.name, new_value));
-                                    } else {
-                                        new_attributes.push(*attr);
-                                    }
-                                }
-                                match event_writer.write(XmlEvent::StartElement {
-                                    name,
-                                    attributes: Cow::Owned(new_attributes),
-                                    namespace: namespace.clone(),
-                                }) {
-                                    Ok(_) => {}
-                                    Err(e) => {
-                                        eprintln!("Error: StartElement-SoftwareComponent--{}", e)
-                                    }
-                                };
-                            } else {
-                                match event_writer.write(w_event) {
-                                    Ok(_) => {}
-                                    Err(e) => eprintln!("Error: StartElement-else--{}", e),
-                                };
-                            }
-                        }
-                        XmlEvent::EndElement { name: _ } => {
-                            match event_writer.write(w_event) {
-                                Ok(_) => {}
-                                Err(e) => {
-                                    eprintln!("Error: EndElement-else-event{}", e)
-                                }
-                            };
-                        }
-                        _ => {
-                            match event_writer.write(w_event) {
-                                Ok(_) => {}
-                                Err(e) => panic!("Write error: {e}"),
-                            };
-                        }
-                    }
-                }
-            }
-            Err(e) => eprintln!("match event: {}", e),
+    document.text = rules.apply(&document.text)?;
+    if let Some(filter) = &rules.filter {
+        let outcome = filter.apply(&document.text)?;
+        if outcome.kept.is_empty() {
+            log::warn!("handle_xml--warning: filter removed every component");
         }
+        document.text = outcome.text;
     }
+    document.write_to_path(&output_xml_path)?;
     Ok(output_xml_path)
 }
 
-fn str_to_pcwstr(s: &str) -> PCWSTR {
-    let result = s
-        .encode_utf16()
-        .chain(std::iter::once(0))
-        .collect::<Vec<u16>>();
-    PCWSTR::from_raw(result.as_ptr())
+/// 合并后的 catalog 及其哈希
+#[derive(Debug, Clone, Serialize)]
+pub struct MergedCatalog {
+    pub xml_path: PathBuf,
+    pub cab_path: PathBuf,
+    pub hash_values: CatalogHashValues,
+    pub identifier: String,
+    pub version: String,
+    pub date_time: String,
+    pub report: MergeReport,
 }
 
-pub fn open_reg_subkey(sub_key: &str) -> Result<HKEY, String> {
-    let mut new_key: HKEY = HKEY::default();
-    let _sub_key = str_to_pcwstr(sub_key);
-    let res = unsafe {
-        Registry::RegOpenKeyExW(
-            HKEY_LOCAL_MACHINE,
-            _sub_key,
-            0,
-            KEY_ALL_ACCESS,
-            &mut new_key,
-        )
+/// 合并多个 catalog (cab 或 xml), 写出 `xml_path` 和同名的 cab 并计算哈希
+pub fn merge_catalogs(
+    catalog_paths: &[PathBuf],
+    xml_path: &Path,
+    options: &MergeOptions,
+    format: HashFormat,
+) -> Result<MergedCatalog, CatalogError> {
+    let manifests = catalog_paths
+        .iter()
+        .map(|path| load_manifest(path))
+        .collect::<Result<Vec<_>, _>>()?;
+    let (merged, report) = merge_manifests(&manifests, options);
+    let xml_path = path::absolute(xml_path)?;
+    let mut writer = BufWriter::new(File::create(&xml_path)?);
+    merged.write_to(&mut writer)?;
+    writer.flush()?;
+    let cab_path = xml_to_cab(&xml_path, CompressionType::MsZip)?;
+    let mut hash_values = CatalogHashValues::default();
+    hash_values.add_catalog_with(&xml_path, format)?;
+    Ok(MergedCatalog {
+        xml_path,
+        cab_path,
+        hash_values,
+        identifier: merged.identifier,
+        version: merged.version,
+        date_time: merged.date_time,
+        report,
+    })
+}
+
+/// 过滤后的 catalog 及其哈希
+#[derive(Debug, Clone, Serialize)]
+pub struct FilteredCatalog {
+    pub xml_path: PathBuf,
+    pub cab_path: PathBuf,
+    pub hash_values: CatalogHashValues,
+    pub kept: Vec<String>,
+    pub removed: Vec<String>,
+    pub warnings: Vec<String>,
+}
+
+/// 按条件删除 catalog (cab 或 xml) 中的组件, 在 `output_dir` 中写出 `<文件名>_filtered.xml/.cab` 并重新计算哈希
+pub fn filter_catalog(
+    catalog_path: &Path,
+    filter: &ComponentFilter,
+    output_dir: &Path,
+    format: HashFormat,
+) -> Result<FilteredCatalog, CatalogError> {
+    let xml_path = if is_cab_path(catalog_path) {
+        cab_to_xml(catalog_path, output_dir)?
+    } else {
+        catalog_path.to_path_buf()
+    };
+    let mut document = XmlText::from_path(&xml_path)?;
+    let outcome = filter.apply(&document.text)?;
+    let mut warnings = Vec::new();
+    if outcome.kept.is_empty() {
+        warnings.push("filter removed every component, the catalog is empty".to_string());
+    }
+    let stem = xml_path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let filtered_xml_path = path::absolute(output_dir.join(format!("{}_filtered.xml", stem)))?;
+    document.text = outcome.text;
+    document.write_to_path(&filtered_xml_path)?;
+    let cab_path = xml_to_cab(&filtered_xml_path, CompressionType::MsZip)?;
+    let mut hash_values = CatalogHashValues::default();
+    hash_values.add_catalog_with(&filtered_xml_path, format)?;
+    for warning in warnings.iter() {
+        log::warn!("filter_catalog--warning: {}", warning);
     }
-    .is_ok();
-    if res {
-        Ok(new_key)
+    Ok(FilteredCatalog {
+        xml_path: filtered_xml_path,
+        cab_path,
+        hash_values,
+        kept: outcome.kept,
+        removed: outcome.removed,
+        warnings,
+    })
+}
+
+/// 离线仓库: 改写后的 catalog, 哈希和已经放好的更新包
+#[derive(Debug, Clone, Serialize)]
+pub struct StagedRepository {
+    pub xml_path: PathBuf,
+    pub cab_path: PathBuf,
+    pub hash_values: CatalogHashValues,
+    pub staged: Vec<StagedPackage>,
+    pub missing: Vec<MissingPackage>,
+    /// 缺少的更新包清单, 没有缺少时内容为空列表
+    pub missing_path: PathBuf,
+}
+
+/// 在 `output_dir` 中建立离线仓库: 改写 catalog, 从 `sources` 中找到每个更新包并按改写后的 `path` 放置
+pub fn stage_repository(
+    catalog_path: &Path,
+    sources: &[PathBuf],
+    output_dir: &Path,
+    rules: &RewriteRules,
+    format: HashFormat,
+) -> Result<StagedRepository, CatalogError> {
+    fs::create_dir_all(output_dir)?;
+    let output_dir = path::absolute(output_dir)?;
+    let original = load_manifest(catalog_path)?;
+    let xml_path = if is_cab_path(catalog_path) {
+        cab_to_xml(catalog_path, &output_dir)?
     } else {
-        Err(format!("Failed to turn on the \"{}\" key", sub_key))
-    }
-}
-
-pub fn set_reg_vaule(
-    sub_key: HKEY,
-    value_name: &str,
-    dw_type: REG_VALUE_TYPE,
-    value: &str,
-) -> bool {
-    let _value_name = str_to_pcwstr(value_name);
-    let value = value
-        .encode_utf16()
-        .into_iter()
-        .flat_map(|x| x.to_le_bytes())
-        .collect::<Vec<u8>>();
-    println!("set_reg_vaule--{:?}", value);
-    let res = unsafe { Registry::RegSetValueExW(sub_key, _value_name, 0, dw_type, Some(&value)) };
-    if res.is_ok() {
-        unsafe {
-            let _ = Registry::RegCloseKey(sub_key);
-        };
-        true
+        // 改写后的 xml 写在原 xml 旁边, 先复制到仓库中
+        let target = output_dir.join(catalog_path.file_name().unwrap_or_default());
+        if path::absolute(catalog_path)? != target {
+            fs::copy(catalog_path, &target)?;
+        }
+        target
+    };
+    let rewritten_xml_path = handle_xml(xml_path, rules)?;
+    let cab_path = xml_to_cab(&rewritten_xml_path, CompressionType::MsZip)?;
+    let mut hash_values = CatalogHashValues::default();
+    hash_values.add_catalog_with(&rewritten_xml_path, format)?;
+
+    let rewritten = Manifest::from_path(&rewritten_xml_path)?;
+    let report = stage_packages(
+        &original,
+        &rewritten,
+        &SourceIndex::build(sources),
+        &output_dir,
+    )?;
+    let missing_path = output_dir.join(MISSING_FILE_NAME);
+    fs::write(
+        &missing_path,
+        serde_json::to_string_pretty(&report.missing)?,
+    )?;
+    Ok(StagedRepository {
+        xml_path: rewritten_xml_path,
+        cab_path,
+        hash_values,
+        staged: report.staged,
+        missing: report.missing,
+        missing_path,
+    })
+}
+
+/// 通过本地 HTTP 服务提供的 catalog
+#[derive(Debug, Clone, Serialize)]
+pub struct ServedCatalog {
+    pub base_url: String,
+    /// 改写后的 cab 的下载地址
+    pub catalog_url: String,
+    pub xml_path: PathBuf,
+    pub cab_path: PathBuf,
+    pub hash_values: CatalogHashValues,
+}
+
+/// 在 catalog (cab 或 xml) 所在目录启动 HTTP 服务, 改写 catalog 让 `baseLocation` 指向服务地址
+///
+/// 返回的服务还没有开始处理请求, 由调用方 `run` 或 `spawn`
+pub fn serve_catalog(
+    catalog_path: &Path,
+    rules: &RewriteRules,
+    options: &ServeOptions,
+    format: HashFormat,
+) -> Result<(ServedCatalog, CatalogServer), CatalogError> {
+    let catalog_path = path::absolute(catalog_path)?;
+    let root = catalog_path
+        .parent()
+        .map(Path::to_path_buf)
+        .unwrap_or_else(get_cur_path);
+    // 先监听才能知道系统分配的端口
+    let server = CatalogServer::bind(&root, options)?;
+    let rules = rules.clone().with_base_location(server.base_url());
+    let xml_path = if is_cab_path(&catalog_path) {
+        cab_to_xml(&catalog_path, &root)?
     } else {
-        eprintln!("set_reg_vaule--error");
-        false
-    }
+        catalog_path
+    };
+    let rewritten_xml_path = handle_xml(xml_path, &rules)?;
+    let cab_path = xml_to_cab(&rewritten_xml_path, CompressionType::MsZip)?;
+    let mut hash_values = CatalogHashValues::default();
+    hash_values.add_catalog_with(&rewritten_xml_path, format)?;
+    let catalog_url = format!(
+        "{}{}",
+        server.base_url(),
+        cab_path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default()
+    );
+    let served = ServedCatalog {
+        base_url: server.base_url().to_string(),
+        catalog_url,
+        xml_path: rewritten_xml_path,
+        cab_path,
+        hash_values,
+    };
+    Ok((served, server))
 }
 
-pub fn delete_reg_key_vaule(key: HKEY, sub_key: Option<&str>, value_names: Vec<&str>) {
-    match sub_key {
-        Some(_sub_key) => unsafe {
-            let _ = Registry::RegDeleteKeyExW(key, str_to_pcwstr(_sub_key), 0x0100, 0);
-        },
-        None => {
-            for lpvaluename in value_names {
-                unsafe {
-                    let _ = Registry::RegDeleteValueW(key, str_to_pcwstr(lpvaluename));
-                };
-            }
+/// 服务配置中安装新 catalog 后需要清除的时间戳
+const SERVICE_TIMESTAMP_VALUES: [&str; 3] = [
+    "LastCheckTimestamp",
+    "LastUpdateTimestamp",
+    "CatalogTimestamp",
+];
+
+/// 写入 catalog 哈希 (与已有的合并), 清除忽略列表和时间戳, 并让 DU/DCU 使用自定义 catalog
+pub fn handle_reg(
+    registry: &mut dyn RegistryBackend,
+    hash_values: &CatalogHashValues,
+    software: &Software,
+) -> Result<(), CatalogError> {
+    log::debug!("handle_reg--{}", software.app_name());
+    // 已有的值无法解析时不能覆盖, 否则其他已注册的 catalog 都会丢失
+    let mut merged = match registry.read_value(SERVICE_KEY, "CustomCatalogHashValues")? {
+        Some(RegValue::Sz(json)) => {
+            CatalogHashValues::from_json(&json).map_err(|e| CatalogError::RegistryValue {
+                name: "CustomCatalogHashValues".into(),
+                reason: e.to_string(),
+            })?
         }
+        _ => CatalogHashValues::default(),
+    };
+    merged.merge(hash_values);
+    let str_hash = merged.to_json()?;
+    log::debug!("handle_reg--{} catalogs registered", merged.values.len());
+    registry.write_value(
+        SERVICE_KEY,
+        "CustomCatalogHashValues",
+        &RegValue::Sz(str_hash),
+    )?;
+    registry.delete_tree(&format!(r"{}\IgnoreList", SERVICE_KEY))?;
+    for name in SERVICE_TIMESTAMP_VALUES {
+        registry.delete_value(SERVICE_KEY, name)?;
+    }
+    handle_client_settings(registry, hash_values, software)
+}
+
+/// 写入客户端的 CustomCatalogPaths 和 EnableCatalogXML, DCU 还要关闭默认的 Dell catalog
+///
+/// CustomCatalogPaths 只包含这次安装的 catalog, 原来的值由注册表快照保存
+fn handle_client_settings(
+    registry: &mut dyn RegistryBackend,
+    hash_values: &CatalogHashValues,
+    software: &Software,
+) -> Result<(), CatalogError> {
+    let settings_key = software.settings_key();
+    let paths: Vec<String> = hash_values.values.iter().map(|v| v.key.clone()).collect();
+    log::debug!("handle_client_settings--{}--{:?}", settings_key, paths);
+    registry.write_value(
+        &settings_key,
+        "CustomCatalogPaths",
+        &RegValue::MultiSz(paths),
+    )?;
+    registry.write_value(&settings_key, "EnableCatalogXML", &RegValue::Dword(1))?;
+    if let Software::DellCommandUpdate { .. } = software {
+        registry.write_value(
+            &settings_key,
+            "EnableDefaultDellCatalog",
+            &RegValue::Dword(0),
+        )?;
     }
+    Ok(())
+}
+
+/// 已经解压, 改写, 重新打包并计算了哈希的 catalog
+#[derive(Debug, Clone, Serialize)]
+pub struct PreparedCatalog {
+    pub xml_path: PathBuf,
+    pub rewritten_xml_path: PathBuf,
+    pub cab_path: PathBuf,
+    pub hash_values: CatalogHashValues,
+}
+
+/// 依次执行解压, 改写, 打包和按 `format` 计算哈希, 输出都放在 cab 所在的目录
+pub fn prepare_catalog(
+    cab_path: &Path,
+    rules: &RewriteRules,
+    format: HashFormat,
+) -> Result<PreparedCatalog, CatalogError> {
+    // 注册表中要记录绝对路径
+    let cab_path = path::absolute(cab_path)?;
+    let output_dir = cab_path
+        .parent()
+        .map(Path::to_path_buf)
+        .unwrap_or_else(get_cur_path);
+    let xml_path = cab_to_xml(&cab_path, &output_dir)?;
+    let rewritten_xml_path = handle_xml(xml_path.clone(), rules)?;
+    let new_cab_path = xml_to_cab(&rewritten_xml_path, CompressionType::MsZip)?;
+    let mut hash_values = CatalogHashValues::default();
+    hash_values.add_catalog_with(&rewritten_xml_path, format)?;
+    Ok(PreparedCatalog {
+        xml_path,
+        rewritten_xml_path,
+        cab_path: new_cab_path,
+        hash_values,
+    })
 }
 
-pub fn get_hash_sha384(xml_path: PathBuf) -> Result<String, std::io::Error> {
-    let file = File::open(&xml_path)?;
-    let mut reader = BufReader::new(file);
-    let mut hasher = Sha3_384::new();
-    let _ = io::copy(&mut reader, &mut hasher);
-    let hash = hasher.finalize();
-    let base64 = BASE64.encode(&hash[..]);
-    let base64_str = match base64.strip_suffix("=") {
-        Some(temp) => format!(
-            r#"{{"CatalogHashValues":[{{"Key":{:?},"Value":"{}"}}]}}"#,
-            xml_path, temp
-        ),
-        None => format!(
-            r#"{{"CatalogHashValues":[{{"Key":{:?},"Value":"{}"}}]}}"#,
-            xml_path, base64
-        ),
+/// 检查 catalog 引用的更新包, `dir` 为空时检查 catalog 所在的目录
+pub fn verify_staged_packages(
+    catalog_path: &Path,
+    dir: Option<&Path>,
+) -> Result<PackageReport, CatalogError> {
+    let manifest = load_manifest(catalog_path)?;
+    let dir = match dir {
+        Some(dir) => dir.to_path_buf(),
+        None => catalog_path
+            .parent()
+            .map(Path::to_path_buf)
+            .unwrap_or_else(get_cur_path),
     };
-    println!("get_hash_sha384--{}", base64_str);
-    Ok(base64_str)
-}
-
-pub fn handle_reg(str_hash: &str, software: &Software) {
-    match software {
-        Software::DellUpdate { app_name } => {
-            let service_key = open_reg_subkey(r#"SOFTWARE\Dell\UpdateService\Service"#).unwrap();
-            set_reg_vaule(service_key, "CustomCatalogHashValues", REG_SZ, str_hash);
-            let service_vaule = vec![
-                "LastCheckTimestamp",
-                "LastUpdateTimestamp",
-                "CatalogTimestamp",
-                "CatalogTimestamp",
-            ];
-            delete_reg_key_vaule(service_key, Some("IgnoreList"), service_vaule.clone());
-            delete_reg_key_vaule(service_key, None, service_vaule);
-            open_software(app_name);
-        }
-        Software::DellCommandUpdate { app_name } => {
-            let service_key = open_reg_subkey(r#"SOFTWARE\Dell\UpdateService\Service"#).unwrap();
-            println!("handle_reg--{}", str_hash);
-            set_reg_vaule(service_key, "CustomCatalogHashValues", REG_SZ, str_hash);
-            let service_vaule = vec![
-                "LastCheckTimestamp",
-                "LastUpdateTimestamp",
-                "CatalogTimestamp",
-                "CatalogTimestamp",
-            ];
-            delete_reg_key_vaule(service_key, Some("IgnoreList"), service_vaule.clone());
-            delete_reg_key_vaule(service_key, None, service_vaule);
-            open_software(app_name);
-        }
+    verify::verify_packages(&manifest, &dir)
+}
+
+/// `baseLocation` 为空时 DU 从 catalog 所在目录读取更新包, 检查这些更新包
+///
+/// `baseLocation` 指向其他位置时不检查, 返回 `None`
+pub fn staged_package_report(catalog_path: &Path) -> Result<Option<PackageReport>, CatalogError> {
+    let manifest = load_manifest(catalog_path)?;
+    if !manifest.base_location.is_empty() {
+        return Ok(None);
+    }
+    let dir = catalog_path
+        .parent()
+        .map(Path::to_path_buf)
+        .unwrap_or_else(get_cur_path);
+    let report = verify::verify_packages(&manifest, &dir)?;
+    if !report.extra.is_empty() {
+        log::warn!("staged_package_report--extra packages {:?}", report.extra);
     }
+    Ok(Some(report))
 }
 
-pub fn open_software(name: &String) {
-    let mut enigo = Enigo::new();
+/// 注册前的检查: 和 `staged_package_report` 相同, 但缺少或不一致时报错
+pub fn ensure_staged_packages(catalog_path: &Path) -> Result<Option<PackageReport>, CatalogError> {
+    let report = staged_package_report(catalog_path)?;
+    match &report {
+        Some(report) if !report.is_ok() => Err(CatalogError::PackageMismatch(report.summary())),
+        _ => Ok(report),
+    }
+}
 
-    enigo.key_down(Key::Meta); // 按下 Windows 键
-    enigo.key_click(Key::Layout('s')); // 按下 S 键
-    enigo.key_up(Key::Meta); // 释放 Windows 键
+/// `service_dir` 为空时使用 DU/DCU 的默认安装目录, 只在 Windows 上可用
+fn ic_installer<'a>(
+    service_dir: Option<&Path>,
+    services: &'a mut dyn ServiceControl,
+) -> Result<IcInstaller<'a>, CatalogError> {
+    match service_dir {
+        Some(dir) => Ok(IcInstaller::new(dir, services)),
+        #[cfg(windows)]
+        None => Ok(IcInstaller::new(DEFAULT_SERVICE_DIR, services)),
+        #[cfg(not(windows))]
+        None => Err(CatalogError::InstallError(format!(
+            "{}: only available on Windows, pass a service directory instead",
+            DEFAULT_SERVICE_DIR
+        ))),
+    }
+}
 
-    thread::sleep(Duration::from_millis(500));
+/// 用选择的 inventory collector 替换 DU/DCU 自带的版本
+pub fn install_ic(
+    ic_path: &Path,
+    service_dir: Option<&Path>,
+    services: &mut dyn ServiceControl,
+) -> Result<IcInstallResult, CatalogError> {
+    ic_installer(service_dir, services)?.install(ic_path)
+}
 
-    enigo.key_sequence(app_name);
+/// 还原 `install_ic` 备份的原始 inventory collector
+pub fn restore_ic(
+    service_dir: Option<&Path>,
+    services: &mut dyn ServiceControl,
+) -> Result<IcInstallResult, CatalogError> {
+    ic_installer(service_dir, services)?.restore()
+}
 
-    thread::sleep(Duration::from_millis(500));
+/// 选择可用的 DU/DCU 并写入 catalog 相关的注册表
+pub fn install_catalog(
+    registry: &mut dyn RegistryBackend,
+    services: &dyn ServiceQuery,
+    preferred: Option<ClientKind>,
+    hash_values: &CatalogHashValues,
+) -> Result<InstalledClient, CatalogError> {
+    let client = choose_client(registry, services, preferred)?;
+    handle_reg(registry, hash_values, &client.software())?;
+    Ok(client)
+}
 
-    enigo.key_click(Key::Return);
+/// 先选择并检查客户端, 再替换 inventory collector 和写入注册表
+///
+/// 注册表写入失败时还原 inventory collector, 注册表快照不包含这个文件
+pub fn install_catalog_with_ic<S: ServiceControl>(
+    registry: &mut dyn RegistryBackend,
+    services: &mut S,
+    preferred: Option<ClientKind>,
+    hash_values: &CatalogHashValues,
+    ic_path: Option<&Path>,
+    service_dir: Option<&Path>,
+) -> Result<(InstalledClient, Option<IcInstallResult>), CatalogError> {
+    let client = choose_client(registry, services, preferred)?;
+    let ic = match ic_path {
+        Some(ic_path) => Some(install_ic(ic_path, service_dir, services)?),
+        None => None,
+    };
+    let Err(e) = handle_reg(registry, hash_values, &client.software()) else {
+        return Ok((client, ic));
+    };
+    let undone = match &ic {
+        Some(IcInstallResult {
+            backup: Some(_), ..
+        }) => restore_ic(service_dir, services).map(|_| ()),
+        // 原来没有 inventory collector, 删除复制过去的文件
+        Some(IcInstallResult { target, .. }) => fs::remove_file(target).map_err(Into::into),
+        None => Ok(()),
+    };
+    match undone {
+        Ok(()) => Err(e),
+        Err(undo) => Err(CatalogError::InstallError(format!(
+            "{}; restoring the inventory collector also failed: {}",
+            e, undo
+        ))),
+    }
 }
 
+#[derive(Debug, Clone, Serialize)]
 pub enum Software {
     DellUpdate { app_name: String },
     DellCommandUpdate { app_name: String },
 }
 
+impl Software {
+    pub fn app_name(&self) -> &str {
+        match self {
+            Software::DellUpdate { app_name } | Software::DellCommandUpdate { app_name } => {
+                app_name
+            }
+        }
+    }
+
+    pub fn kind(&self) -> ClientKind {
+        match self {
+            Software::DellUpdate { .. } => ClientKind::DellUpdate,
+            Software::DellCommandUpdate { .. } => ClientKind::DellCommandUpdate,
+        }
+    }
+
+    /// 客户端在 Clients 下的注册表键
+    pub fn client_key(&self) -> &'static str {
+        match self {
+            Software::DellUpdate { .. } => DU_PATH,
+            Software::DellCommandUpdate { .. } => DCU_PATH,
+        }
+    }
+
+    /// 客户端的常规设置, CustomCatalogPaths 等写在这里
+    pub fn settings_key(&self) -> String {
+        format!(r"{}\Preferences\Settings\General", self.client_key())
+    }
+}
+
 const DCU_PATH: &str = r#"SOFTWARE\Dell\UpdateService\Clients\CommandUpdate"#;
 const DU_PATH: &str = r#"SOFTWARE\Dell\UpdateService\Clients\Update"#;
-pub fn du_or_dcu() -> Option<Software> {
-    match open_reg_subkey(DCU_PATH) {
-        Ok(_) => Some(Software::DellCommandUpdate {
-            app_name: "Dell Command Update".to_string(),
-        }),
-        Err(_) => match open_reg_subkey(DU_PATH) {
-            Ok(_) => Some(Software::DellUpdate {
-                app_name: "Dell Update".to_string(),
-            }),
-            Err(e) => {
-                eprintln!("du_or_dcu--{}", e);
-                None
-            }
-        },
+
+/// 第一个已安装的客户端, 不检查版本和服务; 需要检查时用 `choose_client`
+pub fn du_or_dcu(registry: &dyn RegistryBackend) -> Option<Software> {
+    match detect_clients(registry, &SystemServices) {
+        Ok(clients) => clients.first().map(InstalledClient::software),
+        Err(e) => {
+            log::warn!("du_or_dcu--{}", e);
+            None
+        }
+    }
+}
+
+/// 注册表快照保存的目录, 与启动时的当前目录无关:
+/// Windows 上是 `%ProgramData%\catalog-rs\snapshots`, 其他平台在程序所在的目录中
+pub fn snapshot_dir() -> PathBuf {
+    #[cfg(windows)]
+    {
+        if let Some(program_data) = env::var_os("ProgramData") {
+            return PathBuf::from(program_data)
+                .join("catalog-rs")
+                .join("snapshots");
+        }
+    }
+    env::current_exe()
+        .ok()
+        .and_then(|exe| exe.parent().map(|dir| dir.join("snapshots")))
+        .unwrap_or_else(|| get_cur_path().join("snapshots"))
+}
+
+/// 用快照文件恢复注册表
+pub fn rollback_snapshot(
+    registry: &mut dyn RegistryBackend,
+    snapshot_path: &Path,
+) -> Result<(), CatalogError> {
+    let snapshot = RegistrySnapshot::from_path(snapshot_path)?;
+    registry::rollback(registry, &snapshot)
+}
+
+/// 用最新的快照恢复注册表, 返回使用的快照文件
+pub fn rollback_latest() -> Result<PathBuf, CatalogError> {
+    let snapshot_path = RegistrySnapshot::latest_in(&snapshot_dir())
+        .ok_or_else(|| CatalogError::CurrentFileError("registry snapshot".into()))?;
+    #[cfg(windows)]
+    {
+        rollback_snapshot(&mut WindowsRegistry, &snapshot_path)?;
+        Ok(snapshot_path)
     }
+    #[cfg(not(windows))]
+    Err(CatalogError::SelectedFileError(format!(
+        "Rollback of {:?} is only available on Windows",
+        snapshot_path
+    )))
 }
 
 pub fn get_cur_path() -> PathBuf {
     env::current_dir().unwrap_or_else(|e| {
-        eprintln!("Failed to get current directory: {}", e);
+        log::warn!("Failed to get current directory: {}", e);
         PathBuf::from(".")
     })
 }
 
 pub fn check_catalog_info(catalog_info: &CatalogInfo) -> Result<(), CatalogError> {
-    print!("handle catalog_info--{:?}", catalog_info);
-    if !is_cab_path(&catalog_info.cab_path) {
-        return Err(CatalogError::SelectedFileError(format!(
-            "Invalid file type",
-        )));
+    log::debug!("handle catalog_info--{:?}", catalog_info);
+    match &catalog_info.cab_path {
+        None => return Err(CatalogError::CurrentFileError(".cab".into())),
+        Some(cab_path) if !is_cab_path(cab_path) => {
+            return Err(CatalogError::SelectedFileError("Invalid file type".into()))
+        }
+        Some(_) => {}
     }
-    if !is_ic_path(&catalog_info.ic_path) {
-        return Err(CatalogError::SelectedFileError(format!(
-            "Invalid file type",
-        )));
+    if let Some(ic_path) = &catalog_info.ic_path {
+        if !is_ic_path(ic_path) {
+            return Err(CatalogError::SelectedFileError("Invalid file type".into()));
+        }
     }
     Ok(())
 }
 
-pub async fn handle(catalog_info: &CatalogInfo) -> Result<(), CatalogError> {
-    let xml_path = cab_to_xml(&PathBuf::from(&catalog_info.cab_path))?;
-    let new_xml_path = handle_xml(xml_path)?;
-    let hash = get_hash_sha384(new_xml_path)?;
-    let _ = copy(
-        &catalog_info.ic_path,
-        r"C:\Program Files (x86)\Dell\UpdateService\Service\InvColPC.exe",
-    );
-    if let Some(ref software) = du_or_dcu() {
-        match software {
-            Software::DellUpdate { app_name } => {
-                println!("{}", name);
-                handle_reg(&hash, software)
-            }
-            Software::DellCommandUpdate { app_name } => {
-                println!("{}", name);
-                handle_reg(&hash, software)
-            }
-        }
+pub async fn handle(catalog_info: &CatalogInfo) -> Result<HandleReport, CatalogError> {
+    check_catalog_info(catalog_info)?;
+    let Some(cab_path) = catalog_info.cab_path.as_deref() else {
+        return Err(CatalogError::CurrentFileError(".cab".into()));
+    };
+    let rules = load_rules_near(cab_path)?;
+    let prepared = prepare_catalog(cab_path, &rules, catalog_info.hash_format)?;
+    let packages = if catalog_info.require_staged_packages {
+        ensure_staged_packages(&prepared.rewritten_xml_path)?
     } else {
-        println!("请安装DCU或者DU");
+        staged_package_report(&prepared.rewritten_xml_path)?
     };
-    Ok(())
+    #[cfg(windows)]
+    let scan = {
+        let mut registry = SnapshotRegistry::with_dir(WindowsRegistry, &snapshot_dir());
+        let (client, _) = install_catalog_with_ic(
+            &mut registry,
+            &mut SystemServices,
+            catalog_info.client,
+            &prepared.hash_values,
+            catalog_info.ic_path.as_deref(),
+            None,
+        )?;
+        let software = client.software();
+        log::info!("registry snapshot--{:?}", registry.path());
+        let options = DcuOptions {
+            output_dir: get_cur_path().join("dcu"),
+            ..Default::default()
+        };
+        scan_or_launch(
+            DcuCli::locate(&registry, &software, ProcessRunner),
+            &options,
+            &mut SystemLauncher,
+            &registry,
+            &software,
+        )
+    };
+    #[cfg(not(windows))]
+    let scan = {
+        log::info!("handle--{}", prepared.hash_values.to_json()?);
+        None
+    };
+    Ok(HandleReport { packages, scan })
 }
 
 #[cfg(test)]
@@ -486,8 +871,578 @@ mod tests {
 
     #[test]
     fn test_cab_to_xml() {
-        let xml_path = PathBuf::from("C:\\Users\\LJZ\\dev\\catalog-rs\\Precision_0CBB.cab");
-        let result = cab_to_xml(&xml_path);
-        assert!(result.is_ok());
+        let cab_path = PathBuf::from(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/../Precision_0CBB.cab"
+        ));
+        let output_dir = tempfile::tempdir().unwrap();
+        let xml_path = cab_to_xml(&cab_path, output_dir.path()).unwrap();
+        assert_eq!(xml_path, output_dir.path().join("Precision_0CBB.xml"));
+        assert!(xml_path.exists());
+    }
+
+    #[test]
+    fn test_handle_xml_golden() {
+        let output_dir = tempfile::tempdir().unwrap();
+        let xml_path = output_dir.path().join("Precision_0CBB.xml");
+        std::fs::copy(
+            concat!(env!("CARGO_MANIFEST_DIR"), "/../Precision_0CBB.xml"),
+            &xml_path,
+        )
+        .unwrap();
+        let new_xml_path = handle_xml(xml_path.clone(), &RewriteRules::default()).unwrap();
+        assert_eq!(new_xml_path, output_dir.path().join("_Precision_0CBB.xml"));
+
+        let golden = include_bytes!(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/tests/fixtures/_Precision_0CBB.xml"
+        ));
+        assert_eq!(std::fs::read(&new_xml_path).unwrap(), golden);
+
+        // 与输入逐行对比, 只有改写过属性的三行不同
+        let input = XmlText::from_path(&xml_path).unwrap();
+        let output = XmlText::from_path(&new_xml_path).unwrap();
+        assert_eq!(output.encoding, input.encoding);
+        assert!(output.bom);
+        let changed: Vec<(&str, &str)> = input
+            .text
+            .split("\r\n")
+            .zip(output.text.split("\r\n"))
+            .filter(|(a, b)| a != b)
+            .collect();
+        assert_eq!(
+            input.text.split("\r\n").count(),
+            output.text.split("\r\n").count()
+        );
+        assert_eq!(changed.len(), 3);
+        assert!(changed[0].1.starts_with("<Manifest baseLocation=\"\" "));
+        assert!(changed[2]
+            .1
+            .contains(" path=\"Precision_3590_3591_Latitude_5550_1.8.0.exe\" "));
+    }
+
+    #[test]
+    fn test_xml_to_cab() {
+        let output_dir = tempfile::tempdir().unwrap();
+        let xml_path = output_dir.path().join("_Precision_0CBB.xml");
+        std::fs::copy(
+            concat!(
+                env!("CARGO_MANIFEST_DIR"),
+                "/tests/fixtures/_Precision_0CBB.xml"
+            ),
+            &xml_path,
+        )
+        .unwrap();
+        let cab_path = xml_to_cab(&xml_path, CompressionType::MsZip).unwrap();
+        assert_eq!(cab_path, output_dir.path().join("_Precision_0CBB.cab"));
+
+        let extract_dir = output_dir.path().join("extract");
+        let extracted = cab_to_xml(&cab_path, &extract_dir).unwrap();
+        assert_eq!(
+            std::fs::read(extracted).unwrap(),
+            std::fs::read(xml_path).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_prepare_catalog() {
+        let output_dir = tempfile::tempdir().unwrap();
+        let cab_path = output_dir.path().join("Precision_0CBB.cab");
+        std::fs::copy(
+            concat!(env!("CARGO_MANIFEST_DIR"), "/../Precision_0CBB.cab"),
+            &cab_path,
+        )
+        .unwrap();
+        let rules = load_rules_near(&cab_path).unwrap();
+        // 客户端要求 SHA-384 且保留 `=` 时按这个格式注册
+        let format = HashFormat::new(hash::HashAlgorithm::Sha384, true);
+        let prepared = prepare_catalog(&cab_path, &rules, format).unwrap();
+        assert_eq!(
+            prepared.rewritten_xml_path,
+            output_dir.path().join("_Precision_0CBB.xml")
+        );
+        assert_eq!(
+            prepared.cab_path,
+            output_dir.path().join("_Precision_0CBB.cab")
+        );
+        let key = prepared.rewritten_xml_path.to_string_lossy();
+        assert_eq!(
+            prepared.hash_values.get(&key),
+            Some(
+                format
+                    .hash_file(&prepared.rewritten_xml_path)
+                    .unwrap()
+                    .as_str()
+            )
+        );
+
+        let manifest = load_manifest(&prepared.cab_path).unwrap();
+        assert_eq!(manifest.base_location, "");
+        assert_eq!(
+            load_manifest(&cab_path).unwrap().software_components.len(),
+            2
+        );
+
+        // 更新包没有放在 catalog 旁边: 默认只报告, 要求检查时才报错
+        let report = staged_package_report(&prepared.rewritten_xml_path)
+            .unwrap()
+            .unwrap();
+        assert_eq!(report.missing.len(), 2);
+        assert!(matches!(
+            ensure_staged_packages(&prepared.rewritten_xml_path),
+            Err(CatalogError::PackageMismatch(_))
+        ));
+    }
+
+    #[test]
+    fn test_merge_catalogs() {
+        let output_dir = tempfile::tempdir().unwrap();
+        let sources = [
+            PathBuf::from(concat!(
+                env!("CARGO_MANIFEST_DIR"),
+                "/../Precision_0CBB.cab"
+            )),
+            PathBuf::from(concat!(
+                env!("CARGO_MANIFEST_DIR"),
+                "/../Precision_0CBB.xml"
+            )),
+        ];
+        let xml_path = output_dir.path().join("merged.xml");
+        let format = HashFormat::new(hash::HashAlgorithm::Sha384, false);
+        let merged = merge_catalogs(&sources, &xml_path, &MergeOptions::default(), format).unwrap();
+        assert_eq!(merged.cab_path, output_dir.path().join("merged.cab"));
+        assert_eq!(merged.report.duplicates, 2);
+        assert!(merged.report.conflicts.is_empty());
+        let manifest = load_manifest(&merged.cab_path).unwrap();
+        assert_eq!(manifest.identifier, merged.identifier);
+        assert_eq!(manifest.software_components.len(), 2);
+        assert_eq!(
+            merged.hash_values.get(&xml_path.to_string_lossy()),
+            Some(format.hash_file(&xml_path).unwrap().as_str())
+        );
+    }
+
+    #[test]
+    fn test_stage_repository() {
+        let output_dir = tempfile::tempdir().unwrap();
+        let source_dir = tempfile::tempdir().unwrap();
+        let cab_path = concat!(env!("CARGO_MANIFEST_DIR"), "/../Precision_0CBB.cab").as_ref();
+        let original = load_manifest(cab_path).unwrap();
+        // 文件名正确但内容不对
+        let first = &original.software_components[0];
+        let name = first.path.rsplit('/').next().unwrap();
+        fs::write(source_dir.path().join(name), b"not a package").unwrap();
+
+        let format = HashFormat::new(hash::HashAlgorithm::Sha256, false);
+        let staged = stage_repository(
+            cab_path,
+            &[source_dir.path().to_path_buf()],
+            &output_dir.path().join("repo"),
+            &RewriteRules::default(),
+            format,
+        )
+        .unwrap();
+        assert!(staged.staged.is_empty());
+        assert_eq!(staged.missing.len(), 2);
+        assert_eq!(staged.missing[0].path, name);
+        assert!(staged.missing[0].problem.is_some());
+        assert_eq!(
+            staged.missing[0].url,
+            format!("https://{}/{}", original.base_location, first.path)
+        );
+        assert!(staged.cab_path.starts_with(output_dir.path().join("repo")));
+        let missing: Vec<serde_json::Value> =
+            serde_json::from_str(&fs::read_to_string(&staged.missing_path).unwrap()).unwrap();
+        assert_eq!(missing.len(), 2);
+        assert_eq!(load_manifest(&staged.cab_path).unwrap().base_location, "");
+        assert_eq!(
+            staged.hash_values.get(&staged.xml_path.to_string_lossy()),
+            Some(format.hash_file(&staged.xml_path).unwrap().as_str())
+        );
+    }
+
+    #[test]
+    fn test_serve_catalog() {
+        let root = tempfile::tempdir().unwrap();
+        let cab_path = root.path().join("Precision_0CBB.cab");
+        fs::copy(
+            concat!(env!("CARGO_MANIFEST_DIR"), "/../Precision_0CBB.cab"),
+            &cab_path,
+        )
+        .unwrap();
+        fs::write(root.path().join("package.exe"), b"package").unwrap();
+        let format = HashFormat::new(hash::HashAlgorithm::Sha3_384, true);
+        let (served, server) = serve_catalog(
+            &cab_path,
+            &RewriteRules::default(),
+            &ServeOptions::default(),
+            format,
+        )
+        .unwrap();
+        let server = server.spawn();
+        assert_eq!(served.base_url, server.base_url());
+        assert_eq!(
+            load_manifest(&served.cab_path).unwrap().base_location,
+            served.base_url
+        );
+        assert!(served.catalog_url.ends_with("/_Precision_0CBB.cab"));
+        assert_eq!(
+            served.hash_values.get(&served.xml_path.to_string_lossy()),
+            Some(format.hash_file(&served.xml_path).unwrap().as_str())
+        );
+
+        let path = served.catalog_url.strip_prefix(&served.base_url).unwrap();
+        let (status, _, body) =
+            server::tests::request(server.addr(), "GET", &format!("/{}", path), &[]);
+        assert_eq!(status, 200);
+        assert_eq!(body, fs::read(&served.cab_path).unwrap());
+        let (status, _, body) = server::tests::request(server.addr(), "GET", "/package.exe", &[]);
+        assert_eq!((status, body.as_slice()), (200, b"package".as_slice()));
+    }
+
+    #[test]
+    fn test_filter_catalog() {
+        let output_dir = tempfile::tempdir().unwrap();
+        let cab_path = concat!(env!("CARGO_MANIFEST_DIR"), "/../Precision_0CBB.cab").as_ref();
+        let package_id = load_manifest(cab_path).unwrap().software_components[1]
+            .package_id
+            .clone();
+        let filter = ComponentFilter {
+            package_ids: vec![package_id.clone()],
+            ..Default::default()
+        };
+        let format = HashFormat::new(hash::HashAlgorithm::Sha256, true);
+        let filtered = filter_catalog(cab_path, &filter, output_dir.path(), format).unwrap();
+        assert_eq!(filtered.kept, [package_id]);
+        assert!(filtered.warnings.is_empty());
+        assert_eq!(
+            filtered.cab_path,
+            output_dir.path().join("Precision_0CBB_filtered.cab")
+        );
+        let manifest = load_manifest(&filtered.cab_path).unwrap();
+        assert_eq!(manifest.software_components.len(), 1);
+        let key = filtered.xml_path.to_string_lossy();
+        assert_eq!(
+            filtered.hash_values.get(&key),
+            Some(format.hash_file(&filtered.xml_path).unwrap().as_str())
+        );
+
+        let filter = ComponentFilter {
+            package_ids: vec!["MISSING".into()],
+            ..Default::default()
+        };
+        let filtered =
+            filter_catalog(cab_path, &filter, output_dir.path(), HashFormat::default()).unwrap();
+        assert!(filtered.kept.is_empty());
+        assert_eq!(filtered.warnings.len(), 1);
+    }
+
+    #[test]
+    fn test_du_or_dcu() {
+        let mut registry = registry::MemoryRegistry::new();
+        assert!(du_or_dcu(&registry).is_none());
+        registry.create_key(DU_PATH).unwrap();
+        assert_eq!(du_or_dcu(&registry).unwrap().app_name(), "Dell Update");
+        registry.create_key(DCU_PATH).unwrap();
+        assert_eq!(
+            du_or_dcu(&registry).unwrap().app_name(),
+            "Dell Command Update"
+        );
+    }
+
+    #[test]
+    fn test_handle_reg() {
+        use registry::{MemoryRegistry, RegistryChange};
+
+        let mut registry = MemoryRegistry::new();
+        registry
+            .write_value(
+                SERVICE_KEY,
+                "CustomCatalogHashValues",
+                &RegValue::Sz(
+                    r#"{"CatalogHashValues":[{"Key":"C:\\old.xml","Value":"old"}]}"#.into(),
+                ),
+            )
+            .unwrap();
+        registry
+            .write_value(SERVICE_KEY, "LastCheckTimestamp", &RegValue::Sz("1".into()))
+            .unwrap();
+        registry
+            .write_value(
+                &format!(r"{}\IgnoreList", SERVICE_KEY),
+                "X",
+                &RegValue::Dword(1),
+            )
+            .unwrap();
+        registry.clear_changes();
+
+        let mut hash_values = CatalogHashValues::default();
+        hash_values.insert(r"C:\new.xml", "new");
+        let software = Software::DellCommandUpdate {
+            app_name: "Dell Command Update".into(),
+        };
+        handle_reg(&mut registry, &hash_values, &software).unwrap();
+
+        let json = registry
+            .read_value(SERVICE_KEY, "CustomCatalogHashValues")
+            .unwrap()
+            .unwrap();
+        let merged = CatalogHashValues::from_json(json.as_str().unwrap()).unwrap();
+        assert_eq!(merged.get(r"C:\old.xml"), Some("old"));
+        assert_eq!(merged.get(r"C:\new.xml"), Some("new"));
+        assert_eq!(
+            registry
+                .read_value(SERVICE_KEY, "LastCheckTimestamp")
+                .unwrap(),
+            None
+        );
+        assert!(!registry
+            .key_exists(&format!(r"{}\IgnoreList", SERVICE_KEY))
+            .unwrap());
+
+        let touched: Vec<String> = registry
+            .changes()
+            .iter()
+            .map(|change| match change {
+                RegistryChange::WriteValue { name, .. } => format!("write {}", name),
+                RegistryChange::DeleteValue { name, .. } => format!("delete {}", name),
+                RegistryChange::DeleteTree { key } => format!("delete tree {}", key),
+                RegistryChange::CreateKey { key } => format!("create {}", key),
+            })
+            .collect();
+        assert_eq!(
+            touched,
+            [
+                "write CustomCatalogHashValues".to_string(),
+                format!(r"delete tree {}\IgnoreList", SERVICE_KEY),
+                "delete LastCheckTimestamp".into(),
+                "delete LastUpdateTimestamp".into(),
+                "delete CatalogTimestamp".into(),
+                "write CustomCatalogPaths".into(),
+                "write EnableCatalogXML".into(),
+                "write EnableDefaultDellCatalog".into(),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_handle_reg_invalid_hash_values() {
+        use registry::MemoryRegistry;
+
+        let mut registry = MemoryRegistry::new();
+        let broken = RegValue::Sz(r#"{"CatalogHashValues":[{"Key":"C:\old.xml""#.into());
+        registry
+            .write_value(SERVICE_KEY, "CustomCatalogHashValues", &broken)
+            .unwrap();
+        registry.clear_changes();
+
+        let mut hash_values = CatalogHashValues::default();
+        hash_values.insert(r"C:\new.xml", "new");
+        let software = Software::DellUpdate {
+            app_name: "Dell Update".into(),
+        };
+        let result = handle_reg(&mut registry, &hash_values, &software);
+        assert!(matches!(result, Err(CatalogError::RegistryValue { .. })));
+        assert_eq!(
+            registry
+                .read_value(SERVICE_KEY, "CustomCatalogHashValues")
+                .unwrap(),
+            Some(broken)
+        );
+        assert!(registry.changes().is_empty());
+    }
+
+    #[test]
+    fn test_install_catalog_with_ic() {
+        use detect::{ServiceQuery, ServiceState};
+        use ic::IC_FILE_NAME;
+        use registry::MemoryRegistry;
+
+        /// 服务一直在运行, 停止和启动都不做任何事
+        struct RunningServices;
+
+        impl ServiceQuery for RunningServices {
+            fn state(&self, _name: &str) -> Result<ServiceState, CatalogError> {
+                Ok(ServiceState::Running)
+            }
+        }
+
+        impl ServiceControl for RunningServices {
+            fn stop(&mut self, _name: &str) -> Result<(), CatalogError> {
+                Ok(())
+            }
+
+            fn start(&mut self, _name: &str) -> Result<(), CatalogError> {
+                Ok(())
+            }
+        }
+
+        let service_dir = tempfile::tempdir().unwrap();
+        let target = service_dir.path().join(IC_FILE_NAME);
+        let custom = service_dir.path().join("custom.exe");
+        std::fs::write(&target, b"original").unwrap();
+        std::fs::write(&custom, b"custom").unwrap();
+        let mut hash_values = CatalogHashValues::default();
+        hash_values.insert(r"C:\new.xml", "new");
+
+        // 没有客户端时不替换
+        let result = install_catalog_with_ic(
+            &mut MemoryRegistry::new(),
+            &mut RunningServices,
+            None,
+            &hash_values,
+            Some(&custom),
+            Some(service_dir.path()),
+        );
+        assert!(matches!(result, Err(CatalogError::NoClientInstalled)));
+        assert_eq!(std::fs::read(&target).unwrap(), b"original");
+
+        // 注册表写入失败时还原
+        let mut registry = MemoryRegistry::new();
+        registry
+            .create_key(ClientKind::DellUpdate.software().client_key())
+            .unwrap();
+        registry
+            .write_value(
+                SERVICE_KEY,
+                "CustomCatalogHashValues",
+                &RegValue::Sz("not json".into()),
+            )
+            .unwrap();
+        let result = install_catalog_with_ic(
+            &mut registry,
+            &mut RunningServices,
+            None,
+            &hash_values,
+            Some(&custom),
+            Some(service_dir.path()),
+        );
+        assert!(matches!(result, Err(CatalogError::RegistryValue { .. })));
+        assert_eq!(std::fs::read(&target).unwrap(), b"original");
+        assert!(!service_dir.path().join("InvColPC.exe.bak").exists());
+
+        registry
+            .delete_value(SERVICE_KEY, "CustomCatalogHashValues")
+            .unwrap();
+        let (client, ic) = install_catalog_with_ic(
+            &mut registry,
+            &mut RunningServices,
+            None,
+            &hash_values,
+            Some(&custom),
+            Some(service_dir.path()),
+        )
+        .unwrap();
+        assert_eq!(client.kind, ClientKind::DellUpdate);
+        assert!(ic.unwrap().backup.is_some());
+        assert_eq!(std::fs::read(&target).unwrap(), b"custom");
+    }
+
+    #[test]
+    fn test_handle_client_settings() {
+        use registry::MemoryRegistry;
+
+        let mut hash_values = CatalogHashValues::default();
+        hash_values.insert(r"C:\Catalogs\_Precision_0CBB.xml", "a");
+        hash_values.insert(r"C:\Catalogs\_Latitude.xml", "b");
+
+        let dcu = Software::DellCommandUpdate {
+            app_name: "Dell Command Update".into(),
+        };
+        let mut registry = MemoryRegistry::new();
+        registry
+            .write_value(
+                &dcu.settings_key(),
+                "CustomCatalogPaths",
+                &RegValue::MultiSz(vec![
+                    r"c:\catalogs\_precision_0cbb.xml".into(),
+                    r"D:\old.xml".into(),
+                ]),
+            )
+            .unwrap();
+        handle_reg(&mut registry, &hash_values, &dcu).unwrap();
+        let settings =
+            r"SOFTWARE\Dell\UpdateService\Clients\CommandUpdate\Preferences\Settings\General";
+        // 以前安装的 catalog 不再保留
+        assert_eq!(
+            registry.read_value(settings, "CustomCatalogPaths").unwrap(),
+            Some(RegValue::MultiSz(vec![
+                r"C:\Catalogs\_Precision_0CBB.xml".into(),
+                r"C:\Catalogs\_Latitude.xml".into(),
+            ]))
+        );
+        assert_eq!(
+            registry.read_value(settings, "EnableCatalogXML").unwrap(),
+            Some(RegValue::Dword(1))
+        );
+        assert_eq!(
+            registry
+                .read_value(settings, "EnableDefaultDellCatalog")
+                .unwrap(),
+            Some(RegValue::Dword(0))
+        );
+
+        let du = Software::DellUpdate {
+            app_name: "Dell Update".into(),
+        };
+        let mut registry = MemoryRegistry::new();
+        handle_reg(&mut registry, &hash_values, &du).unwrap();
+        let settings = r"SOFTWARE\Dell\UpdateService\Clients\Update\Preferences\Settings\General";
+        assert_eq!(
+            registry.read_value(settings, "CustomCatalogPaths").unwrap(),
+            Some(RegValue::MultiSz(vec![
+                r"C:\Catalogs\_Precision_0CBB.xml".into(),
+                r"C:\Catalogs\_Latitude.xml".into(),
+            ]))
+        );
+        assert_eq!(
+            registry
+                .read_value(settings, "EnableDefaultDellCatalog")
+                .unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_handle_reg_rollback() {
+        use registry::{MemoryRegistry, SnapshotRegistry};
+
+        let mut stock = MemoryRegistry::new();
+        stock
+            .write_value(
+                SERVICE_KEY,
+                "CatalogTimestamp",
+                &RegValue::Sz("2024".into()),
+            )
+            .unwrap();
+        stock
+            .write_value(
+                &format!(r"{}\IgnoreList", SERVICE_KEY),
+                "A",
+                &RegValue::Dword(1),
+            )
+            .unwrap();
+
+        let dir = tempfile::tempdir().unwrap();
+        let mut registry = SnapshotRegistry::with_dir(stock.clone(), dir.path());
+        let mut hash_values = CatalogHashValues::default();
+        hash_values.insert(r"C:\new.xml", "new");
+        let software = Software::DellUpdate {
+            app_name: "Dell Update".into(),
+        };
+        handle_reg(&mut registry, &hash_values, &software).unwrap();
+        let snapshot_path = registry.path().unwrap().to_path_buf();
+        let (mut modified, _) = registry.into_inner();
+
+        rollback_snapshot(&mut modified, &snapshot_path).unwrap();
+        for key in stock.keys() {
+            assert_eq!(modified.values(key).unwrap(), stock.values(key).unwrap());
+        }
+        assert_eq!(
+            modified
+                .read_value(SERVICE_KEY, "CustomCatalogHashValues")
+                .unwrap(),
+            None
+        );
     }
 }
@@ -0,0 +1,1041 @@
+use std::{
+    fs::File,
+    io::{BufReader, Read, Write},
+    path::Path,
+    str::FromStr,
+};
+
+use xml::{
+    common::XmlVersion,
+    reader::{EventReader, ParserConfig, XmlEvent as ReaderEvent},
+    writer::XmlEvent,
+    EmitterConfig,
+};
+
+use crate::error::CatalogError;
+
+/// Dell catalog 使用的默认命名空间
+pub const NAMESPACE: &str = "openmanage/cm/dm";
+
+/// 通用 XML 元素, 用于保存模型之外的节点
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Element {
+    pub name: String,
+    pub attributes: Vec<(String, String)>,
+    pub children: Vec<Element>,
+    pub text: String,
+    pub cdata: bool,
+}
+
+impl Element {
+    fn new(name: &str) -> Self {
+        Element {
+            name: name.to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn attr(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(k, _)| k == name)
+            .map(|(_, v)| v.as_str())
+    }
+
+    fn attr_string(&self, name: &str) -> String {
+        self.attr(name).unwrap_or_default().to_string()
+    }
+
+    fn attr_option(&self, name: &str) -> Option<String> {
+        self.attr(name).map(str::to_string)
+    }
+
+    fn child(&self, name: &str) -> Option<&Element> {
+        self.children.iter().find(|c| c.name == name)
+    }
+
+    fn children_named<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a Element> + 'a {
+        self.children.iter().filter(move |c| c.name == name)
+    }
+
+    fn with_attr(mut self, name: &str, value: &str) -> Self {
+        self.attributes.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    fn with_option_attr(self, name: &str, value: &Option<String>) -> Self {
+        match value {
+            Some(value) => self.with_attr(name, value),
+            None => self,
+        }
+    }
+
+    fn with_text(mut self, text: &str, cdata: bool) -> Self {
+        self.text = text.to_string();
+        self.cdata = cdata;
+        self
+    }
+
+    fn with_child(mut self, child: Element) -> Self {
+        self.children.push(child);
+        self
+    }
+
+    fn with_children<I: IntoIterator<Item = Element>>(mut self, children: I) -> Self {
+        self.children.extend(children);
+        self
+    }
+
+    fn parse<R: Read>(reader: EventReader<R>) -> Result<(Element, Option<String>), CatalogError> {
+        let mut stack: Vec<Element> = Vec::new();
+        let mut namespace = None;
+        for event in reader {
+            match event? {
+                ReaderEvent::StartElement {
+                    name, attributes, ..
+                } => {
+                    if stack.is_empty() {
+                        namespace = name.namespace.clone();
+                    }
+                    let mut element = Element::new(&name.local_name);
+                    element.attributes = attributes
+                        .into_iter()
+                        .map(|attr| (attr.name.local_name, attr.value))
+                        .collect();
+                    stack.push(element);
+                }
+                ReaderEvent::EndElement { .. } => {
+                    let element = stack.pop().ok_or(CatalogError::Unexpected)?;
+                    match stack.last_mut() {
+                        Some(parent) => parent.children.push(element),
+                        None => return Ok((element, namespace)),
+                    }
+                }
+                ReaderEvent::Characters(text) => {
+                    if let Some(element) = stack.last_mut() {
+                        element.text.push_str(&text);
+                    }
+                }
+                ReaderEvent::CData(text) => {
+                    if let Some(element) = stack.last_mut() {
+                        element.text.push_str(&text);
+                        element.cdata = true;
+                    }
+                }
+                _ => {}
+            }
+        }
+        Err(CatalogError::InvalidCatalog("missing root element".into()))
+    }
+
+    fn write<W: Write>(&self, writer: &mut xml::EventWriter<W>) -> Result<(), CatalogError> {
+        let mut start = XmlEvent::start_element(self.name.as_str());
+        for (name, value) in self.attributes.iter() {
+            start = start.attr(name.as_str(), value);
+        }
+        writer.write(start)?;
+        if !self.text.is_empty() {
+            if self.cdata {
+                writer.write(XmlEvent::cdata(&self.text))?;
+            } else {
+                writer.write(XmlEvent::characters(&self.text))?;
+            }
+        }
+        for child in self.children.iter() {
+            child.write(writer)?;
+        }
+        writer.write(XmlEvent::end_element())?;
+        Ok(())
+    }
+}
+
+/// 带语言标记的显示文本, 对应 `<Display lang="en">`
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Display {
+    pub lang: Option<String>,
+    pub text: String,
+}
+
+impl Display {
+    fn from_element(element: &Element) -> Self {
+        Display {
+            lang: element.attr_option("lang"),
+            text: element.text.clone(),
+        }
+    }
+
+    fn to_element(&self) -> Element {
+        Element::new("Display")
+            .with_option_attr("lang", &self.lang)
+            .with_text(&self.text, true)
+    }
+}
+
+fn displays(element: &Element) -> Vec<Display> {
+    element
+        .children_named("Display")
+        .map(Display::from_element)
+        .collect()
+}
+
+fn display_elements(displays: &[Display]) -> impl Iterator<Item = Element> + '_ {
+    displays.iter().map(Display::to_element)
+}
+
+/// 优先取英文显示文本, 没有则取第一个
+pub fn display_text(displays: &[Display]) -> &str {
+    displays
+        .iter()
+        .find(|d| d.lang.as_deref() == Some("en"))
+        .or(displays.first())
+        .map(|d| d.text.as_str())
+        .unwrap_or_default()
+}
+
+/// `value` 加显示文本的元素, 如 `ComponentType`/`Category`/`Criticality`
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct CodedValue {
+    pub value: String,
+    pub display: Vec<Display>,
+}
+
+pub type ComponentType = CodedValue;
+pub type Category = CodedValue;
+pub type Criticality = CodedValue;
+
+impl CodedValue {
+    fn from_element(element: &Element) -> Self {
+        CodedValue {
+            value: element.attr_string("value"),
+            display: displays(element),
+        }
+    }
+
+    fn to_element(&self, name: &str) -> Element {
+        Element::new(name)
+            .with_attr("value", &self.value)
+            .with_children(display_elements(&self.display))
+    }
+
+    pub fn text(&self) -> &str {
+        display_text(&self.display)
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct PCIInfo {
+    pub device_id: String,
+    pub vendor_id: String,
+    pub sub_device_id: String,
+    pub sub_vendor_id: String,
+}
+
+impl PCIInfo {
+    fn from_element(element: &Element) -> Self {
+        PCIInfo {
+            device_id: element.attr_string("deviceID"),
+            vendor_id: element.attr_string("vendorID"),
+            sub_device_id: element.attr_string("subDeviceID"),
+            sub_vendor_id: element.attr_string("subVendorID"),
+        }
+    }
+
+    fn to_element(&self) -> Element {
+        Element::new("PCIInfo")
+            .with_attr("deviceID", &self.device_id)
+            .with_attr("vendorID", &self.vendor_id)
+            .with_attr("subDeviceID", &self.sub_device_id)
+            .with_attr("subVendorID", &self.sub_vendor_id)
+    }
+}
+
+/// `SupportedDevices` 和 `SupportedDCHDevices` 下的设备
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Device {
+    pub component_id: String,
+    pub embedded: Option<String>,
+    pub guid: Option<String>,
+    pub inf_type: Option<String>,
+    pub component_type: Option<String>,
+    pub id: Option<String>,
+    pub version: Option<String>,
+    pub display: Vec<Display>,
+    pub pci_info: Vec<PCIInfo>,
+    pub generic: Vec<String>,
+}
+
+impl Device {
+    fn from_element(element: &Element) -> Self {
+        Device {
+            component_id: element.attr_string("componentID"),
+            embedded: element.attr_option("embedded"),
+            guid: element.attr_option("guid"),
+            inf_type: element.attr_option("infType"),
+            component_type: element.attr_option("componentType"),
+            id: element.attr_option("id"),
+            version: element.attr_option("version"),
+            display: displays(element),
+            pci_info: element
+                .children_named("PCIInfo")
+                .map(PCIInfo::from_element)
+                .collect(),
+            generic: element
+                .children_named("Generic")
+                .map(|e| e.text.clone())
+                .collect(),
+        }
+    }
+
+    fn to_element(&self) -> Element {
+        Element::new("Device")
+            .with_attr("componentID", &self.component_id)
+            .with_option_attr("embedded", &self.embedded)
+            .with_option_attr("guid", &self.guid)
+            .with_option_attr("infType", &self.inf_type)
+            .with_option_attr("componentType", &self.component_type)
+            .with_option_attr("id", &self.id)
+            .with_option_attr("version", &self.version)
+            .with_children(display_elements(&self.display))
+            .with_children(self.pci_info.iter().map(PCIInfo::to_element))
+            .with_children(
+                self.generic
+                    .iter()
+                    .map(|g| Element::new("Generic").with_text(g, false)),
+            )
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct SupportedDevices {
+    pub devices: Vec<Device>,
+}
+
+impl SupportedDevices {
+    fn from_element(element: &Element) -> Self {
+        SupportedDevices {
+            devices: element
+                .children_named("Device")
+                .map(Device::from_element)
+                .collect(),
+        }
+    }
+
+    fn to_element(&self, name: &str) -> Element {
+        Element::new(name).with_children(self.devices.iter().map(Device::to_element))
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Model {
+    pub system_id: String,
+    pub display: Vec<Display>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Brand {
+    pub key: String,
+    pub prefix: String,
+    pub display: Vec<Display>,
+    pub models: Vec<Model>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct SupportedSystems {
+    pub brands: Vec<Brand>,
+}
+
+impl SupportedSystems {
+    fn from_element(element: &Element) -> Self {
+        let brands = element
+            .children_named("Brand")
+            .map(|brand| Brand {
+                key: brand.attr_string("key"),
+                prefix: brand.attr_string("prefix"),
+                display: displays(brand),
+                models: brand
+                    .children_named("Model")
+                    .map(|model| Model {
+                        system_id: model.attr_string("systemID"),
+                        display: displays(model),
+                    })
+                    .collect(),
+            })
+            .collect();
+        SupportedSystems { brands }
+    }
+
+    fn to_element(&self) -> Element {
+        Element::new("SupportedSystems").with_children(self.brands.iter().map(|brand| {
+            Element::new("Brand")
+                .with_attr("key", &brand.key)
+                .with_attr("prefix", &brand.prefix)
+                .with_children(display_elements(&brand.display))
+                .with_children(brand.models.iter().map(|model| {
+                    Element::new("Model")
+                        .with_attr("systemID", &model.system_id)
+                        .with_children(display_elements(&model.display))
+                }))
+        }))
+    }
+
+    /// 所有支持的 systemID
+    pub fn system_ids(&self) -> impl Iterator<Item = &str> {
+        self.brands
+            .iter()
+            .flat_map(|b| b.models.iter())
+            .map(|m| m.system_id.as_str())
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct OperatingSystem {
+    pub os_code: String,
+    pub os_vendor: String,
+    pub os_arch: String,
+    pub major_version: String,
+    pub minor_version: String,
+    pub sp_major_version: String,
+    pub sp_minor_version: String,
+    pub build_number: Option<String>,
+    pub display: Vec<Display>,
+}
+
+impl OperatingSystem {
+    fn from_element(element: &Element) -> Self {
+        OperatingSystem {
+            os_code: element.attr_string("osCode"),
+            os_vendor: element.attr_string("osVendor"),
+            os_arch: element.attr_string("osArch"),
+            major_version: element.attr_string("majorVersion"),
+            minor_version: element.attr_string("minorVersion"),
+            sp_major_version: element.attr_string("spMajorVersion"),
+            sp_minor_version: element.attr_string("spMinorVersion"),
+            build_number: element.attr_option("buildNumber"),
+            display: displays(element),
+        }
+    }
+
+    fn to_element(&self) -> Element {
+        Element::new("OperatingSystem")
+            .with_attr("osCode", &self.os_code)
+            .with_attr("osVendor", &self.os_vendor)
+            .with_attr("osArch", &self.os_arch)
+            .with_attr("majorVersion", &self.major_version)
+            .with_attr("minorVersion", &self.minor_version)
+            .with_attr("spMajorVersion", &self.sp_major_version)
+            .with_attr("spMinorVersion", &self.sp_minor_version)
+            .with_option_attr("buildNumber", &self.build_number)
+            .with_children(display_elements(&self.display))
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct SupportedOperatingSystems {
+    pub operating_systems: Vec<OperatingSystem>,
+}
+
+impl SupportedOperatingSystems {
+    fn from_element(element: &Element) -> Self {
+        SupportedOperatingSystems {
+            operating_systems: element
+                .children_named("OperatingSystem")
+                .map(OperatingSystem::from_element)
+                .collect(),
+        }
+    }
+
+    fn to_element(&self) -> Element {
+        Element::new("SupportedOperatingSystems").with_children(
+            self.operating_systems
+                .iter()
+                .map(OperatingSystem::to_element),
+        )
+    }
+
+    pub fn os_codes(&self) -> impl Iterator<Item = &str> {
+        self.operating_systems.iter().map(|os| os.os_code.as_str())
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Hash {
+    pub algorithm: String,
+    pub value: String,
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Cryptography {
+    pub hashes: Vec<Hash>,
+}
+
+impl Cryptography {
+    fn from_element(element: &Element) -> Self {
+        Cryptography {
+            hashes: element
+                .children_named("Hash")
+                .map(|hash| Hash {
+                    algorithm: hash.attr_string("algorithm"),
+                    value: hash.text.clone(),
+                })
+                .collect(),
+        }
+    }
+
+    fn to_element(&self) -> Element {
+        Element::new("Cryptography").with_children(self.hashes.iter().map(|hash| {
+            Element::new("Hash")
+                .with_attr("algorithm", &hash.algorithm)
+                .with_text(&hash.value, false)
+        }))
+    }
+
+    /// 按算法名查找哈希值, 不区分大小写
+    pub fn hash(&self, algorithm: &str) -> Option<&str> {
+        self.hashes
+            .iter()
+            .find(|h| h.algorithm.eq_ignore_ascii_case(algorithm))
+            .map(|h| h.value.as_str())
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ImportantInfo {
+    pub url: String,
+    pub display: Vec<Display>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct DeviceGroup {
+    pub operating_system: Option<OperatingSystem>,
+    pub devices: Vec<String>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct SoftwareComponent {
+    pub schema_version: String,
+    pub release_id: String,
+    pub release_date: String,
+    pub vendor_version: String,
+    pub dell_version: String,
+    pub package_type: String,
+    pub path: String,
+    pub package_id: String,
+    pub date_time: String,
+    pub size: Option<u64>,
+    pub identifier: String,
+    pub base_build_number: Option<String>,
+    /// 模型中未列出的属性, 按原顺序保留
+    pub other_attributes: Vec<(String, String)>,
+    pub name: Vec<Display>,
+    pub component_type: Option<ComponentType>,
+    pub description: Vec<Display>,
+    pub category: Option<Category>,
+    pub supported_dch_devices: Option<SupportedDevices>,
+    pub supported_devices: Option<SupportedDevices>,
+    pub supported_systems: Option<SupportedSystems>,
+    pub supported_operating_systems: Option<SupportedOperatingSystems>,
+    pub important_info: Option<ImportantInfo>,
+    pub criticality: Option<Criticality>,
+    pub cryptography: Option<Cryptography>,
+    pub applies_to: Option<Vec<String>>,
+    pub device_groups: Vec<DeviceGroup>,
+    /// 模型中未列出的子元素
+    pub others: Vec<Element>,
+}
+
+const COMPONENT_ATTRIBUTES: [&str; 12] = [
+    "schemaVersion",
+    "releaseID",
+    "releaseDate",
+    "vendorVersion",
+    "dellVersion",
+    "packageType",
+    "path",
+    "packageID",
+    "dateTime",
+    "size",
+    "identifier",
+    "baseBuildNumber",
+];
+
+const COMPONENT_CHILDREN: [&str; 13] = [
+    "Name",
+    "ComponentType",
+    "Description",
+    "Category",
+    "SupportedDCHDevices",
+    "SupportedDevices",
+    "SupportedSystems",
+    "SupportedOperatingSystems",
+    "ImportantInfo",
+    "Criticality",
+    "Cryptography",
+    "AppliesTo",
+    "DeviceGroup",
+];
+
+impl SoftwareComponent {
+    fn from_element(element: &Element) -> Result<Self, CatalogError> {
+        let size = match element.attr("size") {
+            Some(size) => Some(size.parse::<u64>().map_err(|e| {
+                CatalogError::InvalidCatalog(format!("invalid size \"{}\": {}", size, e))
+            })?),
+            None => None,
+        };
+        Ok(SoftwareComponent {
+            schema_version: element.attr_string("schemaVersion"),
+            release_id: element.attr_string("releaseID"),
+            release_date: element.attr_string("releaseDate"),
+            vendor_version: element.attr_string("vendorVersion"),
+            dell_version: element.attr_string("dellVersion"),
+            package_type: element.attr_string("packageType"),
+            path: element.attr_string("path"),
+            package_id: element.attr_string("packageID"),
+            date_time: element.attr_string("dateTime"),
+            size,
+            identifier: element.attr_string("identifier"),
+            base_build_number: element.attr_option("baseBuildNumber"),
+            other_attributes: element
+                .attributes
+                .iter()
+                .filter(|(k, _)| !COMPONENT_ATTRIBUTES.contains(&k.as_str()))
+                .cloned()
+                .collect(),
+            name: element.child("Name").map(displays).unwrap_or_default(),
+            component_type: element.child("ComponentType").map(CodedValue::from_element),
+            description: element
+                .child("Description")
+                .map(displays)
+                .unwrap_or_default(),
+            category: element.child("Category").map(CodedValue::from_element),
+            supported_dch_devices: element
+                .child("SupportedDCHDevices")
+                .map(SupportedDevices::from_element),
+            supported_devices: element
+                .child("SupportedDevices")
+                .map(SupportedDevices::from_element),
+            supported_systems: element
+                .child("SupportedSystems")
+                .map(SupportedSystems::from_element),
+            supported_operating_systems: element
+                .child("SupportedOperatingSystems")
+                .map(SupportedOperatingSystems::from_element),
+            important_info: element.child("ImportantInfo").map(|info| ImportantInfo {
+                url: info.attr_string("URL"),
+                display: displays(info),
+            }),
+            criticality: element.child("Criticality").map(CodedValue::from_element),
+            cryptography: element
+                .child("Cryptography")
+                .map(Cryptography::from_element),
+            applies_to: element.child("AppliesTo").map(|applies_to| {
+                applies_to
+                    .children_named("DeviceInformation")
+                    .map(|e| e.text.clone())
+                    .collect()
+            }),
+            device_groups: element
+                .children_named("DeviceGroup")
+                .map(|group| DeviceGroup {
+                    operating_system: group
+                        .child("OperatingSystem")
+                        .map(OperatingSystem::from_element),
+                    devices: group
+                        .children_named("Device")
+                        .map(|e| e.text.clone())
+                        .collect(),
+                })
+                .collect(),
+            others: element
+                .children
+                .iter()
+                .filter(|c| !COMPONENT_CHILDREN.contains(&c.name.as_str()))
+                .cloned()
+                .collect(),
+        })
+    }
+
+    fn to_element(&self) -> Element {
+        let size = self.size.map(|s| s.to_string());
+        let mut element = Element::new("SoftwareComponent")
+            .with_attr("schemaVersion", &self.schema_version)
+            .with_attr("releaseID", &self.release_id)
+            .with_attr("releaseDate", &self.release_date)
+            .with_attr("vendorVersion", &self.vendor_version)
+            .with_attr("dellVersion", &self.dell_version)
+            .with_attr("packageType", &self.package_type)
+            .with_attr("path", &self.path)
+            .with_attr("packageID", &self.package_id)
+            .with_attr("dateTime", &self.date_time)
+            .with_option_attr("size", &size)
+            .with_attr("identifier", &self.identifier)
+            .with_option_attr("baseBuildNumber", &self.base_build_number);
+        element
+            .attributes
+            .extend(self.other_attributes.iter().cloned());
+
+        element =
+            element.with_child(Element::new("Name").with_children(display_elements(&self.name)));
+        if let Some(ref component_type) = self.component_type {
+            element = element.with_child(component_type.to_element("ComponentType"));
+        }
+        element = element.with_child(
+            Element::new("Description").with_children(display_elements(&self.description)),
+        );
+        if let Some(ref category) = self.category {
+            element = element.with_child(category.to_element("Category"));
+        }
+        if let Some(ref devices) = self.supported_dch_devices {
+            element = element.with_child(devices.to_element("SupportedDCHDevices"));
+        }
+        if let Some(ref devices) = self.supported_devices {
+            element = element.with_child(devices.to_element("SupportedDevices"));
+        }
+        if let Some(ref systems) = self.supported_systems {
+            element = element.with_child(systems.to_element());
+        }
+        if let Some(ref systems) = self.supported_operating_systems {
+            element = element.with_child(systems.to_element());
+        }
+        if let Some(ref info) = self.important_info {
+            element = element.with_child(
+                Element::new("ImportantInfo")
+                    .with_attr("URL", &info.url)
+                    .with_children(display_elements(&info.display)),
+            );
+        }
+        if let Some(ref criticality) = self.criticality {
+            element = element.with_child(criticality.to_element("Criticality"));
+        }
+        if let Some(ref cryptography) = self.cryptography {
+            element = element.with_child(cryptography.to_element());
+        }
+        if let Some(ref applies_to) = self.applies_to {
+            element = element.with_child(
+                Element::new("AppliesTo").with_children(
+                    applies_to
+                        .iter()
+                        .map(|info| Element::new("DeviceInformation").with_text(info, false)),
+                ),
+            );
+        }
+        for group in self.device_groups.iter() {
+            element = element.with_child(
+                Element::new("DeviceGroup")
+                    .with_children(
+                        group
+                            .operating_system
+                            .iter()
+                            .map(OperatingSystem::to_element),
+                    )
+                    .with_children(
+                        group
+                            .devices
+                            .iter()
+                            .map(|d| Element::new("Device").with_text(d, false)),
+                    ),
+            );
+        }
+        element.with_children(self.others.iter().cloned())
+    }
+
+    pub fn display_name(&self) -> &str {
+        display_text(&self.name)
+    }
+
+    pub fn display_description(&self) -> &str {
+        display_text(&self.description)
+    }
+
+    /// 所有支持的 systemID
+    pub fn system_ids(&self) -> Vec<&str> {
+        self.supported_systems
+            .iter()
+            .flat_map(|s| s.system_ids())
+            .collect()
+    }
+
+    /// 所有支持的操作系统代码
+    pub fn os_codes(&self) -> Vec<&str> {
+        self.supported_operating_systems
+            .iter()
+            .flat_map(|s| s.os_codes())
+            .collect()
+    }
+
+    /// `SupportedDevices` 与 `SupportedDCHDevices` 下的全部设备
+    pub fn devices(&self) -> impl Iterator<Item = &Device> {
+        self.supported_devices
+            .iter()
+            .chain(self.supported_dch_devices.iter())
+            .flat_map(|s| s.devices.iter())
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Manifest {
+    pub base_location: String,
+    pub date_time: String,
+    pub identifier: String,
+    pub release_id: String,
+    pub version: String,
+    /// 模型中未列出的属性, 按原顺序保留
+    pub other_attributes: Vec<(String, String)>,
+    pub software_components: Vec<SoftwareComponent>,
+    /// `SoftwareComponent` 之外的子元素, 如 `Prerequisites`
+    pub others: Vec<Element>,
+}
+
+const MANIFEST_ATTRIBUTES: [&str; 5] = [
+    "baseLocation",
+    "dateTime",
+    "identifier",
+    "releaseID",
+    "version",
+];
+
+/// 根元素 `Manifest` 上的版本信息, 读到根元素就停止, 不解析组件
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ManifestHeader {
+    pub date_time: String,
+    pub release_id: String,
+    pub version: String,
+}
+
+impl ManifestHeader {
+    pub fn from_reader<R: Read>(reader: R) -> Result<ManifestHeader, CatalogError> {
+        let reader = ParserConfig::new()
+            .ignore_comments(true)
+            .create_reader(reader);
+        for event in reader {
+            if let ReaderEvent::StartElement {
+                name, attributes, ..
+            } = event?
+            {
+                if name.local_name != "Manifest" || name.namespace.as_deref() != Some(NAMESPACE) {
+                    return Err(CatalogError::InvalidCatalog(format!(
+                        "expected <Manifest xmlns=\"{}\">, found <{}>",
+                        NAMESPACE, name.local_name
+                    )));
+                }
+                let attr = |key: &str| {
+                    attributes
+                        .iter()
+                        .find(|attr| attr.name.local_name == key)
+                        .map(|attr| attr.value.clone())
+                        .unwrap_or_default()
+                };
+                return Ok(ManifestHeader {
+                    date_time: attr("dateTime"),
+                    release_id: attr("releaseID"),
+                    version: attr("version"),
+                });
+            }
+        }
+        Err(CatalogError::InvalidCatalog("missing root element".into()))
+    }
+}
+
+impl Manifest {
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Manifest, CatalogError> {
+        let file = File::open(path)?;
+        Manifest::from_reader(BufReader::new(file))
+    }
+
+    pub fn from_reader<R: Read>(reader: R) -> Result<Manifest, CatalogError> {
+        let reader = ParserConfig::new()
+            .trim_whitespace(true)
+            .ignore_comments(true)
+            .create_reader(reader);
+        let (root, namespace) = Element::parse(reader)?;
+        if root.name != "Manifest" || namespace.as_deref() != Some(NAMESPACE) {
+            return Err(CatalogError::InvalidCatalog(format!(
+                "expected <Manifest xmlns=\"{}\">, found <{}>",
+                NAMESPACE, root.name
+            )));
+        }
+        Manifest::from_element(&root)
+    }
+
+    fn from_element(element: &Element) -> Result<Self, CatalogError> {
+        let mut software_components = Vec::new();
+        let mut others = Vec::new();
+        for child in element.children.iter() {
+            if child.name == "SoftwareComponent" {
+                software_components.push(SoftwareComponent::from_element(child)?);
+            } else {
+                others.push(child.clone());
+            }
+        }
+        Ok(Manifest {
+            base_location: element.attr_string("baseLocation"),
+            date_time: element.attr_string("dateTime"),
+            identifier: element.attr_string("identifier"),
+            release_id: element.attr_string("releaseID"),
+            version: element.attr_string("version"),
+            other_attributes: element
+                .attributes
+                .iter()
+                .filter(|(k, _)| !MANIFEST_ATTRIBUTES.contains(&k.as_str()))
+                .cloned()
+                .collect(),
+            software_components,
+            others,
+        })
+    }
+
+    fn to_element(&self) -> Element {
+        let mut element = Element::new("Manifest")
+            .with_attr("baseLocation", &self.base_location)
+            .with_attr("dateTime", &self.date_time)
+            .with_attr("identifier", &self.identifier)
+            .with_attr("releaseID", &self.release_id)
+            .with_attr("version", &self.version);
+        element
+            .attributes
+            .extend(self.other_attributes.iter().cloned());
+        element
+            .with_children(
+                self.software_components
+                    .iter()
+                    .map(SoftwareComponent::to_element),
+            )
+            .with_children(self.others.iter().cloned())
+    }
+
+    /// 以 UTF-8 写出整个 catalog
+    pub fn write_to<W: Write>(&self, writer: W) -> Result<(), CatalogError> {
+        let mut event_writer = EmitterConfig::new()
+            .perform_indent(true)
+            .indent_string("  ")
+            .pad_self_closing(true)
+            .create_writer(writer);
+        event_writer.write(XmlEvent::StartDocument {
+            version: XmlVersion::Version10,
+            encoding: Some("utf-8"),
+            standalone: None,
+        })?;
+        let root = self.to_element();
+        let mut start = XmlEvent::start_element("Manifest");
+        for (name, value) in root.attributes.iter() {
+            start = start.attr(name.as_str(), value);
+        }
+        event_writer.write(start.default_ns(NAMESPACE))?;
+        for child in root.children.iter() {
+            child.write(&mut event_writer)?;
+        }
+        event_writer.write(XmlEvent::end_element())?;
+        Ok(())
+    }
+
+    pub fn to_xml_string(&self) -> Result<String, CatalogError> {
+        let mut buffer = Vec::new();
+        self.write_to(&mut buffer)?;
+        String::from_utf8(buffer).map_err(|e| CatalogError::ParseError(e.to_string()))
+    }
+
+    pub fn component(&self, package_id: &str) -> Option<&SoftwareComponent> {
+        self.software_components
+            .iter()
+            .find(|c| c.package_id == package_id)
+    }
+}
+
+impl FromStr for Manifest {
+    type Err = CatalogError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Manifest::from_reader(s.as_bytes())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn manifest() -> Manifest {
+        Manifest::from_path(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/../Precision_0CBB.xml"
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_parse_manifest() {
+        let manifest = manifest();
+        assert_eq!(manifest.base_location, "downloads.dell.com");
+        assert_eq!(manifest.release_id, "D0T8G");
+        assert_eq!(manifest.version, "2024.09.18");
+        assert_eq!(manifest.software_components.len(), 2);
+        assert_eq!(manifest.others.len(), 1);
+        assert_eq!(manifest.others[0].name, "Prerequisites");
+
+        let me = manifest.component("WR6M5").unwrap();
+        assert_eq!(
+            me.display_name(),
+            "Intel Management Engine Components Installer"
+        );
+        assert_eq!(me.size, Some(174361800));
+        assert_eq!(me.component_type.as_ref().unwrap().value, "DRVR");
+        assert_eq!(me.category.as_ref().unwrap().text(), "Chipset");
+        assert_eq!(me.criticality.as_ref().unwrap().value, "1");
+        assert_eq!(me.system_ids(), vec!["0CBB"]);
+        assert_eq!(me.os_codes().len(), 14);
+        assert_eq!(me.device_groups.len(), 2);
+        assert_eq!(me.applies_to.as_ref().unwrap().len(), 9);
+        let devices = me.supported_dch_devices.as_ref().unwrap();
+        assert_eq!(devices.devices[1].pci_info[0].device_id, "A1BD");
+        assert_eq!(devices.devices[2].generic.len(), 1);
+        assert_eq!(
+            me.cryptography.as_ref().unwrap().hash("sha256"),
+            Some("c4a1ee29aefd96b2d2f4245b4d58fbe0daa7901c73b693722a0b919905df5d8e")
+        );
+
+        let bios = manifest.component("MG49X").unwrap();
+        let device = bios.devices().next().unwrap();
+        assert_eq!(device.component_id, "159");
+        assert_eq!(device.embedded.as_deref(), Some("false"));
+        assert_eq!(
+            display_text(&device.display),
+            "Latitude 5550 / Precision 3590"
+        );
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let manifest = manifest();
+        let xml = manifest.to_xml_string().unwrap();
+        assert!(xml.contains(r#"xmlns="openmanage/cm/dm""#));
+        assert_eq!(xml.parse::<Manifest>().unwrap(), manifest);
+    }
+
+    #[test]
+    fn test_manifest_header() {
+        let manifest = manifest();
+        let file = File::open(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/../Precision_0CBB.xml"
+        ))
+        .unwrap();
+        let header = ManifestHeader::from_reader(BufReader::new(file)).unwrap();
+        assert_eq!(header.version, manifest.version);
+        assert_eq!(header.release_id, manifest.release_id);
+        assert_eq!(header.date_time, manifest.date_time);
+
+        // 根元素之后的内容不会被读取
+        let header = ManifestHeader::from_reader(
+            r#"<Manifest xmlns="openmanage/cm/dm" version="2024.05"><SoftwareComponent"#.as_bytes(),
+        )
+        .unwrap();
+        assert_eq!(header.version, "2024.05");
+        let result = ManifestHeader::from_reader(r#"<Manifest xmlns="other"/>"#.as_bytes());
+        assert!(matches!(result, Err(CatalogError::InvalidCatalog(_))));
+    }
+
+    #[test]
+    fn test_wrong_namespace() {
+        let result = r#"<Manifest xmlns="other"></Manifest>"#.parse::<Manifest>();
+        assert!(matches!(result, Err(CatalogError::InvalidCatalog(_))));
+    }
+}
@@ -0,0 +1,297 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use data_encoding::HEXLOWER;
+use serde::Serialize;
+
+use crate::{
+    detect::compare_versions,
+    hash::HashAlgorithm,
+    manifest::{Manifest, SoftwareComponent, SupportedSystems},
+};
+
+/// 合并后 catalog 的属性, 为空时自动生成
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MergeOptions {
+    pub identifier: Option<String>,
+    pub version: Option<String>,
+    pub date_time: Option<String>,
+}
+
+/// 同一组件在不同 catalog 中的版本不一致, 保留较新的版本
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct MergeConflict {
+    pub package_id: String,
+    pub name: String,
+    pub kept_version: String,
+    pub dropped_version: String,
+}
+
+/// 合并的统计和冲突
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct MergeReport {
+    pub components: usize,
+    /// 被合并到已有组件中的重复组件数
+    pub duplicates: usize,
+    pub conflicts: Vec<MergeConflict>,
+    pub warnings: Vec<String>,
+}
+
+fn same_component(a: &SoftwareComponent, b: &SoftwareComponent) -> bool {
+    (!a.identifier.is_empty() && a.identifier == b.identifier) || a.package_id == b.package_id
+}
+
+/// 按 Brand 的 key 和 Model 的 systemID 合并, 任何一方不限制机型时合并后也不限制
+fn union_systems(target: &mut Option<SupportedSystems>, other: &Option<SupportedSystems>) {
+    let (Some(target), Some(other)) = (target.as_mut(), other) else {
+        *target = None;
+        return;
+    };
+    for brand in other.brands.iter() {
+        match target.brands.iter_mut().find(|b| b.key == brand.key) {
+            Some(existing) => {
+                for model in brand.models.iter() {
+                    if !existing
+                        .models
+                        .iter()
+                        .any(|m| m.system_id.eq_ignore_ascii_case(&model.system_id))
+                    {
+                        existing.models.push(model.clone());
+                    }
+                }
+            }
+            None => target.brands.push(brand.clone()),
+        }
+    }
+}
+
+/// 把 UNIX 时间转换为 `dateTime` 使用的 ISO 8601 格式 (UTC)
+pub(crate) fn format_date_time(secs: u64) -> String {
+    // Howard Hinnant 的 civil_from_days
+    let days = (secs / 86400) as i64;
+    let secs_of_day = secs % 86400;
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}+00:00",
+        year,
+        month,
+        day,
+        secs_of_day / 3600,
+        secs_of_day % 3600 / 60,
+        secs_of_day % 60
+    )
+}
+
+/// 由所有来源的 identifier 和 version 生成的 GUID, 相同的输入得到相同的结果
+fn merged_identifier(manifests: &[Manifest]) -> String {
+    let seed: String = manifests
+        .iter()
+        .map(|m| format!("{}/{};", m.identifier, m.version))
+        .collect();
+    let hex = HEXLOWER.encode(&HashAlgorithm::Sha256.digest(seed.as_bytes())[..16]);
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..32]
+    )
+}
+
+/// 合并多个 catalog, 重复的组件 (identifier 或 packageID 相同) 只保留一个并合并支持的机型
+pub fn merge_manifests(manifests: &[Manifest], options: &MergeOptions) -> (Manifest, MergeReport) {
+    let mut merged = manifests.first().cloned().unwrap_or_default();
+    merged.software_components.clear();
+    merged.others.clear();
+    let mut report = MergeReport::default();
+    // 合并后只有一个 baseLocation, 其他来源的更新包会从这里下载
+    for manifest in manifests.iter().skip(1) {
+        if manifest.base_location != merged.base_location {
+            report.warnings.push(format!(
+                "baseLocation {:?} of {} differs from {:?} used by the merged catalog",
+                manifest.base_location, manifest.identifier, merged.base_location
+            ));
+        }
+    }
+    for warning in report.warnings.iter() {
+        log::warn!("merge_manifests--{}", warning);
+    }
+
+    for manifest in manifests.iter() {
+        for other in manifest.others.iter() {
+            if !merged.others.contains(other) {
+                merged.others.push(other.clone());
+            }
+        }
+        for component in manifest.software_components.iter() {
+            let Some(existing) = merged
+                .software_components
+                .iter_mut()
+                .find(|c| same_component(c, component))
+            else {
+                merged.software_components.push(component.clone());
+                continue;
+            };
+            report.duplicates += 1;
+            if existing.dell_version == component.dell_version {
+                union_systems(
+                    &mut existing.supported_systems,
+                    &component.supported_systems,
+                );
+                continue;
+            }
+            let mut dropped = component.clone();
+            if compare_versions(&component.dell_version, &existing.dell_version).is_gt() {
+                dropped = std::mem::replace(existing, dropped);
+            }
+            // 较新的版本也要提供给只出现在被丢弃的 catalog 中的机型
+            union_systems(&mut existing.supported_systems, &dropped.supported_systems);
+            report.conflicts.push(MergeConflict {
+                package_id: existing.package_id.clone(),
+                name: existing.display_name().to_string(),
+                kept_version: existing.dell_version.clone(),
+                dropped_version: dropped.dell_version,
+            });
+        }
+    }
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+    let date_time = options
+        .date_time
+        .clone()
+        .unwrap_or_else(|| format_date_time(now));
+    merged.version = options
+        .version
+        .clone()
+        .unwrap_or_else(|| date_time.get(..10).unwrap_or(&date_time).replace('-', "."));
+    merged.identifier = options
+        .identifier
+        .clone()
+        .unwrap_or_else(|| merged_identifier(manifests));
+    merged.date_time = date_time;
+    report.components = merged.software_components.len();
+    log::debug!(
+        "merge_manifests--{} catalogs, {} components, {} conflicts",
+        manifests.len(),
+        report.components,
+        report.conflicts.len()
+    );
+    (merged, report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::manifest::Model;
+
+    #[test]
+    fn test_format_date_time() {
+        assert_eq!(format_date_time(0), "1970-01-01T00:00:00+00:00");
+        assert_eq!(format_date_time(1726641079), "2024-09-18T06:31:19+00:00");
+        assert_eq!(format_date_time(951782400), "2000-02-29T00:00:00+00:00");
+    }
+
+    #[test]
+    fn test_merge_manifests() {
+        let mut first = Manifest::from_path(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/../Precision_0CBB.xml"
+        ))
+        .unwrap();
+        let mut second = first.clone();
+        second.identifier = "other".into();
+        // 同一个组件支持另一台机器
+        let systems = second.software_components[0]
+            .supported_systems
+            .as_mut()
+            .unwrap();
+        systems.brands[0].models.push(Model {
+            system_id: "0ABC".into(),
+            display: Vec::new(),
+        });
+        // 另一个组件版本更新, 旧版本的 catalog 中还支持另一台机器
+        second.software_components[1].dell_version = "99.0.0".into();
+        first.software_components[1]
+            .supported_systems
+            .as_mut()
+            .unwrap()
+            .brands[0]
+            .models
+            .push(Model {
+                system_id: "0DEF".into(),
+                display: Vec::new(),
+            });
+        let mut extra = second.software_components[1].clone();
+        extra.package_id = "EXTRA".into();
+        extra.identifier = "extra-identifier".into();
+        second.software_components.push(extra);
+
+        let options = MergeOptions {
+            date_time: Some("2024-10-01T00:00:00+00:00".into()),
+            ..Default::default()
+        };
+        let (merged, report) = merge_manifests(&[first.clone(), second.clone()], &options);
+        assert_eq!(merged.software_components.len(), 3);
+        assert_eq!(report.components, 3);
+        assert_eq!(report.duplicates, 2);
+        assert_eq!(merged.version, "2024.10.01");
+        assert_eq!(merged.date_time, "2024-10-01T00:00:00+00:00");
+        assert_ne!(merged.identifier, first.identifier);
+        assert_eq!(
+            merged.identifier,
+            merge_manifests(&[first.clone(), second], &options)
+                .0
+                .identifier
+        );
+        assert!(merged.software_components[0].system_ids().contains(&"0ABC"));
+        assert_eq!(merged.software_components[1].dell_version, "99.0.0");
+        assert!(merged.software_components[1].system_ids().contains(&"0DEF"));
+        assert_eq!(
+            report.conflicts,
+            [MergeConflict {
+                package_id: first.software_components[1].package_id.clone(),
+                name: first.software_components[1].display_name().to_string(),
+                kept_version: "99.0.0".into(),
+                dropped_version: first.software_components[1].dell_version.clone(),
+            }]
+        );
+
+        assert!(report.warnings.is_empty());
+
+        let reparsed = Manifest::from_reader(merged.to_xml_string().unwrap().as_bytes()).unwrap();
+        assert_eq!(reparsed.software_components, merged.software_components);
+    }
+
+    #[test]
+    fn test_merge_unrestricted_and_base_location() {
+        let first = Manifest::from_path(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/../Precision_0CBB.xml"
+        ))
+        .unwrap();
+        let mut second = first.clone();
+        second.base_location = "mirror.example.com".into();
+        second.software_components[0].supported_systems = None;
+        second.software_components[1].dell_version = "99.0.0".into();
+        second.software_components[1].supported_systems = None;
+
+        let (merged, report) = merge_manifests(&[first.clone(), second], &MergeOptions::default());
+        // 两个来源中有一个不限制机型, 合并后也不限制
+        assert!(merged.software_components[0].supported_systems.is_none());
+        assert!(merged.software_components[1].supported_systems.is_none());
+        assert_eq!(merged.base_location, first.base_location);
+        assert_eq!(report.warnings.len(), 1);
+        assert!(report.warnings[0].contains("mirror.example.com"));
+    }
+}
@@ -0,0 +1,351 @@
+use std::{collections::BTreeMap, fs, path::Path};
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::CatalogError;
+
+mod snapshot;
+#[cfg(windows)]
+mod win;
+
+pub use snapshot::{
+    rollback, KeySnapshot, RegistrySnapshot, SnapshotRegistry, TreeSnapshot, ValueSnapshot,
+};
+
+#[cfg(windows)]
+pub use win::WindowsRegistry;
+
+/// Dell UpdateService 的服务配置
+pub const SERVICE_KEY: &str = r"SOFTWARE\Dell\UpdateService\Service";
+
+/// 注册表值, 覆盖本工具会读写的类型
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", content = "data")]
+pub enum RegValue {
+    Sz(String),
+    MultiSz(Vec<String>),
+    Dword(u32),
+    /// 其他类型原样保存, 以便快照能够还原
+    Other {
+        kind: u32,
+        bytes: Vec<u8>,
+    },
+}
+
+impl RegValue {
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            RegValue::Sz(s) => Some(s),
+            _ => None,
+        }
+    }
+}
+
+/// 对注册表的一次修改, 内存实现会记录下来供测试断言
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RegistryChange {
+    CreateKey {
+        key: String,
+    },
+    WriteValue {
+        key: String,
+        name: String,
+        value: RegValue,
+    },
+    DeleteValue {
+        key: String,
+        name: String,
+    },
+    DeleteTree {
+        key: String,
+    },
+}
+
+/// 注册表读写接口, 路径都相对于 HKEY_LOCAL_MACHINE
+///
+/// 删除不存在的值或键不算错误
+pub trait RegistryBackend {
+    fn key_exists(&self, key: &str) -> Result<bool, CatalogError>;
+
+    fn create_key(&mut self, key: &str) -> Result<(), CatalogError>;
+
+    fn read_value(&self, key: &str, name: &str) -> Result<Option<RegValue>, CatalogError>;
+
+    /// 键不存在时会先创建
+    fn write_value(&mut self, key: &str, name: &str, value: &RegValue) -> Result<(), CatalogError>;
+
+    fn delete_value(&mut self, key: &str, name: &str) -> Result<(), CatalogError>;
+
+    /// 删除键以及所有子键
+    fn delete_tree(&mut self, key: &str) -> Result<(), CatalogError>;
+
+    /// 直接子键的名称, 键不存在时为空
+    fn subkeys(&self, key: &str) -> Result<Vec<String>, CatalogError>;
+
+    /// 键下的所有值, 键不存在时为空
+    fn values(&self, key: &str) -> Result<Vec<(String, RegValue)>, CatalogError>;
+}
+
+/// 便于把 `&mut dyn RegistryBackend` 交给 `SnapshotRegistry` 等包装
+impl<B: RegistryBackend + ?Sized> RegistryBackend for &mut B {
+    fn key_exists(&self, key: &str) -> Result<bool, CatalogError> {
+        (**self).key_exists(key)
+    }
+
+    fn create_key(&mut self, key: &str) -> Result<(), CatalogError> {
+        (**self).create_key(key)
+    }
+
+    fn read_value(&self, key: &str, name: &str) -> Result<Option<RegValue>, CatalogError> {
+        (**self).read_value(key, name)
+    }
+
+    fn write_value(&mut self, key: &str, name: &str, value: &RegValue) -> Result<(), CatalogError> {
+        (**self).write_value(key, name, value)
+    }
+
+    fn delete_value(&mut self, key: &str, name: &str) -> Result<(), CatalogError> {
+        (**self).delete_value(key, name)
+    }
+
+    fn delete_tree(&mut self, key: &str) -> Result<(), CatalogError> {
+        (**self).delete_tree(key)
+    }
+
+    fn subkeys(&self, key: &str) -> Result<Vec<String>, CatalogError> {
+        (**self).subkeys(key)
+    }
+
+    fn values(&self, key: &str) -> Result<Vec<(String, RegValue)>, CatalogError> {
+        (**self).values(key)
+    }
+}
+
+fn normalize_key(key: &str) -> String {
+    key.replace('/', "\\").trim_matches('\\').to_string()
+}
+
+/// 注册表键名不区分大小写, 作为 map 的键统一小写
+fn fold(s: &str) -> String {
+    normalize_key(s).to_lowercase()
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+struct MemoryKey {
+    path: String,
+    values: BTreeMap<String, (String, RegValue)>,
+}
+
+/// 内存中的注册表, 可以保存为 json 文件, 用于测试和非 Windows 平台
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MemoryRegistry {
+    keys: BTreeMap<String, MemoryKey>,
+    #[serde(skip)]
+    changes: Vec<RegistryChange>,
+}
+
+impl MemoryRegistry {
+    pub fn new() -> Self {
+        MemoryRegistry::default()
+    }
+
+    pub fn from_path(path: &Path) -> Result<Self, CatalogError> {
+        Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), CatalogError> {
+        fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// 按调用顺序记录的所有修改
+    pub fn changes(&self) -> &[RegistryChange] {
+        &self.changes
+    }
+
+    pub fn clear_changes(&mut self) {
+        self.changes.clear();
+    }
+
+    /// 所有键的原始路径
+    pub fn keys(&self) -> Vec<&str> {
+        self.keys.values().map(|k| k.path.as_str()).collect()
+    }
+
+    fn entry(&mut self, key: &str) -> &mut MemoryKey {
+        self.keys.entry(fold(key)).or_insert_with(|| MemoryKey {
+            path: normalize_key(key),
+            values: BTreeMap::new(),
+        })
+    }
+}
+
+impl RegistryBackend for MemoryRegistry {
+    fn key_exists(&self, key: &str) -> Result<bool, CatalogError> {
+        let folded = fold(key);
+        let prefix = format!("{}\\", folded);
+        Ok(self
+            .keys
+            .keys()
+            .any(|k| *k == folded || k.starts_with(&prefix)))
+    }
+
+    fn create_key(&mut self, key: &str) -> Result<(), CatalogError> {
+        self.changes.push(RegistryChange::CreateKey {
+            key: normalize_key(key),
+        });
+        self.entry(key);
+        Ok(())
+    }
+
+    fn read_value(&self, key: &str, name: &str) -> Result<Option<RegValue>, CatalogError> {
+        Ok(self
+            .keys
+            .get(&fold(key))
+            .and_then(|k| k.values.get(&name.to_lowercase()))
+            .map(|(_, value)| value.clone()))
+    }
+
+    fn write_value(&mut self, key: &str, name: &str, value: &RegValue) -> Result<(), CatalogError> {
+        self.changes.push(RegistryChange::WriteValue {
+            key: normalize_key(key),
+            name: name.to_string(),
+            value: value.clone(),
+        });
+        self.entry(key)
+            .values
+            .insert(name.to_lowercase(), (name.to_string(), value.clone()));
+        Ok(())
+    }
+
+    fn delete_value(&mut self, key: &str, name: &str) -> Result<(), CatalogError> {
+        self.changes.push(RegistryChange::DeleteValue {
+            key: normalize_key(key),
+            name: name.to_string(),
+        });
+        if let Some(k) = self.keys.get_mut(&fold(key)) {
+            k.values.remove(&name.to_lowercase());
+        }
+        Ok(())
+    }
+
+    fn delete_tree(&mut self, key: &str) -> Result<(), CatalogError> {
+        self.changes.push(RegistryChange::DeleteTree {
+            key: normalize_key(key),
+        });
+        let folded = fold(key);
+        let prefix = format!("{}\\", folded);
+        self.keys
+            .retain(|k, _| *k != folded && !k.starts_with(&prefix));
+        Ok(())
+    }
+
+    fn subkeys(&self, key: &str) -> Result<Vec<String>, CatalogError> {
+        let folded = fold(key);
+        let prefix = format!("{}\\", folded);
+        let depth = folded.split('\\').count();
+        let mut names: Vec<String> = Vec::new();
+        // 中间的键不一定单独保存, 由更深的路径推出
+        for (k, value) in self.keys.iter() {
+            if !k.starts_with(&prefix) {
+                continue;
+            }
+            if let Some(name) = value.path.split('\\').nth(depth) {
+                if !names.iter().any(|n| n.eq_ignore_ascii_case(name)) {
+                    names.push(name.to_string());
+                }
+            }
+        }
+        Ok(names)
+    }
+
+    fn values(&self, key: &str) -> Result<Vec<(String, RegValue)>, CatalogError> {
+        Ok(self
+            .keys
+            .get(&fold(key))
+            .map(|k| k.values.values().cloned().collect())
+            .unwrap_or_default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_memory_registry() {
+        let mut registry = MemoryRegistry::new();
+        let key = r"SOFTWARE\Dell\UpdateService\Service";
+        assert!(!registry.key_exists(key).unwrap());
+
+        registry
+            .write_value(key, "CustomCatalogHashValues", &RegValue::Sz("{}".into()))
+            .unwrap();
+        registry
+            .create_key(&format!(r"{}\IgnoreList", key))
+            .unwrap();
+        assert!(registry.key_exists(r"software\dell\updateservice").unwrap());
+        assert_eq!(
+            registry
+                .read_value(&key.to_uppercase(), "customcataloghashvalues")
+                .unwrap(),
+            Some(RegValue::Sz("{}".into()))
+        );
+
+        registry.delete_value(key, "Missing").unwrap();
+        registry.delete_tree(key).unwrap();
+        assert!(!registry.key_exists(key).unwrap());
+        assert!(!registry
+            .key_exists(&format!(r"{}\IgnoreList", key))
+            .unwrap());
+        assert_eq!(registry.changes().len(), 4);
+    }
+
+    #[test]
+    fn test_memory_registry_enumerate() {
+        let mut registry = MemoryRegistry::new();
+        registry
+            .write_value(r"A\B\C", "x", &RegValue::Dword(1))
+            .unwrap();
+        registry.create_key(r"A\D").unwrap();
+        registry.write_value("A", "y", &RegValue::Dword(2)).unwrap();
+        assert_eq!(registry.subkeys("a").unwrap(), ["B", "D"]);
+        assert_eq!(registry.subkeys(r"A\B").unwrap(), ["C"]);
+        assert!(registry.subkeys("missing").unwrap().is_empty());
+        assert_eq!(
+            registry.values("A").unwrap(),
+            [("y".to_string(), RegValue::Dword(2))]
+        );
+    }
+
+    #[test]
+    fn test_memory_registry_json_file() {
+        let mut registry = MemoryRegistry::new();
+        let key = r"SOFTWARE\Dell\UpdateService\Clients\CommandUpdate";
+        registry
+            .write_value(
+                key,
+                "Paths",
+                &RegValue::MultiSz(vec!["a".into(), "b".into()]),
+            )
+            .unwrap();
+        registry
+            .write_value(key, "Enabled", &RegValue::Dword(1))
+            .unwrap();
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("registry.json");
+        registry.save(&path).unwrap();
+        let loaded = MemoryRegistry::from_path(&path).unwrap();
+        assert!(loaded.changes().is_empty());
+        assert_eq!(loaded.keys(), vec![key]);
+        assert_eq!(
+            loaded.read_value(key, "enabled").unwrap(),
+            Some(RegValue::Dword(1))
+        );
+        assert_eq!(
+            loaded.read_value(key, "Paths").unwrap(),
+            Some(RegValue::MultiSz(vec!["a".into(), "b".into()]))
+        );
+    }
+}
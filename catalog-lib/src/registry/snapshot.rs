@@ -0,0 +1,482 @@
+use std::{
+    fs::{self, OpenOptions},
+    io::{self, ErrorKind, Write},
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::CatalogError;
+
+use super::{fold, normalize_key, RegValue, RegistryBackend};
+
+/// 同一时间 (秒) 最多保存的快照数
+const MAX_SNAPSHOTS_PER_TIME: u32 = 1000;
+
+/// 修改前某个值的状态, `value` 为 None 表示原来不存在
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ValueSnapshot {
+    pub key: String,
+    pub name: String,
+    pub value: Option<RegValue>,
+}
+
+/// 修改前某个键本身的值
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeySnapshot {
+    pub key: String,
+    pub values: Vec<(String, RegValue)>,
+}
+
+/// 修改前整棵子树的状态, `keys` 为空表示原来不存在
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TreeSnapshot {
+    pub key: String,
+    pub keys: Vec<KeySnapshot>,
+}
+
+/// 一次 catalog 安装前被修改的注册表内容
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RegistrySnapshot {
+    /// 创建时间, unix 毫秒
+    pub created: u64,
+    pub values: Vec<ValueSnapshot>,
+    pub trees: Vec<TreeSnapshot>,
+}
+
+fn is_under(key: &str, tree: &str) -> bool {
+    let (key, tree) = (fold(key), fold(tree));
+    key == tree || key.starts_with(&format!("{}\\", tree))
+}
+
+impl RegistrySnapshot {
+    pub fn new() -> Self {
+        RegistrySnapshot {
+            created: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_millis() as u64)
+                .unwrap_or(0),
+            ..Default::default()
+        }
+    }
+
+    /// 带时间戳的文件名
+    pub fn file_name(&self) -> String {
+        self.numbered_file_name(0)
+    }
+
+    /// 同一时间的快照加上序号, 如 `registry-snapshot-<时间>-1.json`
+    fn numbered_file_name(&self, number: u32) -> String {
+        match number {
+            0 => format!("registry-snapshot-{}.json", self.created),
+            n => format!("registry-snapshot-{}-{}.json", self.created, n),
+        }
+    }
+
+    /// 在 `dir` 中保存为一个新文件, 不会覆盖同一时间创建的其他快照
+    pub fn save_new(&self, dir: &Path) -> Result<PathBuf, CatalogError> {
+        fs::create_dir_all(dir)?;
+        let json = serde_json::to_string_pretty(self)?;
+        for number in 0..MAX_SNAPSHOTS_PER_TIME {
+            let path = dir.join(self.numbered_file_name(number));
+            match OpenOptions::new().write(true).create_new(true).open(&path) {
+                Ok(mut file) => {
+                    file.write_all(json.as_bytes())?;
+                    return Ok(path);
+                }
+                Err(e) if e.kind() == ErrorKind::AlreadyExists => continue,
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Err(io::Error::new(
+            ErrorKind::AlreadyExists,
+            format!(
+                "{} snapshots created at {} in {:?}",
+                MAX_SNAPSHOTS_PER_TIME, self.created, dir
+            ),
+        )
+        .into())
+    }
+
+    pub fn from_path(path: &Path) -> Result<Self, CatalogError> {
+        Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), CatalogError> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// 目录中最新的快照文件, 按时间和序号比较
+    pub fn latest_in(dir: &Path) -> Option<PathBuf> {
+        fs::read_dir(dir)
+            .ok()?
+            .filter_map(Result::ok)
+            .filter_map(|e| {
+                let name = e.file_name();
+                let stem = name
+                    .to_str()?
+                    .strip_prefix("registry-snapshot-")?
+                    .strip_suffix(".json")?;
+                let (created, number) = match stem.split_once('-') {
+                    Some((created, number)) => (created.parse().ok()?, number.parse().ok()?),
+                    None => (stem.parse::<u64>().ok()?, 0u32),
+                };
+                Some((created, number, e.path()))
+            })
+            .max()
+            .map(|(_, _, path)| path)
+    }
+
+    fn covered(&self, key: &str) -> bool {
+        self.trees.iter().any(|t| is_under(key, &t.key))
+    }
+
+    /// 记录一个值的原始状态, 已经记录过的不会覆盖; 返回是否有新记录
+    pub fn capture_value(
+        &mut self,
+        registry: &dyn RegistryBackend,
+        key: &str,
+        name: &str,
+    ) -> Result<bool, CatalogError> {
+        if self.covered(key)
+            || self
+                .values
+                .iter()
+                .any(|v| fold(&v.key) == fold(key) && v.name.eq_ignore_ascii_case(name))
+        {
+            return Ok(false);
+        }
+        if !registry.key_exists(key)? {
+            // 写入时会创建键, 回滚时删除最上层新建的键
+            return self.capture_missing_key(registry, key);
+        }
+        self.values.push(ValueSnapshot {
+            key: normalize_key(key),
+            name: name.to_string(),
+            value: registry.read_value(key, name)?,
+        });
+        Ok(true)
+    }
+
+    fn capture_missing_key(
+        &mut self,
+        registry: &dyn RegistryBackend,
+        key: &str,
+    ) -> Result<bool, CatalogError> {
+        let key = normalize_key(key);
+        let mut top = key.as_str();
+        while let Some((parent, _)) = top.rsplit_once('\\') {
+            if registry.key_exists(parent)? {
+                break;
+            }
+            top = parent;
+        }
+        self.capture_tree(registry, top)
+    }
+
+    /// 记录整棵子树, 包括所有子键和值
+    pub fn capture_tree(
+        &mut self,
+        registry: &dyn RegistryBackend,
+        key: &str,
+    ) -> Result<bool, CatalogError> {
+        if self.covered(key) {
+            return Ok(false);
+        }
+        let mut keys = Vec::new();
+        if registry.key_exists(key)? {
+            let mut pending = vec![normalize_key(key)];
+            while let Some(current) = pending.pop() {
+                for name in registry.subkeys(&current)? {
+                    pending.push(format!("{}\\{}", current, name));
+                }
+                keys.push(KeySnapshot {
+                    values: registry.values(&current)?,
+                    key: current,
+                });
+            }
+        }
+        // 新的子树包含了之前单独记录的值
+        self.values.retain(|v| !is_under(&v.key, key));
+        self.trees.retain(|t| !is_under(&t.key, key));
+        self.trees.push(TreeSnapshot {
+            key: normalize_key(key),
+            keys,
+        });
+        Ok(true)
+    }
+
+    fn capture_key(
+        &mut self,
+        registry: &dyn RegistryBackend,
+        key: &str,
+    ) -> Result<bool, CatalogError> {
+        if self.covered(key) || registry.key_exists(key)? {
+            return Ok(false);
+        }
+        self.capture_missing_key(registry, key)
+    }
+}
+
+/// 把注册表恢复到快照记录的状态, 包括重新创建被删除的子键
+pub fn rollback(
+    registry: &mut dyn RegistryBackend,
+    snapshot: &RegistrySnapshot,
+) -> Result<(), CatalogError> {
+    for tree in snapshot.trees.iter() {
+        log::info!("rollback tree--{}", tree.key);
+        registry.delete_tree(&tree.key)?;
+        for key in tree.keys.iter() {
+            registry.create_key(&key.key)?;
+            for (name, value) in key.values.iter() {
+                registry.write_value(&key.key, name, value)?;
+            }
+        }
+    }
+    for value in snapshot.values.iter() {
+        log::info!("rollback value--{}\\{}", value.key, value.name);
+        match &value.value {
+            Some(v) => registry.write_value(&value.key, &value.name, v)?,
+            None => registry.delete_value(&value.key, &value.name)?,
+        }
+    }
+    Ok(())
+}
+
+/// 在每次修改之前记录原始状态的注册表包装
+///
+/// 设置了 `dir` 时, 每次有新记录都会先写入快照文件再执行修改
+pub struct SnapshotRegistry<B: RegistryBackend> {
+    inner: B,
+    snapshot: RegistrySnapshot,
+    dir: Option<PathBuf>,
+    /// 第一次写入时在 `dir` 中新建的文件
+    path: Option<PathBuf>,
+}
+
+impl<B: RegistryBackend> SnapshotRegistry<B> {
+    pub fn new(inner: B) -> Self {
+        SnapshotRegistry {
+            inner,
+            snapshot: RegistrySnapshot::new(),
+            dir: None,
+            path: None,
+        }
+    }
+
+    /// 快照保存在 `dir` 下带时间戳的文件中
+    pub fn with_dir(inner: B, dir: &Path) -> Self {
+        SnapshotRegistry {
+            dir: Some(dir.to_path_buf()),
+            ..Self::new(inner)
+        }
+    }
+
+    pub fn snapshot(&self) -> &RegistrySnapshot {
+        &self.snapshot
+    }
+
+    /// 快照文件, 还没有修改过注册表时为空
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    pub fn into_inner(self) -> (B, RegistrySnapshot) {
+        (self.inner, self.snapshot)
+    }
+
+    fn persist(&mut self, captured: bool) -> Result<(), CatalogError> {
+        if !captured {
+            return Ok(());
+        }
+        match (&self.path, &self.dir) {
+            (Some(path), _) => self.snapshot.save(path)?,
+            (None, Some(dir)) => self.path = Some(self.snapshot.save_new(dir)?),
+            (None, None) => {}
+        }
+        Ok(())
+    }
+}
+
+impl<B: RegistryBackend> RegistryBackend for SnapshotRegistry<B> {
+    fn key_exists(&self, key: &str) -> Result<bool, CatalogError> {
+        self.inner.key_exists(key)
+    }
+
+    fn create_key(&mut self, key: &str) -> Result<(), CatalogError> {
+        let captured = self.snapshot.capture_key(&self.inner, key)?;
+        self.persist(captured)?;
+        self.inner.create_key(key)
+    }
+
+    fn read_value(&self, key: &str, name: &str) -> Result<Option<RegValue>, CatalogError> {
+        self.inner.read_value(key, name)
+    }
+
+    fn write_value(&mut self, key: &str, name: &str, value: &RegValue) -> Result<(), CatalogError> {
+        let captured = self.snapshot.capture_value(&self.inner, key, name)?;
+        self.persist(captured)?;
+        self.inner.write_value(key, name, value)
+    }
+
+    fn delete_value(&mut self, key: &str, name: &str) -> Result<(), CatalogError> {
+        if !self.inner.key_exists(key)? {
+            return Ok(());
+        }
+        let captured = self.snapshot.capture_value(&self.inner, key, name)?;
+        self.persist(captured)?;
+        self.inner.delete_value(key, name)
+    }
+
+    fn delete_tree(&mut self, key: &str) -> Result<(), CatalogError> {
+        if !self.inner.key_exists(key)? {
+            return Ok(());
+        }
+        let captured = self.snapshot.capture_tree(&self.inner, key)?;
+        self.persist(captured)?;
+        self.inner.delete_tree(key)
+    }
+
+    fn subkeys(&self, key: &str) -> Result<Vec<String>, CatalogError> {
+        self.inner.subkeys(key)
+    }
+
+    fn values(&self, key: &str) -> Result<Vec<(String, RegValue)>, CatalogError> {
+        self.inner.values(key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::MemoryRegistry;
+    use super::*;
+
+    const SERVICE: &str = r"SOFTWARE\Dell\UpdateService\Service";
+
+    fn stock_registry() -> MemoryRegistry {
+        let mut registry = MemoryRegistry::new();
+        let ignore_list = format!(r"{}\IgnoreList\Nested", SERVICE);
+        registry
+            .create_key(&format!(r"{}\IgnoreList", SERVICE))
+            .unwrap();
+        registry
+            .write_value(
+                SERVICE,
+                "CustomCatalogHashValues",
+                &RegValue::Sz("old".into()),
+            )
+            .unwrap();
+        registry
+            .write_value(SERVICE, "LastCheckTimestamp", &RegValue::Sz("1".into()))
+            .unwrap();
+        registry
+            .write_value(
+                &ignore_list,
+                "Package",
+                &RegValue::MultiSz(vec!["A".into()]),
+            )
+            .unwrap();
+        registry
+    }
+
+    #[test]
+    fn test_snapshot_and_rollback() {
+        let original = stock_registry();
+        let dir = tempfile::tempdir().unwrap();
+        let mut registry = SnapshotRegistry::with_dir(original.clone(), dir.path());
+
+        registry
+            .write_value(
+                SERVICE,
+                "CustomCatalogHashValues",
+                &RegValue::Sz("new".into()),
+            )
+            .unwrap();
+        registry
+            .write_value(
+                SERVICE,
+                "CustomCatalogHashValues",
+                &RegValue::Sz("newer".into()),
+            )
+            .unwrap();
+        registry
+            .delete_value(SERVICE, "LastCheckTimestamp")
+            .unwrap();
+        registry.delete_value(SERVICE, "CatalogTimestamp").unwrap();
+        registry
+            .delete_tree(&format!(r"{}\IgnoreList", SERVICE))
+            .unwrap();
+        registry
+            .write_value(r"SOFTWARE\Dell\New\Deep", "Enabled", &RegValue::Dword(1))
+            .unwrap();
+
+        let path = registry.path().unwrap().to_path_buf();
+        assert_eq!(RegistrySnapshot::latest_in(dir.path()), Some(path.clone()));
+        let (mut modified, snapshot) = registry.into_inner();
+        assert_eq!(RegistrySnapshot::from_path(&path).unwrap(), snapshot);
+        assert_eq!(snapshot.values.len(), 3);
+        assert_eq!(snapshot.trees.len(), 2);
+        assert_ne!(
+            modified.values(SERVICE).unwrap(),
+            original.values(SERVICE).unwrap()
+        );
+
+        rollback(&mut modified, &snapshot).unwrap();
+        let mut restored_keys = modified.keys();
+        restored_keys.sort();
+        let mut original_keys = original.keys();
+        original_keys.sort();
+        assert_eq!(restored_keys, original_keys);
+        for key in original_keys {
+            assert_eq!(modified.values(key).unwrap(), original.values(key).unwrap());
+        }
+        assert!(!modified.key_exists(r"SOFTWARE\Dell\New").unwrap());
+    }
+
+    #[test]
+    fn test_latest_in() {
+        let dir = tempfile::tempdir().unwrap();
+        assert_eq!(RegistrySnapshot::latest_in(dir.path()), None);
+        for created in [99, 300, 1000] {
+            let snapshot = RegistrySnapshot {
+                created,
+                ..Default::default()
+            };
+            snapshot
+                .save(&dir.path().join(snapshot.file_name()))
+                .unwrap();
+        }
+        fs::write(dir.path().join("other.json"), "{}").unwrap();
+        assert_eq!(
+            RegistrySnapshot::latest_in(dir.path()),
+            Some(dir.path().join("registry-snapshot-1000.json"))
+        );
+
+        // 同一时间的快照不会互相覆盖
+        let snapshot = RegistrySnapshot {
+            created: 1000,
+            ..Default::default()
+        };
+        let second = snapshot.save_new(dir.path()).unwrap();
+        assert_eq!(second, dir.path().join("registry-snapshot-1000-1.json"));
+        assert_eq!(RegistrySnapshot::latest_in(dir.path()), Some(second));
+        assert_eq!(
+            snapshot.save_new(dir.path()).unwrap(),
+            dir.path().join("registry-snapshot-1000-2.json")
+        );
+
+        // 同一时间的序号用完时返回错误
+        for number in 3..MAX_SNAPSHOTS_PER_TIME {
+            fs::write(dir.path().join(snapshot.numbered_file_name(number)), "{}").unwrap();
+        }
+        assert!(matches!(
+            snapshot.save_new(dir.path()),
+            Err(CatalogError::IoError(e)) if e.kind() == ErrorKind::AlreadyExists
+        ));
+    }
+}
@@ -0,0 +1,283 @@
+use windows::{
+    core::{PCWSTR, PWSTR},
+    Win32::{
+        Foundation::{ERROR_FILE_NOT_FOUND, ERROR_NO_MORE_ITEMS},
+        System::Registry::{
+            self, HKEY, HKEY_LOCAL_MACHINE, KEY_ALL_ACCESS, KEY_READ, REG_DWORD, REG_MULTI_SZ,
+            REG_OPTION_NON_VOLATILE, REG_SAM_FLAGS, REG_SZ, REG_VALUE_TYPE,
+        },
+    },
+};
+
+use crate::error::CatalogError;
+
+use super::{normalize_key, RegValue, RegistryBackend};
+
+/// 以 0 结尾的 UTF-16 字符串, 调用期间必须保持存活
+fn wide(s: &str) -> Vec<u16> {
+    s.encode_utf16().chain(std::iter::once(0)).collect()
+}
+
+fn utf16_bytes(units: impl Iterator<Item = u16>) -> Vec<u8> {
+    units.flat_map(|unit| unit.to_le_bytes()).collect()
+}
+
+fn utf16_units(bytes: &[u8]) -> Vec<u16> {
+    bytes
+        .chunks_exact(2)
+        .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+        .collect()
+}
+
+/// 打开的注册表键, 离开作用域时关闭
+struct KeyHandle(HKEY);
+
+impl Drop for KeyHandle {
+    fn drop(&mut self) {
+        unsafe {
+            let _ = Registry::RegCloseKey(self.0);
+        }
+    }
+}
+
+fn open_key(key: &str, access: REG_SAM_FLAGS) -> Result<Option<KeyHandle>, CatalogError> {
+    let sub_key = wide(&normalize_key(key));
+    let mut handle = HKEY::default();
+    let res = unsafe {
+        Registry::RegOpenKeyExW(
+            HKEY_LOCAL_MACHINE,
+            PCWSTR::from_raw(sub_key.as_ptr()),
+            0,
+            access,
+            &mut handle,
+        )
+    };
+    if res == ERROR_FILE_NOT_FOUND {
+        return Ok(None);
+    }
+    res.ok()?;
+    Ok(Some(KeyHandle(handle)))
+}
+
+fn create_key(key: &str) -> Result<KeyHandle, CatalogError> {
+    let sub_key = wide(&normalize_key(key));
+    let mut handle = HKEY::default();
+    unsafe {
+        Registry::RegCreateKeyExW(
+            HKEY_LOCAL_MACHINE,
+            PCWSTR::from_raw(sub_key.as_ptr()),
+            0,
+            PCWSTR::null(),
+            REG_OPTION_NON_VOLATILE,
+            KEY_ALL_ACCESS,
+            None,
+            &mut handle,
+            None,
+        )
+    }
+    .ok()?;
+    Ok(KeyHandle(handle))
+}
+
+/// 基于 Win32 API 的 HKEY_LOCAL_MACHINE 读写, 需要管理员权限
+#[derive(Debug, Clone, Copy, Default)]
+pub struct WindowsRegistry;
+
+impl RegistryBackend for WindowsRegistry {
+    fn key_exists(&self, key: &str) -> Result<bool, CatalogError> {
+        Ok(open_key(key, KEY_READ)?.is_some())
+    }
+
+    fn create_key(&mut self, key: &str) -> Result<(), CatalogError> {
+        create_key(key)?;
+        Ok(())
+    }
+
+    fn read_value(&self, key: &str, name: &str) -> Result<Option<RegValue>, CatalogError> {
+        let Some(handle) = open_key(key, KEY_READ)? else {
+            return Ok(None);
+        };
+        let value_name = wide(name);
+        let mut kind = REG_VALUE_TYPE::default();
+        let mut size = 0u32;
+        let res = unsafe {
+            Registry::RegQueryValueExW(
+                handle.0,
+                PCWSTR::from_raw(value_name.as_ptr()),
+                None,
+                Some(&mut kind),
+                None,
+                Some(&mut size),
+            )
+        };
+        if res == ERROR_FILE_NOT_FOUND {
+            return Ok(None);
+        }
+        res.ok()?;
+        let mut bytes = vec![0u8; size as usize];
+        unsafe {
+            Registry::RegQueryValueExW(
+                handle.0,
+                PCWSTR::from_raw(value_name.as_ptr()),
+                None,
+                Some(&mut kind),
+                Some(bytes.as_mut_ptr()),
+                Some(&mut size),
+            )
+        }
+        .ok()?;
+        bytes.truncate(size as usize);
+
+        let value = match kind {
+            REG_SZ => {
+                let units = utf16_units(&bytes);
+                RegValue::Sz(
+                    String::from_utf16_lossy(&units)
+                        .trim_end_matches('\0')
+                        .to_string(),
+                )
+            }
+            REG_MULTI_SZ => {
+                let units = utf16_units(&bytes);
+                RegValue::MultiSz(
+                    String::from_utf16_lossy(&units)
+                        .split('\0')
+                        .filter(|s| !s.is_empty())
+                        .map(String::from)
+                        .collect(),
+                )
+            }
+            REG_DWORD if bytes.len() == 4 => {
+                RegValue::Dword(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+            }
+            _ => RegValue::Other {
+                kind: kind.0,
+                bytes,
+            },
+        };
+        Ok(Some(value))
+    }
+
+    fn write_value(&mut self, key: &str, name: &str, value: &RegValue) -> Result<(), CatalogError> {
+        let handle = create_key(key)?;
+        let (kind, bytes) = match value {
+            RegValue::Sz(s) => (REG_SZ, utf16_bytes(s.encode_utf16().chain([0]))),
+            RegValue::MultiSz(strings) => {
+                // 每个字符串以 0 结尾, 整体再多一个 0
+                let units = strings
+                    .iter()
+                    .flat_map(|s| s.encode_utf16().chain([0]))
+                    .chain([0]);
+                (REG_MULTI_SZ, utf16_bytes(units))
+            }
+            RegValue::Dword(d) => (REG_DWORD, d.to_le_bytes().to_vec()),
+            RegValue::Other { kind, bytes } => (REG_VALUE_TYPE(*kind), bytes.clone()),
+        };
+        log::debug!("write_value--{}\\{}--{:?}", key, name, value);
+        let value_name = wide(name);
+        unsafe {
+            Registry::RegSetValueExW(
+                handle.0,
+                PCWSTR::from_raw(value_name.as_ptr()),
+                0,
+                kind,
+                Some(&bytes),
+            )
+        }
+        .ok()?;
+        Ok(())
+    }
+
+    fn delete_value(&mut self, key: &str, name: &str) -> Result<(), CatalogError> {
+        let Some(handle) = open_key(key, KEY_ALL_ACCESS)? else {
+            return Ok(());
+        };
+        let value_name = wide(name);
+        let res =
+            unsafe { Registry::RegDeleteValueW(handle.0, PCWSTR::from_raw(value_name.as_ptr())) };
+        if res != ERROR_FILE_NOT_FOUND {
+            res.ok()?;
+        }
+        Ok(())
+    }
+
+    /// 指定子键时 RegDeleteTreeW 会连同该键本身一起删除
+    fn delete_tree(&mut self, key: &str) -> Result<(), CatalogError> {
+        let sub_key = wide(&normalize_key(key));
+        let res = unsafe {
+            Registry::RegDeleteTreeW(HKEY_LOCAL_MACHINE, PCWSTR::from_raw(sub_key.as_ptr()))
+        };
+        if res != ERROR_FILE_NOT_FOUND {
+            res.ok()?;
+        }
+        Ok(())
+    }
+
+    fn subkeys(&self, key: &str) -> Result<Vec<String>, CatalogError> {
+        let Some(handle) = open_key(key, KEY_READ)? else {
+            return Ok(Vec::new());
+        };
+        let mut names = Vec::new();
+        // 键名最长 255 个字符
+        let mut buffer = [0u16; 256];
+        for index in 0.. {
+            let mut len = buffer.len() as u32;
+            let res = unsafe {
+                Registry::RegEnumKeyExW(
+                    handle.0,
+                    index,
+                    PWSTR::from_raw(buffer.as_mut_ptr()),
+                    &mut len,
+                    None,
+                    PWSTR::null(),
+                    None,
+                    None,
+                )
+            };
+            if res == ERROR_NO_MORE_ITEMS {
+                break;
+            }
+            res.ok()?;
+            names.push(String::from_utf16_lossy(&buffer[..len as usize]));
+        }
+        Ok(names)
+    }
+
+    fn values(&self, key: &str) -> Result<Vec<(String, RegValue)>, CatalogError> {
+        let Some(handle) = open_key(key, KEY_READ)? else {
+            return Ok(Vec::new());
+        };
+        let mut names = Vec::new();
+        // 值名最长 16383 个字符
+        let mut buffer = vec![0u16; 16384];
+        for index in 0.. {
+            let mut len = buffer.len() as u32;
+            let res = unsafe {
+                Registry::RegEnumValueW(
+                    handle.0,
+                    index,
+                    PWSTR::from_raw(buffer.as_mut_ptr()),
+                    &mut len,
+                    None,
+                    None,
+                    None,
+                    None,
+                )
+            };
+            if res == ERROR_NO_MORE_ITEMS {
+                break;
+            }
+            res.ok()?;
+            names.push(String::from_utf16_lossy(&buffer[..len as usize]));
+        }
+        drop(handle);
+
+        let mut values = Vec::new();
+        for name in names {
+            if let Some(value) = self.read_value(key, &name)? {
+                values.push((name, value));
+            }
+        }
+        Ok(values)
+    }
+}
@@ -0,0 +1,478 @@
+use std::{fs, ops::Range, path::Path};
+
+use crate::error::CatalogError;
+
+mod rules;
+
+pub use rules::{Predicate, RewriteAction, RewriteRule, RewriteRules, RULES_FILE_NAME};
+
+const BOM_UTF8: &[u8] = &[0xEF, 0xBB, 0xBF];
+const BOM_UTF16_LE: &[u8] = &[0xFF, 0xFE];
+const BOM_UTF16_BE: &[u8] = &[0xFE, 0xFF];
+
+/// xml 文件的字节编码
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextEncoding {
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+}
+
+/// 解码后的 xml 文本, 记录原始编码和 BOM 以便原样写回
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct XmlText {
+    pub encoding: TextEncoding,
+    pub bom: bool,
+    pub text: String,
+}
+
+impl XmlText {
+    pub fn from_path(path: &Path) -> Result<Self, CatalogError> {
+        Self::from_bytes(&fs::read(path)?)
+    }
+
+    /// 按 BOM 或前两个字节判断编码并解码
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, CatalogError> {
+        let (encoding, bom, body) = if let Some(body) = bytes.strip_prefix(BOM_UTF8) {
+            (TextEncoding::Utf8, true, body)
+        } else if let Some(body) = bytes.strip_prefix(BOM_UTF16_LE) {
+            (TextEncoding::Utf16Le, true, body)
+        } else if let Some(body) = bytes.strip_prefix(BOM_UTF16_BE) {
+            (TextEncoding::Utf16Be, true, body)
+        } else if bytes.starts_with(&[b'<', 0]) {
+            (TextEncoding::Utf16Le, false, bytes)
+        } else if bytes.starts_with(&[0, b'<']) {
+            (TextEncoding::Utf16Be, false, bytes)
+        } else {
+            (TextEncoding::Utf8, false, bytes)
+        };
+
+        let text = match encoding {
+            TextEncoding::Utf8 => String::from_utf8(body.to_vec())
+                .map_err(|e| CatalogError::ParseError(e.to_string()))?,
+            TextEncoding::Utf16Le | TextEncoding::Utf16Be => {
+                if body.len() % 2 != 0 {
+                    return Err(CatalogError::ParseError(
+                        "odd number of bytes in UTF-16 xml".into(),
+                    ));
+                }
+                let units: Vec<u16> = body
+                    .chunks_exact(2)
+                    .map(|pair| match encoding {
+                        TextEncoding::Utf16Be => u16::from_be_bytes([pair[0], pair[1]]),
+                        _ => u16::from_le_bytes([pair[0], pair[1]]),
+                    })
+                    .collect();
+                String::from_utf16(&units).map_err(|e| CatalogError::ParseError(e.to_string()))?
+            }
+        };
+        Ok(XmlText {
+            encoding,
+            bom,
+            text,
+        })
+    }
+
+    /// 按原始编码和 BOM 重新编码
+    pub fn to_bytes(&self) -> Vec<u8> {
+        match self.encoding {
+            TextEncoding::Utf8 => {
+                let mut bytes = Vec::with_capacity(self.text.len() + 3);
+                if self.bom {
+                    bytes.extend_from_slice(BOM_UTF8);
+                }
+                bytes.extend_from_slice(self.text.as_bytes());
+                bytes
+            }
+            TextEncoding::Utf16Le => {
+                let mut bytes = Vec::with_capacity(self.text.len() * 2 + 2);
+                if self.bom {
+                    bytes.extend_from_slice(BOM_UTF16_LE);
+                }
+                for unit in self.text.encode_utf16() {
+                    bytes.extend_from_slice(&unit.to_le_bytes());
+                }
+                bytes
+            }
+            TextEncoding::Utf16Be => {
+                let mut bytes = Vec::with_capacity(self.text.len() * 2 + 2);
+                if self.bom {
+                    bytes.extend_from_slice(BOM_UTF16_BE);
+                }
+                for unit in self.text.encode_utf16() {
+                    bytes.extend_from_slice(&unit.to_be_bytes());
+                }
+                bytes
+            }
+        }
+    }
+
+    pub fn write_to_path(&self, path: &Path) -> Result<(), CatalogError> {
+        fs::write(path, self.to_bytes())?;
+        Ok(())
+    }
+}
+
+fn find_end(text: &str, from: usize, pattern: &str) -> Result<usize, CatalogError> {
+    text[from..]
+        .find(pattern)
+        .map(|offset| from + offset + pattern.len())
+        .ok_or_else(|| {
+            CatalogError::ParseError(format!("unterminated xml markup, expected {}", pattern))
+        })
+}
+
+fn local_name(name: &str) -> &str {
+    name.rsplit(':').next().unwrap_or(name)
+}
+
+fn unescape(value: &str) -> Result<String, CatalogError> {
+    let mut result = String::with_capacity(value.len());
+    let mut rest = value;
+    while let Some(start) = rest.find('&') {
+        result.push_str(&rest[..start]);
+        let end = rest[start..]
+            .find(';')
+            .map(|offset| start + offset)
+            .ok_or_else(|| CatalogError::ParseError(format!("bad entity in {:?}", value)))?;
+        let entity = &rest[start + 1..end];
+        let c = match entity {
+            "amp" => '&',
+            "lt" => '<',
+            "gt" => '>',
+            "quot" => '"',
+            "apos" => '\'',
+            _ => {
+                let code = if let Some(hex) = entity.strip_prefix("#x") {
+                    u32::from_str_radix(hex, 16).ok()
+                } else if let Some(dec) = entity.strip_prefix('#') {
+                    dec.parse().ok()
+                } else {
+                    None
+                };
+                code.and_then(char::from_u32)
+                    .ok_or_else(|| CatalogError::ParseError(format!("bad entity &{};", entity)))?
+            }
+        };
+        result.push(c);
+        rest = &rest[end + 1..];
+    }
+    result.push_str(rest);
+    Ok(result)
+}
+
+fn escape(value: &str, quote: char) -> String {
+    let mut result = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => result.push_str("&amp;"),
+            '<' => result.push_str("&lt;"),
+            '"' if quote == '"' => result.push_str("&quot;"),
+            '\'' if quote == '\'' => result.push_str("&apos;"),
+            _ => result.push(c),
+        }
+    }
+    result
+}
+
+/// 开始标签的元素本地名和反转义后的属性
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StartTag<'a> {
+    pub element: &'a str,
+    pub attributes: Vec<(&'a str, String)>,
+}
+
+impl StartTag<'_> {
+    pub fn attr(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+/// 改写开始标签中的属性值, 其余字节 (声明, 换行, 属性顺序, 引号) 原样保留
+///
+/// 回调参数为所在的开始标签以及属性的本地名和值, 返回 `Some` 时替换该值
+pub fn rewrite_attributes<F>(text: &str, mut rewrite: F) -> Result<String, CatalogError>
+where
+    F: FnMut(&StartTag, &str, &str) -> Option<String>,
+{
+    let bytes = text.as_bytes();
+    let is_space = |b: u8| matches!(b, b' ' | b'\t' | b'\r' | b'\n');
+    let unexpected_end = || CatalogError::ParseError("unexpected end of xml start tag".into());
+    let mut output = String::with_capacity(text.len());
+    let mut copied = 0;
+    let mut pos = 0;
+
+    while let Some(offset) = text[pos..].find('<') {
+        let start = pos + offset;
+        let rest = &text[start..];
+        if rest.starts_with("<!--") {
+            pos = find_end(text, start, "-->")?;
+            continue;
+        }
+        if rest.starts_with("<![CDATA[") {
+            pos = find_end(text, start, "]]>")?;
+            continue;
+        }
+        if rest.starts_with("<?") {
+            pos = find_end(text, start, "?>")?;
+            continue;
+        }
+        if rest.starts_with("<!") || rest.starts_with("</") {
+            pos = find_end(text, start, ">")?;
+            continue;
+        }
+
+        pos = start + 1;
+        while pos < bytes.len() && !is_space(bytes[pos]) && !matches!(bytes[pos], b'/' | b'>') {
+            pos += 1;
+        }
+        let mut tag = StartTag {
+            element: local_name(&text[start + 1..pos]),
+            attributes: Vec::new(),
+        };
+        // 属性值在原文中的位置和引号
+        let mut spans = Vec::new();
+        loop {
+            while pos < bytes.len() && is_space(bytes[pos]) {
+                pos += 1;
+            }
+            match bytes.get(pos) {
+                None => return Err(unexpected_end()),
+                Some(b'>') | Some(b'/') => break,
+                _ => {}
+            }
+            let name_start = pos;
+            while pos < bytes.len() && !is_space(bytes[pos]) && bytes[pos] != b'=' {
+                pos += 1;
+            }
+            let attribute = local_name(&text[name_start..pos]);
+            while pos < bytes.len() && is_space(bytes[pos]) {
+                pos += 1;
+            }
+            if bytes.get(pos) != Some(&b'=') {
+                return Err(CatalogError::ParseError(format!(
+                    "attribute {} without value in <{}>",
+                    attribute, tag.element
+                )));
+            }
+            pos += 1;
+            while pos < bytes.len() && is_space(bytes[pos]) {
+                pos += 1;
+            }
+            let quote = match bytes.get(pos) {
+                Some(&q @ (b'"' | b'\'')) => q as char,
+                _ => {
+                    return Err(CatalogError::ParseError(format!(
+                        "unquoted attribute {} in <{}>",
+                        attribute, tag.element
+                    )))
+                }
+            };
+            let value_start = pos + 1;
+            let value_end = text[value_start..]
+                .find(quote)
+                .map(|offset| value_start + offset)
+                .ok_or_else(unexpected_end)?;
+            tag.attributes
+                .push((attribute, unescape(&text[value_start..value_end])?));
+            spans.push((value_start, value_end, quote));
+            pos = value_end + 1;
+        }
+
+        for ((attribute, value), (value_start, value_end, quote)) in
+            tag.attributes.iter().zip(spans)
+        {
+            if let Some(new_value) = rewrite(&tag, attribute, value) {
+                output.push_str(&text[copied..value_start]);
+                output.push_str(&escape(&new_value, quote));
+                copied = value_end;
+            }
+        }
+    }
+    output.push_str(&text[copied..]);
+    Ok(output)
+}
+
+/// 查找所有 `element` 元素 (包括自闭合的) 在原文中的位置, 注释和 CDATA 中的内容会被跳过
+pub fn element_spans(text: &str, element: &str) -> Result<Vec<Range<usize>>, CatalogError> {
+    let bytes = text.as_bytes();
+    let mut spans = Vec::new();
+    let mut open: Option<usize> = None;
+    let mut pos = 0;
+
+    while let Some(offset) = text[pos..].find('<') {
+        let start = pos + offset;
+        let rest = &text[start..];
+        if rest.starts_with("<!--") {
+            pos = find_end(text, start, "-->")?;
+            continue;
+        }
+        if rest.starts_with("<![CDATA[") {
+            pos = find_end(text, start, "]]>")?;
+            continue;
+        }
+        if rest.starts_with("<?") || rest.starts_with("<!") {
+            pos = find_end(text, start, ">")?;
+            continue;
+        }
+        if let Some(close) = rest.strip_prefix("</") {
+            pos = find_end(text, start, ">")?;
+            let name = close
+                .split(|c: char| c == '>' || c.is_whitespace())
+                .next()
+                .unwrap_or_default();
+            if local_name(name) == element {
+                if let Some(open_start) = open.take() {
+                    spans.push(open_start..pos);
+                }
+            }
+            continue;
+        }
+
+        let name_end = start
+            + 1
+            + rest[1..]
+                .find(|c: char| c.is_whitespace() || c == '/' || c == '>')
+                .unwrap_or(rest.len() - 1);
+        // 属性值中可能有 `>`, 按引号跳过
+        let mut quote = None;
+        let mut end = name_end;
+        loop {
+            match (bytes.get(end), quote) {
+                (None, _) => {
+                    return Err(CatalogError::ParseError(
+                        "unexpected end of xml start tag".into(),
+                    ))
+                }
+                (Some(&q), Some(open_quote)) if q == open_quote => quote = None,
+                (Some(&q @ (b'"' | b'\'')), None) => quote = Some(q),
+                (Some(b'>'), None) => break,
+                _ => {}
+            }
+            end += 1;
+        }
+        pos = end + 1;
+        if open.is_none() && local_name(&text[start + 1..name_end]) == element {
+            if bytes[end - 1] == b'/' {
+                spans.push(start..pos);
+            } else {
+                open = Some(start);
+            }
+        }
+    }
+    if open.is_some() {
+        return Err(CatalogError::ParseError(format!("unclosed <{}>", element)));
+    }
+    Ok(spans)
+}
+
+/// 删除给定位置的内容以及它前面的空白, 位置需要按顺序排列且不重叠
+pub fn remove_spans(text: &str, spans: &[Range<usize>]) -> String {
+    let mut output = String::with_capacity(text.len());
+    let mut copied = 0;
+    for span in spans {
+        let start = copied.max(text[..span.start].trim_end().len());
+        output.push_str(&text[copied..start]);
+        copied = span.end;
+    }
+    output.push_str(&text[copied..]);
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FIXTURE: &[u8] = include_bytes!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/../Precision_0CBB.xml"
+    ));
+
+    #[test]
+    fn test_decode_encode_identity() {
+        let document = XmlText::from_bytes(FIXTURE).unwrap();
+        assert_eq!(document.encoding, TextEncoding::Utf16Le);
+        assert!(document.bom);
+        assert!(document
+            .text
+            .starts_with("<?xml version=\"1.0\" encoding=\"utf-16\"?>\r\n"));
+        assert_eq!(document.to_bytes(), FIXTURE);
+
+        let unchanged = rewrite_attributes(&document.text, |_, _, _| None).unwrap();
+        assert_eq!(unchanged, document.text);
+    }
+
+    #[test]
+    fn test_rewrite_golden() {
+        let mut document = XmlText::from_bytes(FIXTURE).unwrap();
+        let expected = document
+            .text
+            .replace("baseLocation=\"downloads.dell.com\"", "baseLocation=\"\"")
+            .replace("path=\"FOLDER12085904M/1/", "path=\"");
+        document.text = rewrite_attributes(&document.text, |tag, attribute, value| {
+            match (tag.element, attribute) {
+                ("Manifest", "baseLocation") => Some(String::new()),
+                ("SoftwareComponent", "path") if value.contains("Precision_3590") => {
+                    value.rsplit('/').next().map(String::from)
+                }
+                _ => None,
+            }
+        })
+        .unwrap();
+        assert_eq!(document.text, expected);
+
+        let bytes = document.to_bytes();
+        assert_eq!(&bytes[..2], BOM_UTF16_LE);
+        assert_eq!(
+            bytes,
+            XmlText {
+                encoding: TextEncoding::Utf16Le,
+                bom: true,
+                text: expected,
+            }
+            .to_bytes()
+        );
+    }
+
+    #[test]
+    fn test_rewrite_escapes() {
+        let text = "<?xml version='1.0'?><!-- <a b='c'/> --><a x='1' b = 'A&amp;B'><![CDATA[<a b='z'>]]><ns:a b=\"q\"/></a>";
+        let mut seen = Vec::new();
+        let result = rewrite_attributes(text, |tag, attribute, value| {
+            seen.push(format!("{}.{}={}", tag.element, attribute, value));
+            (attribute == "b").then(|| format!("{}'\"<", value))
+        })
+        .unwrap();
+        assert_eq!(seen, ["a.x=1", "a.b=A&B", "a.b=q"]);
+        assert_eq!(
+            result,
+            "<?xml version='1.0'?><!-- <a b='c'/> --><a x='1' b = 'A&amp;B&apos;\"&lt;'><![CDATA[<a b='z'>]]><ns:a b=\"q'&quot;&lt;\"/></a>"
+        );
+    }
+
+    #[test]
+    fn test_element_spans() {
+        let text = "<m>\r\n  <c a='>'>\r\n    <d>x</d>\r\n  </c>\r\n  <!-- <c/> -->\r\n  <c b=\"1\"/>\r\n  <ns:c><![CDATA[</c>]]></ns:c>\r\n</m>";
+        let spans = element_spans(text, "c").unwrap();
+        assert_eq!(spans.len(), 3);
+        assert_eq!(&text[spans[1].clone()], "<c b=\"1\"/>");
+        assert_eq!(
+            remove_spans(text, &spans[..2]),
+            "<m>\r\n  <!-- <c/> -->\r\n  <ns:c><![CDATA[</c>]]></ns:c>\r\n</m>"
+        );
+        assert!(element_spans("<m><c>", "c").is_err());
+    }
+
+    #[test]
+    fn test_utf16_be_round_trip() {
+        let document = XmlText {
+            encoding: TextEncoding::Utf16Be,
+            bom: false,
+            text: "<a b=\"中文\"/>".into(),
+        };
+        assert_eq!(XmlText::from_bytes(&document.to_bytes()).unwrap(), document);
+    }
+}
@@ -0,0 +1,249 @@
+use std::{fs, path::Path};
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::{error::CatalogError, filter::ComponentFilter};
+
+use super::{rewrite_attributes, StartTag};
+
+/// 默认的规则配置文件名, 放在 cab 文件旁边
+pub const RULES_FILE_NAME: &str = "rewrite_rules.json";
+
+/// 对属性值的修改方式
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum RewriteAction {
+    /// 设置为固定值
+    Set { value: String },
+    /// 设置为空字符串
+    Clear,
+    /// 去掉前缀, 不以该前缀开头时保持不变
+    StripPrefix { prefix: String },
+    /// 正则替换, `replacement` 中可以使用 `$1` 等捕获组
+    RegexReplace {
+        pattern: String,
+        replacement: String,
+    },
+}
+
+/// 规则生效的前提: 同一元素上另一个属性的值匹配正则
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Predicate {
+    pub attribute: String,
+    pub matches: String,
+}
+
+/// 一条改写规则, 作用于某个元素的某个属性
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RewriteRule {
+    pub element: String,
+    pub attribute: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub when: Vec<Predicate>,
+    #[serde(flatten)]
+    pub action: RewriteAction,
+}
+
+/// 按顺序执行的改写规则集合
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RewriteRules {
+    pub rules: Vec<RewriteRule>,
+    /// 改写之后再按条件删除组件, 用于生成只包含少数组件的测试 catalog
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub filter: Option<ComponentFilter>,
+}
+
+impl Default for RewriteRules {
+    /// 清空 `Manifest@baseLocation`, `SoftwareComponent@path` 只保留文件名
+    fn default() -> Self {
+        RewriteRules {
+            rules: vec![
+                RewriteRule {
+                    element: "Manifest".into(),
+                    attribute: "baseLocation".into(),
+                    when: Vec::new(),
+                    action: RewriteAction::Clear,
+                },
+                RewriteRule {
+                    element: "SoftwareComponent".into(),
+                    attribute: "path".into(),
+                    when: Vec::new(),
+                    action: RewriteAction::RegexReplace {
+                        pattern: "^.*/".into(),
+                        replacement: String::new(),
+                    },
+                },
+            ],
+            filter: None,
+        }
+    }
+}
+
+fn compile(pattern: &str) -> Result<Regex, CatalogError> {
+    Regex::new(pattern)
+        .map_err(|e| CatalogError::Config(format!("rule pattern {}: {}", pattern, e)))
+}
+
+/// 预先编译好正则的规则
+struct CompiledRule<'a> {
+    rule: &'a RewriteRule,
+    when: Vec<(&'a str, Regex)>,
+    regex: Option<Regex>,
+}
+
+impl CompiledRule<'_> {
+    fn applies(&self, tag: &StartTag, attribute: &str) -> bool {
+        self.rule.element == tag.element
+            && self.rule.attribute == attribute
+            && self
+                .when
+                .iter()
+                .all(|(name, regex)| tag.attr(name).is_some_and(|v| regex.is_match(v)))
+    }
+
+    fn apply(&self, value: &str) -> String {
+        match (&self.rule.action, &self.regex) {
+            (RewriteAction::Set { value }, _) => value.clone(),
+            (RewriteAction::Clear, _) => String::new(),
+            (RewriteAction::StripPrefix { prefix }, _) => {
+                value.strip_prefix(prefix.as_str()).unwrap_or(value).into()
+            }
+            (RewriteAction::RegexReplace { replacement, .. }, Some(regex)) => {
+                regex.replace_all(value, replacement.as_str()).into_owned()
+            }
+            (RewriteAction::RegexReplace { .. }, None) => value.into(),
+        }
+    }
+}
+
+impl RewriteRules {
+    pub fn from_json(json: &str) -> Result<Self, CatalogError> {
+        let rules: RewriteRules = serde_json::from_str(json)?;
+        // 提前检查正则, 避免改写到一半才报错
+        rules.compile()?;
+        Ok(rules)
+    }
+
+    pub fn from_path(path: &Path) -> Result<Self, CatalogError> {
+        Self::from_json(&fs::read_to_string(path)?)
+    }
+
+    /// 把 `Manifest@baseLocation` 改为固定值, 替换已有的 baseLocation 规则, 用于本地 HTTP 服务
+    pub fn with_base_location(mut self, base_location: &str) -> Self {
+        self.rules
+            .retain(|r| !(r.element == "Manifest" && r.attribute == "baseLocation"));
+        self.rules.push(RewriteRule {
+            element: "Manifest".into(),
+            attribute: "baseLocation".into(),
+            when: Vec::new(),
+            action: RewriteAction::Set {
+                value: base_location.into(),
+            },
+        });
+        self
+    }
+
+    /// 配置文件存在时读取, 否则使用默认规则
+    pub fn load_or_default(path: &Path) -> Result<Self, CatalogError> {
+        if path.is_file() {
+            log::info!("load rewrite rules--{:?}", path);
+            Self::from_path(path)
+        } else {
+            Ok(Self::default())
+        }
+    }
+
+    pub fn to_json(&self) -> Result<String, CatalogError> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    fn compile(&self) -> Result<Vec<CompiledRule<'_>>, CatalogError> {
+        self.rules
+            .iter()
+            .map(|rule| {
+                let when = rule
+                    .when
+                    .iter()
+                    .map(|p| Ok((p.attribute.as_str(), compile(&p.matches)?)))
+                    .collect::<Result<_, CatalogError>>()?;
+                let regex = match &rule.action {
+                    RewriteAction::RegexReplace { pattern, .. } => Some(compile(pattern)?),
+                    _ => None,
+                };
+                Ok(CompiledRule { rule, when, regex })
+            })
+            .collect()
+    }
+
+    /// 对 xml 文本依次应用所有规则, 同一属性上的多条规则按顺序叠加
+    pub fn apply(&self, text: &str) -> Result<String, CatalogError> {
+        let rules = self.compile()?;
+        rewrite_attributes(text, |tag, attribute, value| {
+            let mut result: Option<String> = None;
+            for rule in rules.iter().filter(|r| r.applies(tag, attribute)) {
+                result = Some(rule.apply(result.as_deref().unwrap_or(value)));
+            }
+            result
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const XML: &str = r#"<Manifest baseLocation="downloads.dell.com"><SoftwareComponent packageType="LWXP" path="FOLDER1/1/a.exe"/><SoftwareComponent packageType="LW64" path="b.exe"/></Manifest>"#;
+
+    #[test]
+    fn test_default_rules() {
+        let result = RewriteRules::default().apply(XML).unwrap();
+        assert_eq!(
+            result,
+            r#"<Manifest baseLocation=""><SoftwareComponent packageType="LWXP" path="a.exe"/><SoftwareComponent packageType="LW64" path="b.exe"/></Manifest>"#
+        );
+    }
+
+    #[test]
+    fn test_with_base_location() {
+        let rules = RewriteRules::default().with_base_location("http://127.0.0.1:8080/");
+        assert_eq!(rules.rules.len(), 2);
+        assert_eq!(
+            rules.apply(XML).unwrap(),
+            r#"<Manifest baseLocation="http://127.0.0.1:8080/"><SoftwareComponent packageType="LWXP" path="a.exe"/><SoftwareComponent packageType="LW64" path="b.exe"/></Manifest>"#
+        );
+    }
+
+    #[test]
+    fn test_rules_from_json() {
+        let rules = RewriteRules::from_json(
+            r#"{"rules": [
+                {"element": "Manifest", "attribute": "baseLocation", "action": "set", "value": "\\\\server\\share"},
+                {"element": "SoftwareComponent", "attribute": "path", "action": "strip_prefix", "prefix": "FOLDER1/"},
+                {"element": "SoftwareComponent", "attribute": "path", "action": "regex_replace",
+                 "pattern": "^(\\d+)/", "replacement": "mirror/$1/",
+                 "when": [{"attribute": "packageType", "matches": "^LWXP$"}]}
+            ]}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            rules.apply(XML).unwrap(),
+            r#"<Manifest baseLocation="\\server\share"><SoftwareComponent packageType="LWXP" path="mirror/1/a.exe"/><SoftwareComponent packageType="LW64" path="b.exe"/></Manifest>"#
+        );
+        assert_eq!(
+            RewriteRules::from_json(&rules.to_json().unwrap()).unwrap(),
+            rules
+        );
+    }
+
+    #[test]
+    fn test_invalid_rules() {
+        let bad_regex = r#"{"rules": [{"element": "a", "attribute": "b", "action": "regex_replace", "pattern": "(", "replacement": ""}]}"#;
+        assert!(matches!(
+            RewriteRules::from_json(bad_regex),
+            Err(CatalogError::Config(_))
+        ));
+        let bad_action = r#"{"rules": [{"element": "a", "attribute": "b", "action": "rename"}]}"#;
+        assert!(RewriteRules::from_json(bad_action).is_err());
+    }
+}
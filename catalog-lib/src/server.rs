@@ -0,0 +1,577 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{Read, Seek, SeekFrom, Write},
+    net::{IpAddr, SocketAddr},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde_json::json;
+use tiny_http::{Header, Method, Request, Response, Server, StatusCode};
+
+use crate::{error::CatalogError, merge::format_date_time};
+
+/// 健康检查地址, 返回 json
+pub const HEALTH_PATH: &str = "/health";
+
+/// 监听地址和访问日志
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServeOptions {
+    pub host: String,
+    /// 0 表示由系统分配
+    pub port: u16,
+    /// 写入 `baseLocation` 的主机名, 监听 0.0.0.0 供其他机器访问时需要给出
+    pub public_host: Option<String>,
+    /// 访问日志追加写入的文件, 每一行同时以 info 级别写入日志
+    pub access_log: Option<PathBuf>,
+    /// 同时处理请求的线程数, 一个大文件的下载不会阻塞其他机器
+    pub workers: usize,
+}
+
+impl Default for ServeOptions {
+    fn default() -> Self {
+        ServeOptions {
+            host: "127.0.0.1".into(),
+            port: 0,
+            public_host: None,
+            access_log: None,
+            workers: 8,
+        }
+    }
+}
+
+/// `Range` 请求头的解析结果, 只支持单个区间, 多个区间时发送整个文件
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ByteRange {
+    Full,
+    /// 起止位置, 都包含在内
+    Partial(u64, u64),
+    Unsatisfiable,
+}
+
+impl ByteRange {
+    pub fn parse(header: Option<&str>, len: u64) -> ByteRange {
+        let Some(spec) = header.and_then(|h| h.trim().strip_prefix("bytes=")) else {
+            return ByteRange::Full;
+        };
+        if spec.contains(',') {
+            return ByteRange::Full;
+        }
+        let Some((start, end)) = spec.trim().split_once('-') else {
+            return ByteRange::Full;
+        };
+        let range = match (start.parse::<u64>(), end.parse::<u64>()) {
+            // bytes=-n: 最后 n 个字节
+            (Err(_), Ok(suffix)) if start.is_empty() => {
+                (len.saturating_sub(suffix), len.wrapping_sub(1))
+            }
+            (Ok(start), Err(_)) if end.is_empty() => (start, len.wrapping_sub(1)),
+            (Ok(start), Ok(end)) if start <= end => (start, end.min(len.wrapping_sub(1))),
+            // 格式错误的 Range 按规范忽略
+            _ => return ByteRange::Full,
+        };
+        if len == 0 || range.0 >= len {
+            ByteRange::Unsatisfiable
+        } else {
+            ByteRange::Partial(range.0, range.1)
+        }
+    }
+}
+
+fn header(field: &str, value: &str) -> Header {
+    Header::from_bytes(field.as_bytes(), value.as_bytes()).expect("ascii header")
+}
+
+fn content_type(path: &Path) -> &'static str {
+    let extension = path
+        .extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    match extension.as_str() {
+        "xml" => "application/xml",
+        "cab" => "application/vnd.ms-cab-compressed",
+        "json" => "application/json",
+        _ => "application/octet-stream",
+    }
+}
+
+fn percent_decode(text: &str) -> Option<String> {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = text.get(i + 1..i + 3)?;
+            decoded.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(decoded).ok()
+}
+
+/// 请求地址对应的文件, 不允许 `..` 离开根目录
+fn resolve(root: &Path, url: &str) -> Option<PathBuf> {
+    let path = percent_decode(url.split(['?', '#']).next().unwrap_or_default())?;
+    let mut resolved = root.to_path_buf();
+    for part in path.split(['/', '\\']) {
+        match part {
+            "" | "." => {}
+            ".." => return None,
+            part if part.contains(':') => return None,
+            part => resolved.push(part),
+        }
+    }
+    resolved.is_file().then_some(resolved)
+}
+
+struct Shared {
+    root: PathBuf,
+    base_url: String,
+    access_log: Option<Mutex<File>>,
+    requests: AtomicU64,
+    stopping: AtomicBool,
+}
+
+impl Shared {
+    fn log(&self, request: &Request, status: u16, bytes: u64) {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        let line = format!(
+            "{} [{}] \"{} {} HTTP/{}\" {} {}",
+            request
+                .remote_addr()
+                .map(|addr| addr.ip().to_string())
+                .unwrap_or_else(|| "-".into()),
+            format_date_time(now),
+            request.method(),
+            request.url(),
+            request.http_version(),
+            status,
+            bytes
+        );
+        log::info!("CatalogServer--{}", line);
+        if let Some(file) = &self.access_log {
+            if let Ok(mut file) = file.lock() {
+                if let Err(e) = writeln!(file, "{}", line) {
+                    log::warn!("CatalogServer--access log: {}", e);
+                }
+            }
+        }
+    }
+
+    fn handle(&self, request: Request) {
+        self.requests.fetch_add(1, Ordering::Relaxed);
+        let (status, bytes, result) = self.respond(request);
+        if let Err(e) = result {
+            // 客户端中断下载也会走到这里
+            log::warn!("CatalogServer--{} {}: {}", status, bytes, e);
+        }
+    }
+
+    fn respond(&self, request: Request) -> (u16, u64, std::io::Result<()>) {
+        let send_body = *request.method() == Method::Get;
+        if !send_body && *request.method() != Method::Head {
+            self.log(&request, 405, 0);
+            let response = Response::empty(405).with_header(header("Allow", "GET, HEAD"));
+            return (405, 0, request.respond(response));
+        }
+        if request.url().split('?').next() == Some(HEALTH_PATH) {
+            let body = json!({
+                "status": "ok",
+                "root": self.root,
+                "base_url": self.base_url,
+                "requests": self.requests.load(Ordering::Relaxed),
+            })
+            .to_string();
+            let bytes = if send_body { body.len() as u64 } else { 0 };
+            self.log(&request, 200, bytes);
+            let response =
+                Response::from_string(body).with_header(header("Content-Type", "application/json"));
+            return (200, bytes, request.respond(response));
+        }
+        let Some(path) = resolve(&self.root, request.url()) else {
+            self.log(&request, 404, 0);
+            return (404, 0, request.respond(Response::empty(404)));
+        };
+        let opened = File::open(&path).and_then(|file| {
+            let len = file.metadata()?.len();
+            Ok((file, len))
+        });
+        let (mut file, len) = match opened {
+            Ok(opened) => opened,
+            Err(e) => {
+                self.log(&request, 500, 0);
+                let result = request.respond(Response::empty(500));
+                return (500, 0, result.and(Err(e)));
+            }
+        };
+        let range = request
+            .headers()
+            .iter()
+            .find(|h| h.field.equiv("Range"))
+            .map(|h| h.value.as_str());
+        let mut headers = vec![
+            header("Content-Type", content_type(&path)),
+            header("Accept-Ranges", "bytes"),
+        ];
+        let (status, start, length) = match ByteRange::parse(range, len) {
+            ByteRange::Full => (200, 0, len),
+            ByteRange::Partial(start, end) => {
+                headers.push(header(
+                    "Content-Range",
+                    &format!("bytes {}-{}/{}", start, end, len),
+                ));
+                (206, start, end - start + 1)
+            }
+            ByteRange::Unsatisfiable => {
+                self.log(&request, 416, 0);
+                let response = Response::empty(416)
+                    .with_header(header("Content-Range", &format!("bytes */{}", len)));
+                return (416, 0, request.respond(response));
+            }
+        };
+        if let Err(e) = file.seek(SeekFrom::Start(start)) {
+            self.log(&request, 500, 0);
+            let result = request.respond(Response::empty(500));
+            return (500, 0, result.and(Err(e)));
+        }
+        let bytes = if send_body { length } else { 0 };
+        self.log(&request, status, bytes);
+        let response = Response::new(
+            StatusCode(status),
+            headers,
+            file.take(length),
+            Some(length as usize),
+            None,
+        );
+        (status, bytes, request.respond(response))
+    }
+}
+
+/// 提供 catalog 和更新包下载的本地 HTTP 服务, 根目录就是 catalog 所在的目录
+pub struct CatalogServer {
+    server: Arc<Server>,
+    shared: Arc<Shared>,
+    addr: SocketAddr,
+    workers: usize,
+}
+
+impl CatalogServer {
+    pub fn bind(root: &Path, options: &ServeOptions) -> Result<Self, CatalogError> {
+        // 0.0.0.0 或 :: 写入 baseLocation 后客户端无法访问
+        let unspecified = options
+            .host
+            .trim_matches(['[', ']'])
+            .parse::<IpAddr>()
+            .is_ok_and(|ip| ip.is_unspecified());
+        if unspecified && options.public_host.is_none() {
+            return Err(CatalogError::ServerError(format!(
+                "listening on {} needs a public host for baseLocation",
+                options.host
+            )));
+        }
+        let server = Server::http((options.host.as_str(), options.port))
+            .map_err(|e| CatalogError::ServerError(e.to_string()))?;
+        let addr = server
+            .server_addr()
+            .to_ip()
+            .ok_or_else(|| CatalogError::ServerError("not listening on an IP address".into()))?;
+        let host = options
+            .public_host
+            .clone()
+            .unwrap_or_else(|| addr.ip().to_string());
+        let base_url = if host.contains(':') {
+            format!("http://[{}]:{}/", host, addr.port())
+        } else {
+            format!("http://{}:{}/", host, addr.port())
+        };
+        let access_log = match &options.access_log {
+            Some(path) => Some(Mutex::new(
+                OpenOptions::new().create(true).append(true).open(path)?,
+            )),
+            None => None,
+        };
+        log::info!("CatalogServer--{:?} at {}", root, base_url);
+        Ok(CatalogServer {
+            server: Arc::new(server),
+            shared: Arc::new(Shared {
+                root: root.to_path_buf(),
+                base_url,
+                access_log,
+                requests: AtomicU64::new(0),
+                stopping: AtomicBool::new(false),
+            }),
+            addr,
+            workers: options.workers.max(1),
+        })
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// 写入 `baseLocation` 的地址, 以 `/` 结尾
+    pub fn base_url(&self) -> &str {
+        &self.shared.base_url
+    }
+
+    /// 用 `workers` 个线程处理请求, 阻塞到 `stop`
+    pub fn run(&self) {
+        thread::scope(|scope| {
+            for _ in 0..self.workers {
+                scope.spawn(|| loop {
+                    match self.server.recv() {
+                        Ok(request) => self.shared.handle(request),
+                        Err(_) if self.shared.stopping.load(Ordering::Relaxed) => break,
+                        Err(e) => log::warn!("CatalogServer--{}", e),
+                    }
+                });
+            }
+        });
+    }
+
+    /// 在后台线程处理请求, 返回的句柄被 drop 时停止服务
+    pub fn spawn(self) -> RunningServer {
+        let server = Arc::new(self);
+        let thread = {
+            let server = server.clone();
+            thread::spawn(move || server.run())
+        };
+        RunningServer {
+            server,
+            thread: Some(thread),
+        }
+    }
+
+    pub fn stop(&self) {
+        self.shared.stopping.store(true, Ordering::Relaxed);
+        // 每次只唤醒一个在 recv 中等待的线程
+        for _ in 0..self.workers {
+            self.server.unblock();
+        }
+    }
+}
+
+/// 在后台运行的 `CatalogServer`
+pub struct RunningServer {
+    server: Arc<CatalogServer>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl RunningServer {
+    pub fn addr(&self) -> SocketAddr {
+        self.server.addr()
+    }
+
+    pub fn base_url(&self) -> &str {
+        self.server.base_url()
+    }
+}
+
+impl Drop for RunningServer {
+    fn drop(&mut self) {
+        self.server.stop();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::{fs, io::Read, net::TcpStream, sync::mpsc, time::Duration};
+
+    use super::*;
+
+    /// 发送一个请求, 返回状态码, 响应头 (小写) 和内容
+    pub(crate) fn request(
+        addr: SocketAddr,
+        method: &str,
+        path: &str,
+        headers: &[(&str, &str)],
+    ) -> (u16, Vec<(String, String)>, Vec<u8>) {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        let mut text = format!(
+            "{} {} HTTP/1.1\r\nHost: {}\r\nConnection: close\r\n",
+            method, path, addr
+        );
+        for (name, value) in headers {
+            text.push_str(&format!("{}: {}\r\n", name, value));
+        }
+        text.push_str("\r\n");
+        stream.write_all(text.as_bytes()).unwrap();
+        let mut response = Vec::new();
+        stream.read_to_end(&mut response).unwrap();
+        let split = response.windows(4).position(|w| w == b"\r\n\r\n").unwrap();
+        let head = String::from_utf8_lossy(&response[..split]).into_owned();
+        let mut lines = head.lines();
+        let status = lines.next().unwrap().split(' ').nth(1).unwrap();
+        let headers = lines
+            .filter_map(|line| line.split_once(':'))
+            .map(|(k, v)| (k.trim().to_lowercase(), v.trim().to_string()))
+            .collect();
+        (
+            status.parse().unwrap(),
+            headers,
+            response[split + 4..].to_vec(),
+        )
+    }
+
+    fn get_header<'a>(headers: &'a [(String, String)], name: &str) -> Option<&'a str> {
+        headers
+            .iter()
+            .find(|(k, _)| k == name)
+            .map(|(_, v)| v.as_str())
+    }
+
+    #[test]
+    fn test_byte_range() {
+        assert_eq!(ByteRange::parse(None, 10), ByteRange::Full);
+        assert_eq!(
+            ByteRange::parse(Some("bytes=2-5"), 10),
+            ByteRange::Partial(2, 5)
+        );
+        assert_eq!(
+            ByteRange::parse(Some("bytes=7-"), 10),
+            ByteRange::Partial(7, 9)
+        );
+        assert_eq!(
+            ByteRange::parse(Some("bytes=-3"), 10),
+            ByteRange::Partial(7, 9)
+        );
+        assert_eq!(
+            ByteRange::parse(Some("bytes=5-100"), 10),
+            ByteRange::Partial(5, 9)
+        );
+        assert_eq!(
+            ByteRange::parse(Some("bytes=-100"), 10),
+            ByteRange::Partial(0, 9)
+        );
+        assert_eq!(
+            ByteRange::parse(Some("bytes=10-"), 10),
+            ByteRange::Unsatisfiable
+        );
+        assert_eq!(
+            ByteRange::parse(Some("bytes=-0"), 10),
+            ByteRange::Unsatisfiable
+        );
+        assert_eq!(
+            ByteRange::parse(Some("bytes=0-"), 0),
+            ByteRange::Unsatisfiable
+        );
+        assert_eq!(ByteRange::parse(Some("bytes=5-2"), 10), ByteRange::Full);
+        assert_eq!(ByteRange::parse(Some("bytes=0-1,3-4"), 10), ByteRange::Full);
+        assert_eq!(ByteRange::parse(Some("items=0-1"), 10), ByteRange::Full);
+    }
+
+    #[test]
+    fn test_catalog_server() {
+        let root = tempfile::tempdir().unwrap();
+        let log_dir = tempfile::tempdir().unwrap();
+        fs::create_dir(root.path().join("sub dir")).unwrap();
+        fs::write(root.path().join("sub dir/a.exe"), b"0123456789").unwrap();
+        let options = ServeOptions {
+            access_log: Some(log_dir.path().join("access.log")),
+            ..Default::default()
+        };
+        let server = CatalogServer::bind(root.path(), &options).unwrap().spawn();
+        let addr = server.addr();
+        assert_eq!(
+            server.base_url(),
+            format!("http://127.0.0.1:{}/", addr.port())
+        );
+
+        let (status, headers, body) = request(addr, "GET", "/sub%20dir/a.exe", &[]);
+        assert_eq!(status, 200);
+        assert_eq!(body, b"0123456789");
+
+        let unspecified = ServeOptions {
+            host: "0.0.0.0".into(),
+            ..Default::default()
+        };
+        assert!(matches!(
+            CatalogServer::bind(root.path(), &unspecified),
+            Err(CatalogError::ServerError(_))
+        ));
+        let public = ServeOptions {
+            public_host: Some("catalog.lan".into()),
+            ..unspecified
+        };
+        let public = CatalogServer::bind(root.path(), &public).unwrap();
+        assert!(public.base_url().starts_with("http://catalog.lan:"));
+        assert_eq!(get_header(&headers, "accept-ranges"), Some("bytes"));
+
+        let (status, headers, body) =
+            request(addr, "GET", "//sub%20dir/a.exe", &[("Range", "bytes=2-5")]);
+        assert_eq!(status, 206);
+        assert_eq!(body, b"2345");
+        assert_eq!(get_header(&headers, "content-range"), Some("bytes 2-5/10"));
+
+        let (status, _, body) = request(addr, "GET", "/sub%20dir/a.exe", &[("Range", "bytes=-3")]);
+        assert_eq!((status, body.as_slice()), (206, b"789".as_slice()));
+
+        let (status, headers, _) =
+            request(addr, "GET", "/sub%20dir/a.exe", &[("Range", "bytes=10-")]);
+        assert_eq!(status, 416);
+        assert_eq!(get_header(&headers, "content-range"), Some("bytes */10"));
+
+        let (status, headers, body) = request(addr, "HEAD", "/sub%20dir/a.exe", &[]);
+        assert_eq!(status, 200);
+        assert!(body.is_empty());
+        assert_eq!(get_header(&headers, "content-length"), Some("10"));
+
+        assert_eq!(request(addr, "GET", "/../a.exe", &[]).0, 404);
+        assert_eq!(request(addr, "GET", "/missing.exe", &[]).0, 404);
+        assert_eq!(request(addr, "POST", "/sub%20dir/a.exe", &[]).0, 405);
+
+        let (status, _, body) = request(addr, "GET", HEALTH_PATH, &[]);
+        assert_eq!(status, 200);
+        let health: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(health["status"], "ok");
+        assert_eq!(health["requests"], 9);
+
+        drop(server);
+        let log = fs::read_to_string(log_dir.path().join("access.log")).unwrap();
+        assert_eq!(log.lines().count(), 9);
+        assert!(log
+            .lines()
+            .nth(1)
+            .unwrap()
+            .ends_with("\"GET //sub%20dir/a.exe HTTP/1.1\" 206 4"));
+    }
+
+    #[test]
+    fn test_concurrent_requests() {
+        let root = tempfile::tempdir().unwrap();
+        // 比本机 socket 缓冲区大, 客户端不读取时处理它的线程会一直阻塞
+        fs::write(root.path().join("big.exe"), vec![0u8; 32 << 20]).unwrap();
+        let server = CatalogServer::bind(root.path(), &ServeOptions::default())
+            .unwrap()
+            .spawn();
+        let addr = server.addr();
+
+        let mut slow = TcpStream::connect(addr).unwrap();
+        slow.write_all(b"GET /big.exe HTTP/1.1\r\nHost: x\r\n\r\n")
+            .unwrap();
+        let mut head = [0u8; 12];
+        slow.read_exact(&mut head).unwrap();
+        assert_eq!(&head, b"HTTP/1.1 200");
+
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || sender.send(request(addr, "GET", HEALTH_PATH, &[]).0));
+        assert_eq!(receiver.recv_timeout(Duration::from_secs(10)), Ok(200));
+
+        drop(slow);
+        drop(server);
+    }
+}
@@ -0,0 +1,279 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use serde::Serialize;
+use walkdir::WalkDir;
+
+use crate::{
+    error::CatalogError,
+    manifest::{Manifest, SoftwareComponent},
+    verify::{check_package, PackageProblem},
+};
+
+/// 缺少的更新包清单, 写在仓库目录中
+pub const MISSING_FILE_NAME: &str = "missing_packages.json";
+
+/// 来源目录中的文件, 按小写文件名索引
+#[derive(Debug, Clone, Default)]
+pub struct SourceIndex {
+    roots: Vec<PathBuf>,
+    by_name: HashMap<String, Vec<PathBuf>>,
+}
+
+impl SourceIndex {
+    /// 遍历所有来源目录, 可以是散放的文件也可以是按原始 `path` 排列的镜像
+    pub fn build(roots: &[PathBuf]) -> Self {
+        let mut by_name: HashMap<String, Vec<PathBuf>> = HashMap::new();
+        for root in roots.iter() {
+            for entry in WalkDir::new(root)
+                .into_iter()
+                .filter_map(Result::ok)
+                .filter(|e| e.file_type().is_file())
+            {
+                let name = entry.file_name().to_string_lossy().to_lowercase();
+                by_name.entry(name).or_default().push(entry.into_path());
+            }
+        }
+        log::debug!("SourceIndex--{} files in {:?}", by_name.len(), roots);
+        SourceIndex {
+            roots: roots.to_vec(),
+            by_name,
+        }
+    }
+
+    /// 可能的来源文件: 先找镜像中原始 `path` 的位置, 再按文件名查找
+    fn candidates(&self, original_path: &str) -> Vec<PathBuf> {
+        let relative = relative_path(original_path);
+        let mut candidates: Vec<PathBuf> = self
+            .roots
+            .iter()
+            .map(|root| root.join(&relative))
+            .filter(|path| path.is_file())
+            .collect();
+        let name = file_name(original_path).to_lowercase();
+        for path in self.by_name.get(&name).into_iter().flatten() {
+            if !candidates.contains(path) {
+                candidates.push(path.clone());
+            }
+        }
+        candidates
+    }
+}
+
+fn file_name(path: &str) -> &str {
+    path.rsplit(['/', '\\']).next().unwrap_or(path)
+}
+
+/// catalog 中的 `path` 转换为相对路径, 忽略 `..` 和盘符等, 不会写到仓库目录之外
+fn relative_path(path: &str) -> PathBuf {
+    path.split(['/', '\\'])
+        .filter(|part| !part.is_empty() && *part != "." && *part != ".." && !part.contains(':'))
+        .collect()
+}
+
+/// 已经放到仓库中的更新包, `source` 为空表示仓库中原本就有且校验通过
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct StagedPackage {
+    pub package_id: String,
+    pub path: PathBuf,
+    pub source: Option<PathBuf>,
+}
+
+/// 找不到或校验不通过的更新包, 按这个清单手动下载
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct MissingPackage {
+    pub package_id: String,
+    pub name: String,
+    /// 改写后的 `path`, 相对于仓库目录
+    pub path: String,
+    pub size: Option<u64>,
+    /// 原始 `baseLocation` 和 `path` 组成的下载地址
+    pub url: String,
+    /// 找到了同名文件但是校验不通过
+    pub problem: Option<PackageProblem>,
+}
+
+/// 放置更新包的结果
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct StageReport {
+    pub staged: Vec<StagedPackage>,
+    pub missing: Vec<MissingPackage>,
+}
+
+fn download_url(base_location: &str, path: &str) -> String {
+    let path = path.replace('\\', "/");
+    let base = base_location.trim_end_matches('/');
+    if base.is_empty() {
+        path
+    } else if base.contains("://") {
+        format!("{}/{}", base, path)
+    } else {
+        format!("https://{}/{}", base, path)
+    }
+}
+
+/// 把 `rewritten` 引用的每个更新包按改写后的 `path` 复制到 `output_dir`, 来源按原始 catalog 的 `path` 查找
+pub fn stage_packages(
+    original: &Manifest,
+    rewritten: &Manifest,
+    index: &SourceIndex,
+    output_dir: &Path,
+) -> Result<StageReport, CatalogError> {
+    let original_paths: HashMap<&str, &SoftwareComponent> = original
+        .software_components
+        .iter()
+        .map(|c| (c.package_id.as_str(), c))
+        .collect();
+    let mut report = StageReport::default();
+    for component in rewritten.software_components.iter() {
+        let original_path = original_paths
+            .get(component.package_id.as_str())
+            .map(|c| c.path.as_str())
+            .unwrap_or(&component.path);
+        let target = output_dir.join(relative_path(&component.path));
+        let mut problem = None;
+        if target.is_file() {
+            match check_package(component, &target)? {
+                None => {
+                    report.staged.push(StagedPackage {
+                        package_id: component.package_id.clone(),
+                        path: target,
+                        source: None,
+                    });
+                    continue;
+                }
+                Some(p) => problem = Some(p),
+            }
+        }
+        let mut source = None;
+        for candidate in index.candidates(original_path) {
+            if candidate == target {
+                continue;
+            }
+            match check_package(component, &candidate)? {
+                None => {
+                    source = Some(candidate);
+                    break;
+                }
+                Some(p) => problem = problem.or(Some(p)),
+            }
+        }
+        let Some(source) = source else {
+            report.missing.push(MissingPackage {
+                package_id: component.package_id.clone(),
+                name: component.display_name().to_string(),
+                path: component.path.clone(),
+                size: component.size,
+                url: download_url(&original.base_location, original_path),
+                problem,
+            });
+            continue;
+        };
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::copy(&source, &target)?;
+        if let Some(problem) = check_package(component, &target)? {
+            return Err(CatalogError::PackageMismatch(format!(
+                "{:?} changed while copying from {:?}: {:?}",
+                target, source, problem
+            )));
+        }
+        report.staged.push(StagedPackage {
+            package_id: component.package_id.clone(),
+            path: target,
+            source: Some(source),
+        });
+    }
+    log::debug!(
+        "stage_packages--{} staged, {} missing",
+        report.staged.len(),
+        report.missing.len()
+    );
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::verify::tests::ABC_SHA256;
+
+    fn component(package_id: &str, path: &str) -> SoftwareComponent {
+        crate::verify::tests::component(package_id, path, b"abc", &[("SHA256", ABC_SHA256.into())])
+    }
+
+    #[test]
+    fn test_relative_path() {
+        assert_eq!(
+            relative_path("FOLDER1/1/a.exe"),
+            Path::new("FOLDER1").join("1").join("a.exe")
+        );
+        assert_eq!(relative_path("..\\..\\C:\\a.exe"), PathBuf::from("a.exe"));
+        assert_eq!(
+            download_url("downloads.dell.com", "FOLDER1/1/a.exe"),
+            "https://downloads.dell.com/FOLDER1/1/a.exe"
+        );
+    }
+
+    #[test]
+    fn test_stage_packages() {
+        let mirror = tempfile::tempdir().unwrap();
+        let loose = tempfile::tempdir().unwrap();
+        let output = tempfile::tempdir().unwrap();
+        fs::create_dir_all(mirror.path().join("FOLDER1/1")).unwrap();
+        fs::write(mirror.path().join("FOLDER1/1/a.exe"), b"abc").unwrap();
+        // 同名但内容错误的文件会被跳过
+        fs::write(loose.path().join("B.EXE"), b"abd").unwrap();
+        fs::create_dir_all(loose.path().join("sub")).unwrap();
+        fs::write(loose.path().join("sub/b.exe"), b"abc").unwrap();
+        fs::write(loose.path().join("c.exe"), b"xyz").unwrap();
+        fs::write(output.path().join("d.exe"), b"abc").unwrap();
+
+        let original = Manifest {
+            base_location: "downloads.dell.com".into(),
+            software_components: vec![
+                component("A", "FOLDER1/1/a.exe"),
+                component("B", "FOLDER2/1/b.exe"),
+                component("C", "FOLDER3/1/c.exe"),
+                component("D", "FOLDER4/1/d.exe"),
+                component("E", "FOLDER5/1/e.exe"),
+            ],
+            ..Default::default()
+        };
+        let mut rewritten = original.clone();
+        rewritten.base_location.clear();
+        for c in rewritten.software_components.iter_mut() {
+            c.path = file_name(&c.path).to_string();
+        }
+
+        let index = SourceIndex::build(&[mirror.path().to_path_buf(), loose.path().to_path_buf()]);
+        let report = stage_packages(&original, &rewritten, &index, output.path()).unwrap();
+        let staged: Vec<(&str, Option<&Path>)> = report
+            .staged
+            .iter()
+            .map(|p| (p.package_id.as_str(), p.source.as_deref()))
+            .collect();
+        assert_eq!(
+            staged,
+            [
+                ("A", Some(mirror.path().join("FOLDER1/1/a.exe").as_path())),
+                ("B", Some(loose.path().join("sub/b.exe").as_path())),
+                ("D", None),
+            ]
+        );
+        assert_eq!(fs::read(output.path().join("b.exe")).unwrap(), b"abc");
+        assert_eq!(report.missing.len(), 2);
+        assert!(matches!(
+            report.missing[0].problem,
+            Some(PackageProblem::Hash { .. })
+        ));
+        assert_eq!(
+            report.missing[1].url,
+            "https://downloads.dell.com/FOLDER5/1/e.exe"
+        );
+        assert_eq!(report.missing[1].problem, None);
+    }
+}
@@ -0,0 +1,313 @@
+use std::{
+    collections::HashSet,
+    fs::{self, File},
+    io::{self, BufReader},
+    path::{Path, PathBuf},
+};
+
+use data_encoding::HEXLOWER;
+use md5::{Digest, Md5};
+use serde::Serialize;
+use sha1::Sha1;
+
+use crate::{
+    error::CatalogError,
+    hash::{digest_reader_with, HashAlgorithm},
+    is_ic_path,
+    manifest::{Manifest, SoftwareComponent},
+};
+
+/// 视为更新包的文件扩展名, 用于查找多余的文件
+const PACKAGE_EXTENSIONS: [&str; 4] = ["exe", "msi", "zip", "bin"];
+
+/// `Cryptography` 中的摘要算法, 按强度从低到高排列
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+pub enum PackageHashAlgorithm {
+    Md5,
+    Sha1,
+    Sha256,
+}
+
+impl PackageHashAlgorithm {
+    fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().replace(['-', '_'], "").as_str() {
+            "md5" => Some(PackageHashAlgorithm::Md5),
+            "sha1" => Some(PackageHashAlgorithm::Sha1),
+            "sha256" => Some(PackageHashAlgorithm::Sha256),
+            _ => None,
+        }
+    }
+
+    /// 文件内容的小写 hex 摘要
+    pub fn hash_file(&self, path: &Path) -> Result<String, io::Error> {
+        let mut reader = BufReader::new(File::open(path)?);
+        let digest = match self {
+            PackageHashAlgorithm::Md5 => digest_reader_with(Md5::new(), &mut reader)?,
+            PackageHashAlgorithm::Sha1 => digest_reader_with(Sha1::new(), &mut reader)?,
+            PackageHashAlgorithm::Sha256 => HashAlgorithm::Sha256.digest_reader(&mut reader)?,
+        };
+        Ok(HEXLOWER.encode(&digest))
+    }
+}
+
+/// 更新包和 catalog 不一致的地方
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum PackageProblem {
+    Size {
+        expected: u64,
+        actual: u64,
+    },
+    Hash {
+        algorithm: PackageHashAlgorithm,
+        expected: String,
+        actual: String,
+    },
+    /// catalog 中没有可识别的哈希
+    NoHash,
+}
+
+/// 一个被 catalog 引用的更新包
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PackageRef {
+    pub package_id: String,
+    pub file_name: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PackageMismatch {
+    pub package: PackageRef,
+    pub problem: PackageProblem,
+}
+
+/// 检查结果, `verified` 中是大小和哈希都一致的文件名
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct PackageReport {
+    pub dir: PathBuf,
+    pub verified: Vec<String>,
+    pub missing: Vec<PackageRef>,
+    pub mismatched: Vec<PackageMismatch>,
+    /// 目录中没有被 catalog 引用的更新包
+    pub extra: Vec<String>,
+}
+
+impl PackageReport {
+    /// 没有缺少或不一致的文件, 多余的文件不影响结果
+    pub fn is_ok(&self) -> bool {
+        self.missing.is_empty() && self.mismatched.is_empty()
+    }
+
+    /// 一行的摘要, 用于错误信息
+    pub fn summary(&self) -> String {
+        let names = |refs: &mut dyn Iterator<Item = &PackageRef>| {
+            refs.map(|r| r.file_name.as_str())
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        format!(
+            "{} missing [{}], {} mismatched [{}], {} extra",
+            self.missing.len(),
+            names(&mut self.missing.iter()),
+            self.mismatched.len(),
+            names(&mut self.mismatched.iter().map(|m| &m.package)),
+            self.extra.len()
+        )
+    }
+}
+
+/// `path` 的最后一段, `handle_xml` 改写后 DU 在 catalog 所在目录中按这个名称查找
+fn package_file_name(component: &SoftwareComponent) -> &str {
+    component
+        .path
+        .rsplit(['/', '\\'])
+        .next()
+        .unwrap_or(&component.path)
+}
+
+pub(crate) fn check_package(
+    component: &SoftwareComponent,
+    path: &Path,
+) -> Result<Option<PackageProblem>, CatalogError> {
+    let actual_size = fs::metadata(path)?.len();
+    if let Some(expected) = component.size {
+        if expected != actual_size {
+            return Ok(Some(PackageProblem::Size {
+                expected,
+                actual: actual_size,
+            }));
+        }
+    }
+    let strongest = component
+        .cryptography
+        .iter()
+        .flat_map(|c| c.hashes.iter())
+        .filter_map(|hash| Some((PackageHashAlgorithm::from_name(&hash.algorithm)?, hash)))
+        .max_by_key(|(algorithm, _)| *algorithm);
+    let Some((algorithm, hash)) = strongest else {
+        return Ok(Some(PackageProblem::NoHash));
+    };
+    let actual = algorithm.hash_file(path)?;
+    if actual.eq_ignore_ascii_case(hash.value.trim()) {
+        Ok(None)
+    } else {
+        Ok(Some(PackageProblem::Hash {
+            algorithm,
+            expected: hash.value.trim().to_lowercase(),
+            actual,
+        }))
+    }
+}
+
+/// 检查 `dir` 中 catalog 引用的每个更新包是否存在, 大小和最强的哈希是否一致
+pub fn verify_packages(manifest: &Manifest, dir: &Path) -> Result<PackageReport, CatalogError> {
+    let mut report = PackageReport {
+        dir: dir.to_path_buf(),
+        ..Default::default()
+    };
+    let mut referenced = HashSet::new();
+    for component in manifest.software_components.iter() {
+        let file_name = package_file_name(component);
+        referenced.insert(file_name.to_lowercase());
+        let package = PackageRef {
+            package_id: component.package_id.clone(),
+            file_name: file_name.to_string(),
+        };
+        let path = dir.join(file_name);
+        if !path.is_file() {
+            report.missing.push(package);
+            continue;
+        }
+        match check_package(component, &path)? {
+            None => report.verified.push(package.file_name),
+            Some(problem) => report.mismatched.push(PackageMismatch { package, problem }),
+        }
+    }
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let is_package = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .is_some_and(|e| PACKAGE_EXTENSIONS.contains(&e.to_lowercase().as_str()));
+        let Some(file_name) = path.file_name().and_then(|n| n.to_str()) else {
+            continue;
+        };
+        if path.is_file()
+            && is_package
+            && !is_ic_path(&path)
+            && !referenced.contains(&file_name.to_lowercase())
+        {
+            report.extra.push(file_name.to_string());
+        }
+    }
+    report.extra.sort();
+    log::debug!("verify_packages--{}", report.summary());
+    Ok(report)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::manifest::{Cryptography, Hash};
+
+    /// `b"abc"` 的 SHA-256
+    pub(crate) const ABC_SHA256: &str =
+        "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad";
+
+    pub(crate) fn component(
+        package_id: &str,
+        path: &str,
+        content: &[u8],
+        hashes: &[(&str, String)],
+    ) -> SoftwareComponent {
+        SoftwareComponent {
+            package_id: package_id.into(),
+            path: path.into(),
+            size: Some(content.len() as u64),
+            cryptography: Some(Cryptography {
+                hashes: hashes
+                    .iter()
+                    .map(|(algorithm, value)| Hash {
+                        algorithm: algorithm.to_string(),
+                        value: value.clone(),
+                    })
+                    .collect(),
+            }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_hash_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("abc.exe");
+        fs::write(&path, b"abc").unwrap();
+        assert_eq!(
+            PackageHashAlgorithm::Md5.hash_file(&path).unwrap(),
+            "900150983cd24fb0d6963f7d28e17f72"
+        );
+        assert_eq!(
+            PackageHashAlgorithm::Sha1.hash_file(&path).unwrap(),
+            "a9993e364706816aba3e25717850c26c9cd0d89d"
+        );
+        assert_eq!(
+            PackageHashAlgorithm::Sha256.hash_file(&path).unwrap(),
+            ABC_SHA256
+        );
+    }
+
+    #[test]
+    fn test_verify_packages() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("good.exe"), b"abc").unwrap();
+        fs::write(dir.path().join("corrupt.EXE"), b"abd").unwrap();
+        fs::write(dir.path().join("short.exe"), b"ab").unwrap();
+        fs::write(dir.path().join("leftover.msi"), b"x").unwrap();
+        fs::write(dir.path().join("InvColPC.exe"), b"ic").unwrap();
+        fs::write(dir.path().join("catalog.xml"), b"<Manifest/>").unwrap();
+
+        let sha256 = ABC_SHA256.to_uppercase();
+        // MD5 是错的, 但只检查最强的 SHA256
+        let md5 = ("MD5", "0".repeat(32));
+        let manifest = Manifest {
+            software_components: vec![
+                component(
+                    "GOOD1",
+                    "FOLDER1/1/good.exe",
+                    b"abc",
+                    &[md5.clone(), ("SHA256", sha256.clone())],
+                ),
+                component(
+                    "BAD01",
+                    "corrupt.EXE",
+                    b"abc",
+                    &[("SHA256", sha256.clone())],
+                ),
+                component("SIZE1", "short.exe", b"abc", &[("SHA256", sha256.clone())]),
+                component("GONE1", "missing.exe", b"abc", &[md5]),
+            ],
+            ..Default::default()
+        };
+
+        let report = verify_packages(&manifest, dir.path()).unwrap();
+        assert_eq!(report.verified, ["good.exe"]);
+        assert_eq!(report.missing[0].package_id, "GONE1");
+        assert_eq!(report.mismatched.len(), 2);
+        assert!(matches!(
+            report.mismatched[0].problem,
+            PackageProblem::Hash {
+                algorithm: PackageHashAlgorithm::Sha256,
+                ..
+            }
+        ));
+        assert_eq!(
+            report.mismatched[1].problem,
+            PackageProblem::Size {
+                expected: 3,
+                actual: 2
+            }
+        );
+        assert_eq!(report.extra, ["leftover.msi"]);
+        assert!(!report.is_ok());
+        assert!(report.summary().contains("missing.exe"));
+    }
+}
@@ -1,14 +1,26 @@
-use std::path::PathBuf;
+use std::{fmt, path::PathBuf, sync::Arc};
 
 // #![windows_subsystem = "windows"]
-use catalog_lib::{get_cur_path, CatalogInfo};
+use catalog_lib::{
+    browse::{ComponentDetail, ComponentQuery, SortColumn},
+    dcu::ScanResult,
+    detect::ClientKind,
+    diff::diff_manifests,
+    discover::{CatalogCandidate, DiscoverOptions, Discovery, IcCandidate},
+    get_cur_path,
+    manifest::Manifest,
+    CatalogInfo,
+};
 use iced::{
     alignment::Horizontal,
     theme::Palette,
-    widget::{button, center, column, container, row, text, text_input},
+    widget::{
+        button, center, checkbox, column, container, pick_list, row, scrollable, text, text_input,
+        Column,
+    },
     Alignment::Center,
     Background, Border, Element,
-    Length::Fill,
+    Length::{Fill, FillPortion},
     Size, Task, Theme,
 };
 
@@ -23,7 +35,52 @@ struct State {
     size: Size,
     title: String,
     catalog_info: CatalogInfo,
+    /// 当前目录中找到的所有候选
+    discovery: Discovery,
     error: String,
+    /// 注册成功但需要用户注意的问题, 例如缺少更新包
+    warning: String,
+    /// 打开组件列表时不为空
+    browser: Option<Box<Browser>>,
+}
+
+/// 组件列表页面的状态
+#[derive(Debug, Clone)]
+struct Browser {
+    manifest: Arc<Manifest>,
+    query: ComponentQuery,
+    /// 选中组件在 `software_components` 中的位置
+    selected: Option<usize>,
+    /// 与旧 catalog 比较的结果, 显示时代替组件详情
+    diff: Option<String>,
+}
+
+/// DU 和 DCU 都安装时使用哪一个
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ClientChoice {
+    Auto,
+    Client(ClientKind),
+}
+
+const CLIENT_CHOICES: [ClientChoice; 3] = [
+    ClientChoice::Auto,
+    ClientChoice::Client(ClientKind::DellCommandUpdate),
+    ClientChoice::Client(ClientKind::DellUpdate),
+];
+
+impl From<Option<ClientKind>> for ClientChoice {
+    fn from(client: Option<ClientKind>) -> Self {
+        client.map_or(ClientChoice::Auto, ClientChoice::Client)
+    }
+}
+
+impl fmt::Display for ClientChoice {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClientChoice::Auto => write!(f, "自动选择"),
+            ClientChoice::Client(kind) => write!(f, "{}", kind),
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -31,8 +88,22 @@ enum Message {
     Loaded(State),
     GoToSelectCatalog,
     GoToSeleceIc,
+    CatalogPicked(CatalogCandidate),
+    IcPicked(IcCandidate),
     CatalogChanged,
+    ClientSelected(ClientChoice),
+    RequirePackagesToggled(bool),
+    BrowseComponents,
+    ComponentsLoaded(Result<Arc<Manifest>, String>),
+    FilterChanged(String),
+    SortSelected(SortColumn),
+    ToggleSortOrder,
+    ComponentSelected(usize),
+    CompareCatalog,
+    DiffLoaded(Result<String, String>),
+    CloseBrowser,
     StartUpdate,
+    Rollback,
     ButtonClicked(State),
     // CreateTask,
     // FilterChanged(Filter),
@@ -43,20 +114,29 @@ enum Message {
 
 impl State {
     async fn load() -> State {
-        let paths = catalog_lib::get_catalog_and_ic_paths(get_cur_path()).await;
-        // let paths: Result<(String, String), CatalogError> = Ok(("s".into(), "ss".into()));
+        let discovery =
+            catalog_lib::get_catalog_and_ic_paths(get_cur_path(), DiscoverOptions::default()).await;
         let size = catalog_lib::get_window_size();
-        match paths {
-            Ok(catalog_info) => State {
-                catalog_info,
+        match discovery {
+            Ok(discovery) => State {
+                catalog_info: discovery.catalog_info(),
+                error: if discovery.catalogs.is_empty() {
+                    "No .cab file found".into()
+                } else {
+                    Default::default()
+                },
+                discovery,
                 title: "Welcome to the Home Page".into(),
                 size,
-                ..Default::default()
+                warning: Default::default(),
+                browser: None,
             },
             Err(e) => {
                 println!("load Error: {}", e);
                 State {
                     error: e.to_string(),
+                    title: "Welcome to the Home Page".into(),
+                    size,
                     ..Default::default()
                 }
             }
@@ -66,11 +146,24 @@ impl State {
     async fn process(&self) -> State {
         let result = catalog_lib::handle(&self.catalog_info).await;
         match result {
-            Ok(_) => self.clone(),
+            Ok(report) => State {
+                title: report
+                    .scan
+                    .map(|scan| scan_summary(&scan))
+                    .unwrap_or_else(|| self.title.clone()),
+                error: Default::default(),
+                warning: report
+                    .packages
+                    .filter(|packages| !packages.is_ok())
+                    .map(|packages| format!("更新包不完整: {}", packages.summary()))
+                    .unwrap_or_default(),
+                ..self.clone()
+            },
             Err(e) => {
                 println!("process Error: {}", e);
                 State {
                     error: e.to_string(),
+                    warning: Default::default(),
                     ..self.clone()
                 }
             }
@@ -86,17 +179,13 @@ impl Catalog {
     fn update(&mut self, message: Message) -> Task<Message> {
         match self {
             Catalog::Loading => {
-                match message {
-                    Message::Loaded(state) => {
-                        *self = Catalog::Loaded(state);
-                    }
-                    _ => {}
-                };
+                if let Message::Loaded(state) = message {
+                    *self = Catalog::Loaded(state);
+                }
                 Task::none()
                 // text_input::focus("new-task")
             }
             Catalog::Loaded(state) => {
-                println!("Catalog-update-Catalog::Loaded(state)--{:?}", state);
                 let command = match message {
                     Message::GoToSelectCatalog => {
                         file_selection(&mut state.catalog_info.cab_path, &mut state.error)
@@ -104,6 +193,14 @@ impl Catalog {
                     Message::GoToSeleceIc => {
                         file_selection(&mut state.catalog_info.ic_path, &mut state.error)
                     }
+                    Message::CatalogPicked(candidate) => {
+                        state.catalog_info.cab_path = Some(candidate.path);
+                        Task::none()
+                    }
+                    Message::IcPicked(candidate) => {
+                        state.catalog_info.ic_path = Some(candidate.path);
+                        Task::none()
+                    }
                     Message::CatalogChanged => {
                         match catalog_lib::check_catalog_info(&state.catalog_info) {
                             Ok(_) => {
@@ -111,17 +208,133 @@ impl Catalog {
                                 Task::done(Message::StartUpdate)
                             }
                             Err(e) => {
-                                *(&mut state.error) = e.to_string();
+                                state.error = e.to_string();
                                 Task::none()
                             }
                         }
                     }
+                    Message::ClientSelected(choice) => {
+                        state.catalog_info.client = match choice {
+                            ClientChoice::Auto => None,
+                            ClientChoice::Client(kind) => Some(kind),
+                        };
+                        Task::none()
+                    }
+                    Message::RequirePackagesToggled(require) => {
+                        state.catalog_info.require_staged_packages = require;
+                        Task::none()
+                    }
                     Message::StartUpdate => {
                         let state = state.clone();
                         Task::perform(async move { state.process().await }, Message::ButtonClicked)
                     }
+                    Message::Rollback => {
+                        match catalog_lib::rollback_latest() {
+                            Ok(snapshot_path) => {
+                                state.error = Default::default();
+                                state.title = format!("已恢复注册表: {}", snapshot_path.display());
+                            }
+                            Err(e) => state.error = e.to_string(),
+                        }
+                        Task::none()
+                    }
                     // Message::GoToHomePage => todo!(),
-                    Message::ButtonClicked(new_state) => Task::none(),
+                    Message::BrowseComponents => match state.catalog_info.cab_path.clone() {
+                        Some(cab_path) => Task::perform(
+                            async move {
+                                catalog_lib::load_manifest(&cab_path)
+                                    .map(Arc::new)
+                                    .map_err(|e| e.to_string())
+                            },
+                            Message::ComponentsLoaded,
+                        ),
+                        None => {
+                            state.error = "请先选择 catalog".into();
+                            Task::none()
+                        }
+                    },
+                    Message::ComponentsLoaded(manifest) => {
+                        match manifest {
+                            Ok(manifest) => {
+                                state.error = Default::default();
+                                state.browser = Some(Box::new(Browser {
+                                    manifest,
+                                    query: Default::default(),
+                                    selected: None,
+                                    diff: None,
+                                }));
+                            }
+                            Err(e) => state.error = e,
+                        }
+                        Task::none()
+                    }
+                    Message::FilterChanged(filter) => {
+                        if let Some(browser) = &mut state.browser {
+                            browser.query.filter = filter;
+                        }
+                        Task::none()
+                    }
+                    Message::SortSelected(sort) => {
+                        if let Some(browser) = &mut state.browser {
+                            browser.query.sort = sort;
+                        }
+                        Task::none()
+                    }
+                    Message::ToggleSortOrder => {
+                        if let Some(browser) = &mut state.browser {
+                            browser.query.descending = !browser.query.descending;
+                        }
+                        Task::none()
+                    }
+                    Message::ComponentSelected(index) => {
+                        if let Some(browser) = &mut state.browser {
+                            browser.selected = Some(index);
+                            browser.diff = None;
+                        }
+                        Task::none()
+                    }
+                    Message::CompareCatalog => match &state.browser {
+                        Some(browser) => match catalog_lib::open_file_dialog() {
+                            Ok(old_path) => {
+                                let new = browser.manifest.clone();
+                                Task::perform(
+                                    async move {
+                                        catalog_lib::load_manifest(&old_path)
+                                            .map(|old| diff_manifests(&old, &new).to_text())
+                                            .map_err(|e| e.to_string())
+                                    },
+                                    Message::DiffLoaded,
+                                )
+                            }
+                            Err(e) => {
+                                state.error = e.to_string();
+                                Task::none()
+                            }
+                        },
+                        None => Task::none(),
+                    },
+                    Message::DiffLoaded(diff) => {
+                        if let Some(browser) = &mut state.browser {
+                            match diff {
+                                Ok(diff) => {
+                                    browser.diff = Some(diff);
+                                    browser.selected = None;
+                                }
+                                Err(e) => browser.diff = Some(e),
+                            }
+                        }
+                        Task::none()
+                    }
+                    Message::CloseBrowser => {
+                        state.browser = None;
+                        Task::none()
+                    }
+                    Message::ButtonClicked(new_state) => {
+                        state.title = new_state.title;
+                        state.error = new_state.error;
+                        state.warning = new_state.warning;
+                        Task::none()
+                    }
                     _ => {
                         println!("ss");
                         Task::none()
@@ -136,13 +349,20 @@ impl Catalog {
     fn view(&self) -> Element<'_, Message> {
         match self {
             Catalog::Loading => loading_message(),
+            Catalog::Loaded(State {
+                browser: Some(browser),
+                ..
+            }) => browser_view(browser),
             Catalog::Loaded(State {
                 title,
                 catalog_info,
+                discovery,
                 error,
+                warning,
                 size,
+                browser: None,
             }) => {
-                let border_sytle = |theme: &Theme, status: text_input::Status| {
+                let border_sytle = |theme: &Theme, _status: text_input::Status| {
                     let palette = theme.extended_palette();
                     text_input::Style {
                         background: Background::Color(palette.background.base.color),
@@ -160,7 +380,7 @@ impl Catalog {
 
                 container(
                     column![
-                        text("Welcome to the Home Page")
+                        text(title)
                             .width(Fill)
                             .height(size.height / 2.0)
                             .color([0.5, 0.5, 0.5])
@@ -168,7 +388,11 @@ impl Catalog {
                         row!(
                             text_input(
                                 "请选择你的catalog cab文件?",
-                                catalog_info.cab_path.to_str().unwrap_or("")
+                                catalog_info
+                                    .cab_path
+                                    .as_deref()
+                                    .and_then(|path| path.to_str())
+                                    .unwrap_or("")
                             )
                             // .on_input(Message1::InputCatalogPathChanged)
                             .style(border_sytle)
@@ -177,11 +401,23 @@ impl Catalog {
                                 .width(100)
                                 .on_press(Message::GoToSelectCatalog),
                         )
+                        .push_maybe((discovery.catalogs.len() > 1).then(|| {
+                            pick_list(
+                                discovery.catalogs.as_slice(),
+                                selected(&discovery.catalogs, &catalog_info.cab_path, |c| &c.path),
+                                Message::CatalogPicked,
+                            )
+                            .width(200)
+                        }))
                         .spacing(20),
                         row!(
                             text_input(
                                 "请选择你的ic文件?",
-                                catalog_info.ic_path.to_str().unwrap_or("")
+                                catalog_info
+                                    .ic_path
+                                    .as_deref()
+                                    .and_then(|path| path.to_str())
+                                    .unwrap_or("")
                             )
                             // .on_input(Message::InputIcPathChanged)
                             // .on_submit(Message::CreateTask)
@@ -191,9 +427,30 @@ impl Catalog {
                                 .width(100)
                                 .on_press(Message::GoToSeleceIc),
                         )
+                        .push_maybe((discovery.ics.len() > 1).then(|| {
+                            pick_list(
+                                discovery.ics.as_slice(),
+                                selected(&discovery.ics, &catalog_info.ic_path, |ic| &ic.path),
+                                Message::IcPicked,
+                            )
+                            .width(200)
+                        }))
                         .spacing(20),
-                        button(text("Start Update")).on_press(Message::CatalogChanged),
-                        text(error).color([1.0, 0.0, 0.0])
+                        row!(
+                            pick_list(
+                                CLIENT_CHOICES,
+                                Some(ClientChoice::from(catalog_info.client)),
+                                Message::ClientSelected,
+                            ),
+                            button(text("Start Update")).on_press(Message::CatalogChanged),
+                            button(text("Components")).on_press(Message::BrowseComponents),
+                            button(text("Rollback")).on_press(Message::Rollback),
+                        )
+                        .spacing(20),
+                        checkbox("缺少更新包时停止", catalog_info.require_staged_packages)
+                            .on_toggle(Message::RequirePackagesToggled),
+                        text(error).color([1.0, 0.0, 0.0]),
+                        text(warning).color([1.0, 0.8, 0.0])
                     ]
                     .align_x(Horizontal::Center)
                     .spacing(30)
@@ -235,10 +492,10 @@ impl Catalog {
     // }
 }
 
-fn file_selection(path: &mut PathBuf, error: &mut String) -> Task<Message> {
+fn file_selection(path: &mut Option<PathBuf>, error: &mut String) -> Task<Message> {
     match catalog_lib::open_file_dialog() {
         Ok(file_path) => {
-            *path = file_path;
+            *path = Some(file_path);
             Task::done(Message::CatalogChanged)
         }
         Err(e) => {
@@ -248,6 +505,149 @@ fn file_selection(path: &mut PathBuf, error: &mut String) -> Task<Message> {
     }
 }
 
+/// 组件列表, 选中一行时在右侧显示详细信息
+fn browser_view(browser: &Browser) -> Element<'_, Message> {
+    let rows = browser.query.rows(&browser.manifest);
+    let toolbar = row![
+        text_input("过滤名称, 分类, 类型, 版本...", &browser.query.filter)
+            .on_input(Message::FilterChanged),
+        pick_list(
+            SortColumn::ALL,
+            Some(browser.query.sort),
+            Message::SortSelected
+        ),
+        button(text(if browser.query.descending {
+            "降序"
+        } else {
+            "升序"
+        }))
+        .on_press(Message::ToggleSortOrder),
+        button(text("与旧 catalog 比较")).on_press(Message::CompareCatalog),
+        button(text("返回")).on_press(Message::CloseBrowser),
+    ]
+    .spacing(10);
+    let summary = text(format!(
+        "{} / {} components",
+        rows.len(),
+        browser.manifest.software_components.len()
+    ));
+    let header = component_columns(SortColumn::ALL.map(|column| column.to_string()));
+    let list = rows
+        .into_iter()
+        .fold(Column::new().spacing(2), |list, row| {
+            list.push(
+                button(component_columns([
+                    row.name,
+                    row.category,
+                    row.component_type,
+                    format!("{} / {}", row.dell_version, row.vendor_version),
+                    row.release_date,
+                    row.criticality,
+                    format_size(row.size),
+                ]))
+                .style(button::text)
+                .width(Fill)
+                .on_press(Message::ComponentSelected(row.index)),
+            )
+        });
+    let detail = match (&browser.diff, browser.selected) {
+        (Some(diff), _) => Some(text(diff).into()),
+        (None, Some(index)) => Some(detail_view(ComponentDetail::from(
+            &browser.manifest.software_components[index],
+        ))),
+        (None, None) => None,
+    }
+    .map(|detail: Element<'_, Message>| scrollable(detail).width(FillPortion(2)));
+    column![
+        toolbar,
+        summary,
+        header,
+        row![scrollable(list).width(FillPortion(3))]
+            .push_maybe(detail)
+            .spacing(20)
+            .height(Fill),
+    ]
+    .spacing(10)
+    .padding(20)
+    .into()
+}
+
+/// 与 `SortColumn::ALL` 顺序相同的一行
+fn component_columns<'a>(values: [String; 7]) -> Element<'a, Message> {
+    let [name, category, component_type, version, release_date, criticality, size] = values;
+    row![
+        text(name).width(FillPortion(4)),
+        text(category).width(FillPortion(2)),
+        text(component_type).width(FillPortion(2)),
+        text(version).width(FillPortion(2)),
+        text(release_date).width(FillPortion(2)),
+        text(criticality).width(FillPortion(2)),
+        text(size).width(FillPortion(1)),
+    ]
+    .spacing(10)
+    .into()
+}
+
+fn detail_view<'a>(detail: ComponentDetail) -> Element<'a, Message> {
+    let section = |title: &str, lines: Vec<String>| {
+        lines.into_iter().fold(
+            column![text(title.to_string()).color([0.5, 0.5, 0.5])].spacing(4),
+            |section, line| section.push(text(line)),
+        )
+    };
+    column![
+        text(detail.name).size(20),
+        text(detail.path).color([0.5, 0.5, 0.5]),
+        text(detail.description),
+        section("Supported devices", detail.devices),
+        section("Supported systems", detail.system_ids),
+        section("Supported OS", detail.os_codes),
+        section(
+            "Hashes",
+            detail
+                .hashes
+                .into_iter()
+                .map(|(algorithm, value)| format!("{}: {}", algorithm, value))
+                .collect(),
+        ),
+    ]
+    .spacing(15)
+    .into()
+}
+
+/// dcu-cli 扫描结果, 每个可用更新一行
+fn scan_summary(scan: &ScanResult) -> String {
+    scan.updates.iter().fold(
+        format!("dcu-cli 找到 {} 个可用更新", scan.updates.len()),
+        |summary, update| {
+            format!(
+                "{}\n{} {} ({})",
+                summary, update.name, update.version, update.update_type
+            )
+        },
+    )
+}
+
+fn format_size(size: Option<u64>) -> String {
+    match size {
+        Some(size) if size >= 1 << 20 => format!("{:.1} MB", size as f64 / (1 << 20) as f64),
+        Some(size) => format!("{:.1} KB", size as f64 / 1024.0),
+        None => String::new(),
+    }
+}
+
+/// 候选中与当前路径相同的一项
+fn selected<T: Clone>(
+    candidates: &[T],
+    current: &Option<PathBuf>,
+    path: impl Fn(&T) -> &PathBuf,
+) -> Option<T> {
+    candidates
+        .iter()
+        .find(|candidate| Some(path(candidate)) == current.as_ref())
+        .cloned()
+}
+
 fn loading_message<'a>() -> Element<'a, Message> {
     center(text("Loading...").width(Fill).align_x(Center).size(50)).into()
 }